@@ -0,0 +1,352 @@
+//! This module contains the account and database maintenance operations of
+//! the `api_admin` command-line tool. Changes to accounts are recorded in
+//! the audit log like the ones made through the API, with a request id
+//! starting with `cli-`
+
+use crate::audit::{self, AUDIT_EVENTS};
+use crate::db::err::DBError;
+use crate::db::migrations::{self, MigrationError, Migrator};
+use crate::db::{Database, DatabaseAccess};
+use common::audit::{AuditEvent, AuditEventKind};
+use common::query::{Model, Update};
+use common::security;
+use common::user::User;
+use mongodb::bson::{doc, Document};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use thiserror::Error;
+
+const USERS: &str = "users";
+
+#[derive(Error, Debug)]
+pub enum AdminError {
+    #[error("No user was found: {0}")]
+    NoUser(String),
+
+    #[error("An account with this username already exists: {0}")]
+    UsernameTaken(String),
+
+    #[error("Unknown role: {0}")]
+    UnknownRole(String),
+
+    #[error("An issue occurred with the db: {source}")]
+    DBError {
+        #[from]
+        source: DBError,
+    },
+
+    #[error("{source}")]
+    MigrationError {
+        #[from]
+        source: MigrationError,
+    },
+}
+
+impl From<mongodb::error::Error> for AdminError {
+    fn from(e: mongodb::error::Error) -> Self {
+        AdminError::from(DBError::from(e))
+    }
+}
+
+/// A role which can be granted to an account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Grants access to the admin endpoints
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = AdminError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "admin" => Ok(Role::Admin),
+            _ => Err(AdminError::UnknownRole(s.into())),
+        }
+    }
+}
+
+/// An account as shown by the tool, without its secrets
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserInfo {
+    pub id: Option<String>,
+    pub username: String,
+    pub email: String,
+    pub admin: bool,
+    /// Whether the account holds an auth token
+    pub logged_in: bool,
+    pub last_login: String,
+    pub last_login_ip: Option<String>,
+    pub login_count: i64,
+    pub created: String,
+    pub updated: String,
+}
+
+impl From<User> for UserInfo {
+    fn from(user: User) -> Self {
+        UserInfo {
+            id: user.id.map(|id| id.to_hex()),
+            username: user.username,
+            email: user.email,
+            admin: user.admin,
+            logged_in: user.auth_token.is_some(),
+            last_login: user.last_login.to_rfc3339(),
+            last_login_ip: user.last_login_ip,
+            login_count: user.login_count,
+            created: user.created.to_rfc3339(),
+            updated: user.updated.to_rfc3339(),
+        }
+    }
+}
+
+/// The indexes of a collection
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CollectionIndexes {
+    pub collection: String,
+    pub indexes: Vec<String>,
+}
+
+/// Document counts and sizes of the database
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub users: u64,
+    pub admins: u64,
+    /// Accounts holding an auth token
+    pub logged_in_users: u64,
+    pub audit_events: u64,
+    pub applied_migrations: usize,
+    pub pending_migrations: usize,
+    /// Size of the stored documents, in bytes
+    pub data_size: f64,
+    /// Size of the indexes, in bytes
+    pub index_size: f64,
+}
+
+/// Sizes reported by the `dbStats` command
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DbStats {
+    data_size: f64,
+    index_size: f64,
+}
+
+/// Returns an audit event of `kind` concerning `user`
+fn event(kind: AuditEventKind, user: &User, reason: &str) -> AuditEvent {
+    let request_id = format!("cli-{}", security::generate_auth_token(16));
+    AuditEvent {
+        user_id: user.id,
+        username: Some(user.username.clone()),
+        reason: Some(reason.into()),
+        ..AuditEvent::new(kind, &request_id)
+    }
+}
+
+/// Applies `update` to the account named `username`, returning it as updated
+async fn update_user(
+    db: &Database,
+    username: &str,
+    update: &Update<User>,
+) -> Result<User, AdminError> {
+    let query = User::fields().username.eq(username.to_string());
+
+    db.find_one_and_update::<User>(USERS, &query, update)
+        .await?
+        .ok_or_else(|| AdminError::NoUser(username.into()))
+}
+
+/// Creates an account
+///
+/// # Arguments
+///
+/// * `db` - The app database
+/// * `username` - Username of the account
+/// * `email` - Email address of the account
+/// * `password` - Cleartext password of the account
+/// * `admin` - Whether the account is an admin
+pub async fn create_user(
+    db: &Database,
+    username: &str,
+    email: &str,
+    password: &str,
+    admin: bool,
+) -> Result<UserInfo, AdminError> {
+    let user = User {
+        admin,
+        ..User::new(email, username, password)
+    };
+
+    let query = User::fields().username.eq(user.username.clone());
+    if db.find_one::<User>(USERS, &query).await?.is_some() {
+        return Err(AdminError::UsernameTaken(username.into()));
+    }
+
+    let user = match db.insert_one(USERS, &user).await {
+        Ok(user) => user,
+        Err(e) if e.is_duplicate_key() => return Err(AdminError::UsernameTaken(username.into())),
+        Err(e) => return Err(e.into()),
+    };
+
+    let reason = if admin {
+        "created_admin"
+    } else {
+        "created_user"
+    };
+    audit::record(db, event(AuditEventKind::AdminAction, &user, reason)).await;
+
+    Ok(user.into())
+}
+
+/// Sets the password of an account, ending every session of it
+///
+/// # Arguments
+///
+/// * `db` - The app database
+/// * `username` - Username of the account
+/// * `password` - The new cleartext password
+pub async fn reset_password(
+    db: &Database,
+    username: &str,
+    password: &str,
+) -> Result<UserInfo, AdminError> {
+    let salt = security::generate_salt(256);
+    let password_hash = security::hash(&salt, password);
+
+    let fields = User::fields();
+    let update = Update::new()
+        .set(fields.salt, salt)
+        .set(fields.password_hash, password_hash)
+        .unset(fields.auth_token)
+        .unset(fields.csrf_token);
+
+    let user = update_user(db, username, &update).await?;
+
+    audit::record(
+        db,
+        event(AuditEventKind::PasswordChanged, &user, "admin_reset"),
+    )
+    .await;
+    audit::record(
+        db,
+        event(AuditEventKind::SessionRevoked, &user, "admin_reset"),
+    )
+    .await;
+
+    Ok(user.into())
+}
+
+/// Ends every session of an account by revoking its auth token
+///
+/// # Arguments
+///
+/// * `db` - The app database
+/// * `username` - Username of the account
+pub async fn revoke_sessions(db: &Database, username: &str) -> Result<UserInfo, AdminError> {
+    let fields = User::fields();
+    let update = Update::new()
+        .unset(fields.auth_token)
+        .unset(fields.csrf_token);
+
+    let user = update_user(db, username, &update).await?;
+
+    audit::record(
+        db,
+        event(AuditEventKind::SessionRevoked, &user, "admin_revoked"),
+    )
+    .await;
+
+    Ok(user.into())
+}
+
+/// Grants a role to an account or revokes it
+///
+/// # Arguments
+///
+/// * `db` - The app database
+/// * `username` - Username of the account
+/// * `role` - The role to grant or revoke
+/// * `granted` - Whether the account holds the role afterwards
+pub async fn set_role(
+    db: &Database,
+    username: &str,
+    role: Role,
+    granted: bool,
+) -> Result<UserInfo, AdminError> {
+    let fields = User::fields();
+    let update = match role {
+        Role::Admin => Update::new().set(fields.admin, granted),
+    };
+
+    let user = update_user(db, username, &update).await?;
+
+    let reason = if granted {
+        "granted_admin"
+    } else {
+        "revoked_admin"
+    };
+    audit::record(db, event(AuditEventKind::AdminAction, &user, reason)).await;
+
+    Ok(user.into())
+}
+
+/// Creates the indexes the server relies on if they are missing, returning
+/// the indexes of every collection which has some
+///
+/// # Arguments
+///
+/// * `db` - The app database
+pub async fn ensure_indexes(db: &Database) -> Result<Vec<CollectionIndexes>, AdminError> {
+    migrations::ensure_indexes(db).await?;
+
+    let mut indexes = Vec::new();
+    for collection in [USERS, AUDIT_EVENTS] {
+        let mut names = db
+            .to_inner()
+            .collection::<Document>(collection)
+            .list_index_names()
+            .await?;
+        names.sort();
+        indexes.push(CollectionIndexes {
+            collection: collection.into(),
+            indexes: names,
+        });
+    }
+
+    Ok(indexes)
+}
+
+/// Returns document counts and sizes of the database
+///
+/// # Arguments
+///
+/// * `db` - The app database
+pub async fn stats(db: &Database) -> Result<Stats, AdminError> {
+    let users = db.to_inner().collection::<Document>(USERS);
+    let count = |filter: Document| {
+        let users = users.clone();
+        async move { users.count_documents(filter, None).await }
+    };
+
+    let migrations = Migrator::new(db).status().await?;
+    let applied_migrations = migrations.iter().filter(|m| m.applied.is_some()).count();
+
+    let sizes = db
+        .to_inner()
+        .run_command(doc! { "dbStats": 1 }, None)
+        .await?;
+    let sizes: DbStats = mongodb::bson::from_document(sizes).map_err(DBError::from)?;
+
+    Ok(Stats {
+        users: count(doc! {}).await?,
+        admins: count(doc! { "admin": true }).await?,
+        logged_in_users: count(doc! { "auth_token": { "$exists": true } }).await?,
+        audit_events: db
+            .to_inner()
+            .collection::<Document>(AUDIT_EVENTS)
+            .estimated_document_count(None)
+            .await?,
+        applied_migrations,
+        pending_migrations: migrations.len() - applied_migrations,
+        data_size: sizes.data_size,
+        index_size: sizes.index_size,
+    })
+}
@@ -0,0 +1,73 @@
+//! This module records security relevant events, such as logins and
+//! password changes, in the append-only `audit_events` collection. Events
+//! are only ever inserted; nothing in the server updates or deletes them
+
+use crate::db::{Database, DatabaseAccess};
+use crate::forwarded;
+use crate::request_id::RequestId;
+use common::audit::{AuditEvent, AuditEventKind};
+use log::error;
+use rocket::request::{FromRequest, Outcome, Request};
+use std::convert::Infallible;
+
+pub const AUDIT_EVENTS: &str = "audit_events";
+
+/// Where a request came from, copied onto every event it records. As a
+/// request guard this never fails
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditContext {
+    ip: Option<String>,
+    user_agent: Option<String>,
+    request_id: String,
+}
+
+impl AuditContext {
+    pub fn of(request: &Request<'_>) -> Self {
+        AuditContext {
+            ip: forwarded::client_ip(request).map(|ip| ip.to_string()),
+            user_agent: request.headers().get_one("User-Agent").map(String::from),
+            request_id: RequestId::of(request).into(),
+        }
+    }
+
+    /// Returns the address of the client which sent the request
+    pub fn ip(&self) -> Option<&str> {
+        self.ip.as_deref()
+    }
+
+    /// Returns the `User-Agent` header of the request
+    pub fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+
+    /// Returns an event of `kind` for this request
+    pub fn event(&self, kind: AuditEventKind) -> AuditEvent {
+        AuditEvent {
+            ip: self.ip.clone(),
+            user_agent: self.user_agent.clone(),
+            ..AuditEvent::new(kind, &self.request_id)
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuditContext {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(AuditContext::of(request))
+    }
+}
+
+/// Stores an event. Failing to audit must not fail the request being
+/// audited, so errors are logged rather than returned
+///
+/// # Arguments
+///
+/// * `db` - The database to store the event in
+/// * `event` - The event to store
+pub async fn record(db: &Database, event: AuditEvent) {
+    if let Err(e) = db.insert_one(AUDIT_EVENTS, &event).await {
+        error!(target: "Audit", "Could not record {:?} event: {}", event.kind, e);
+    }
+}
@@ -0,0 +1,40 @@
+use crate::auth::token_auth::TokenAuth;
+use crate::error::ApiError;
+use common::user::User;
+use log::info;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+
+use super::err::AuthError;
+
+/// Request guard for endpoints restricted to admins. Authenticates like
+/// `TokenAuth`, then fails with `403 Forbidden` unless the user is an admin
+pub struct AdminAuth(User);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminAuth {
+    type Error = AuthError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let user = match request.guard::<TokenAuth>().await {
+            Outcome::Success(auth) => auth.into_inner(),
+            Outcome::Error(f) => return Outcome::Error(f),
+            Outcome::Forward(f) => return Outcome::Forward(f),
+        };
+
+        if user.admin {
+            Outcome::Success(AdminAuth(user))
+        } else {
+            info!("AdminAuth failed for user: {}", user.username);
+            let e = AuthError::NotAdmin(user.username);
+            ApiError::from(&e).remember(request);
+            Outcome::Error((Status::Forbidden, e))
+        }
+    }
+}
+
+impl AdminAuth {
+    pub fn into_inner(self) -> User {
+        self.0
+    }
+}
@@ -0,0 +1,55 @@
+//! This module contains the protection against cross-site request forgery
+//! for requests authenticated by the `auth_token` cookie, which browsers
+//! attach to requests no matter which site made them
+//!
+//! Logging in issues a CSRF token together with the auth token. It is
+//! stored with the account and sent in the `csrf_token` cookie, which
+//! unlike the auth token can be read by the front-end's scripts. Requests
+//! authenticated by the cookie with any method but `GET`, `HEAD` and
+//! `OPTIONS` must echo the token in the `X-CSRF-Token` header. A forged
+//! request from another site can't read the cookie, so it can't send the
+//! header and is rejected with `403 Forbidden`.
+
+use crate::auth::err::AuthError;
+use common::security;
+use common::user::User;
+use rocket::http::Method;
+use rocket::Request;
+
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+const TOKEN_LENGTH: usize = 64;
+
+/// Returns a new random CSRF token
+pub fn generate_token() -> String {
+    security::generate_auth_token(TOKEN_LENGTH)
+}
+
+/// Returns true if requests with `method` may change state
+fn is_state_changing(method: Method) -> bool {
+    !matches!(method, Method::Get | Method::Head | Method::Options)
+}
+
+/// Checks that `request`, authenticated as `user` by the `auth_token`
+/// cookie, carries the user's CSRF token if it may change state
+///
+/// # Arguments
+///
+/// * `request` - The request being authenticated
+/// * `user` - The user the auth token belongs to
+pub(crate) fn verify(request: &Request<'_>, user: &User) -> Result<(), AuthError> {
+    if !is_state_changing(request.method()) {
+        return Ok(());
+    }
+
+    let sent = request
+        .headers()
+        .get_one(CSRF_HEADER)
+        .ok_or(AuthError::MissingCsrfToken)?;
+
+    match &user.csrf_token {
+        Some(expected) if security::constant_time_eq(sent, expected) => Ok(()),
+        _ => Err(AuthError::BadCsrfToken),
+    }
+}
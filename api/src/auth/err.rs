@@ -18,9 +18,18 @@ pub enum AuthError {
     #[error("An incorrect password was used for user: {0}")]
     WrongPassword(String),
 
+    #[error("No X-CSRF-Token header was provided in the request")]
+    MissingCsrfToken,
+
+    #[error("An invalid CSRF token was provided in the request")]
+    BadCsrfToken,
+
     #[error("Multiple Authorization headers were found in the request")]
     BadHeaderCount,
 
+    #[error("User is not an admin: {0}")]
+    NotAdmin(String),
+
     #[error("An issue occurred with the db: {source:?}")]
     DBError {
         #[from]
@@ -30,3 +39,23 @@ pub enum AuthError {
     #[error("...")]
     Unspecified,
 }
+
+impl AuthError {
+    /// Returns a short, stable name for the kind of error, used when
+    /// recording failed logins in the audit log
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AuthError::MissingAuth => "missing_auth",
+            AuthError::MissingToken => "missing_token",
+            AuthError::NoUser(_) => "no_user",
+            AuthError::BadToken => "bad_token",
+            AuthError::WrongPassword(_) => "wrong_password",
+            AuthError::MissingCsrfToken => "missing_csrf_token",
+            AuthError::BadCsrfToken => "bad_csrf_token",
+            AuthError::BadHeaderCount => "bad_header_count",
+            AuthError::NotAdmin(_) => "not_admin",
+            AuthError::DBError { .. } => "db_error",
+            AuthError::Unspecified => "unspecified",
+        }
+    }
+}
@@ -1,26 +1,41 @@
+use crate::audit::{self, AuditContext};
 use crate::db::{Database, DatabaseAccess};
+use crate::error::ApiError;
+use crate::{logging, metrics, security_headers, telemetry};
+use common::audit::{AuditEvent, AuditEventKind};
+use common::query::Model;
 use common::security::hash;
 use common::user::User;
-use log::info;
+use log::{error, info};
 use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome, Request};
 use rocket::State;
-use rocket_contrib::json;
 
 pub struct LoginAuth(User);
 
 use super::err::AuthError;
 
-impl<'a, 'r> FromRequest<'a, 'r> for LoginAuth {
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LoginAuth {
     type Error = AuthError;
 
     // Wrapper around from_request in order to get some kind of logging
-    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
-        match _from_request(request) {
-            o @ Outcome::Success(_) => o,
-            Outcome::Failure((s, e)) => {
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match _from_request(request).await {
+            Outcome::Success(auth) => {
+                metrics::record_auth("LoginAuth", true);
+                security_headers::mark_authenticated(request);
+                if let Some(id) = &auth.0.id {
+                    logging::set_user(&id.to_hex());
+                }
+                Outcome::Success(auth)
+            }
+            Outcome::Error((s, e)) => {
                 info!("LoginAuth failed with: {} - {}", s, e);
-                Outcome::Failure((s, e))
+                metrics::record_auth("LoginAuth", false);
+                audit_failure(request, &e).await;
+                ApiError::from(&e).remember(request);
+                Outcome::Error((s, e))
             }
             o @ Outcome::Forward(_) => o,
         }
@@ -33,12 +48,51 @@ impl LoginAuth {
     }
 }
 
-fn _from_request(request: &Request) -> Outcome<LoginAuth, AuthError> {
+/// Records a failed credential check in the audit log, whichever endpoint
+/// it guarded. Successful logins are audited by the login endpoint, since
+/// other endpoints only use this guard to confirm the password
+///
+/// # Arguments
+///
+/// * `request` - The request which failed to authenticate
+/// * `e` - Why it failed
+async fn audit_failure(request: &Request<'_>, e: &AuthError) {
+    let db = match request.guard::<&State<Database>>().await {
+        Outcome::Success(db) => db,
+        _ => return,
+    };
+
+    let username = match e {
+        AuthError::NoUser(u) | AuthError::WrongPassword(u) => Some(u.clone()),
+        _ => None,
+    };
+    // Tie attempts against an existing account to it, so its owner can see them
+    let user_id = match (e, &username) {
+        (AuthError::WrongPassword(_), Some(username)) => {
+            let query = User::fields().username.eq(username.as_str());
+            match db.find_one::<User>("users", &query).await {
+                Ok(user) => user.and_then(|u| u.id),
+                Err(_) => None,
+            }
+        }
+        _ => None,
+    };
+
+    let event = AuditEvent {
+        user_id,
+        username,
+        reason: Some(e.kind().into()),
+        ..AuditContext::of(request).event(AuditEventKind::LoginFailed)
+    };
+    audit::record(db, event).await;
+}
+
+async fn _from_request(request: &Request<'_>) -> Outcome<LoginAuth, AuthError> {
     let auth_header: Vec<_> = request.headers().get("Authorization").collect();
     match auth_header.len() {
-        0 => Outcome::Failure((Status::Unauthorized, AuthError::MissingAuth)),
-        1 => authorize(auth_header[0], request),
-        _ => Outcome::Failure((Status::BadRequest, AuthError::BadHeaderCount)),
+        0 => Outcome::Error((Status::Unauthorized, AuthError::MissingAuth)),
+        1 => authorize(auth_header[0], request).await,
+        _ => Outcome::Error((Status::BadRequest, AuthError::BadHeaderCount)),
     }
 }
 
@@ -50,49 +104,54 @@ fn _from_request(request: &Request) -> Outcome<LoginAuth, AuthError> {
 ///
 /// * `auth_header` - The value of the HTTP Authorization header in the form of `username:password`
 /// * `request` - The active request to authenticate for
-fn authorize(auth_header: &str, request: &Request) -> Outcome<LoginAuth, AuthError> {
+async fn authorize(auth_header: &str, request: &Request<'_>) -> Outcome<LoginAuth, AuthError> {
     // Parse username and password from auth header
     let creds: Vec<&str> = auth_header.split(':').collect();
 
-    let username = match creds.get(0) {
+    let username = match creds.first() {
         Some(u) => *u,
-        None => return Outcome::Failure((Status::Unauthorized, AuthError::MissingAuth)),
+        None => return Outcome::Error((Status::Unauthorized, AuthError::MissingAuth)),
     };
     let password = match creds.get(1) {
         Some(p) => *p,
-        None => return Outcome::Failure((Status::Unauthorized, AuthError::MissingAuth)),
+        None => return Outcome::Error((Status::Unauthorized, AuthError::MissingAuth)),
     };
 
     // Get db
-    let db = request
-        .guard::<State<Database>>()
-        .expect("No managed db connection");
+    let db = match request.guard::<&State<Database>>().await {
+        Outcome::Success(db) => db,
+        _ => {
+            error!("No managed db connection");
+            return Outcome::Error((Status::InternalServerError, AuthError::Unspecified));
+        }
+    };
     // Get user
 
-    let query = json! {{
-        "username": username
-    }};
+    let query = User::fields().username.eq(username);
 
-    let user = db.find_one::<User>("users", &query);
+    let user = db.find_one::<User>("users", &query).await;
 
     let user = match user {
         Ok(Some(u)) => u,
         Ok(None) => {
-            return Outcome::Failure((Status::Unauthorized, AuthError::NoUser(username.into())))
+            return Outcome::Error((Status::Unauthorized, AuthError::NoUser(username.into())))
         }
         Err(e) => {
-            return Outcome::Failure((Status::ServiceUnavailable, AuthError::DBError { source: e }))
+            return Outcome::Error((Status::ServiceUnavailable, AuthError::DBError { source: e }))
         }
     };
 
     // Hash password
-    let encoded_hash = hash(&user.salt, &password);
+    let encoded_hash = telemetry::in_span("password_hash".into(), Vec::new(), async {
+        hash(&user.salt, password)
+    })
+    .await;
 
     // Compare
     if encoded_hash == user.password_hash {
         Outcome::Success(LoginAuth(user))
     } else {
-        Outcome::Failure((
+        Outcome::Error((
             Status::Unauthorized,
             AuthError::WrongPassword(username.into()),
         ))
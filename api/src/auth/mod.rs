@@ -1,3 +1,6 @@
+pub mod admin_auth;
+pub mod csrf;
 pub mod err;
 pub mod login_auth;
+pub mod session;
 pub mod token_auth;
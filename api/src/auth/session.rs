@@ -0,0 +1,102 @@
+//! This module contains the cookies carrying a login session: the private
+//! `auth_token` cookie, and the `csrf_token` cookie which the front-end
+//! reads to fill the `X-CSRF-Token` header (see `csrf`)
+//!
+//! Their `SameSite` attribute is read from the Rocket config (`Rocket.toml`
+//! or `ROCKET_SESSION_COOKIE_SAME_SITE`):
+//!
+//! ```toml
+//! session_cookie_same_site = "lax"
+//! ```
+//!
+//! It is one of `"strict"` (the default), `"lax"` or `"none"`. Front-ends
+//! served from another site need `"none"` for browsers to send the cookies
+//! at all, which leaves the CSRF token as the only protection.
+
+use crate::auth::csrf;
+use log::error;
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
+use rocket::http::{Cookie, CookieJar, SameSite};
+
+pub const AUTH_COOKIE: &str = "auth_token";
+
+/// Settings of the session cookies
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionOptions {
+    /// `SameSite` attribute of the session cookies
+    pub same_site: SameSite,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        SessionOptions {
+            same_site: SameSite::Strict,
+        }
+    }
+}
+
+impl SessionOptions {
+    /// Reads the options from the Rocket config, falling back to the
+    /// defaults for anything missing
+    pub fn from_figment(figment: &Figment) -> Result<Self, String> {
+        let same_site = match figment.extract_inner::<String>("session_cookie_same_site") {
+            Ok(value) => match value.to_ascii_lowercase().as_str() {
+                "strict" => SameSite::Strict,
+                "lax" => SameSite::Lax,
+                "none" => SameSite::None,
+                _ => return Err(format!("Unknown session_cookie_same_site {:?}", value)),
+            },
+            Err(_) => SessionOptions::default().same_site,
+        };
+
+        Ok(SessionOptions { same_site })
+    }
+
+    /// Sets the cookies of a new session
+    ///
+    /// # Arguments
+    ///
+    /// * `cookies` - The cookies of the response
+    /// * `auth_token` - The auth token issued to the user
+    /// * `csrf_token` - The CSRF token issued with it
+    pub fn start(&self, cookies: &CookieJar<'_>, auth_token: String, csrf_token: String) {
+        // Cookies with `SameSite=None` are dropped by browsers unless they
+        // are secure, which every session cookie is
+        let auth_cookie = Cookie::build((AUTH_COOKIE, auth_token))
+            .path("/")
+            .secure(true)
+            .http_only(true)
+            .same_site(self.same_site);
+        cookies.add_private(auth_cookie);
+
+        // Readable by scripts, which is safe because other sites can't read
+        // it and it grants nothing without the auth token
+        let csrf_cookie = Cookie::build((csrf::CSRF_COOKIE, csrf_token))
+            .path("/")
+            .secure(true)
+            .http_only(false)
+            .same_site(self.same_site);
+        cookies.add(csrf_cookie);
+    }
+}
+
+/// Removes the cookies of the session, if any
+pub fn end(cookies: &CookieJar<'_>) {
+    cookies.remove_private(AUTH_COOKIE);
+    cookies.remove(csrf::CSRF_COOKIE);
+}
+
+/// Returns a fairing which reads the session cookie settings on ignite.
+/// Launch is aborted if they are invalid
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Session cookies", |rocket| async {
+        match SessionOptions::from_figment(rocket.figment()) {
+            Ok(options) => Ok(rocket.manage(options)),
+            Err(e) => {
+                error!(target: "Session cookies", "Invalid session cookie config: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
@@ -1,25 +1,38 @@
 use crate::db::{Database, DatabaseAccess};
+use crate::error::ApiError;
+use crate::{logging, metrics, security_headers};
+use common::query::Model;
 use common::user::User;
 use log::{error, info};
-use rocket::http::{Cookies, Status};
+use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome, Request};
 use rocket::State;
-use rocket_contrib::json;
 
 use super::err::AuthError;
+use super::{csrf, session};
 
 pub struct TokenAuth(User);
 
-impl<'a, 'r> FromRequest<'a, 'r> for TokenAuth {
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TokenAuth {
     type Error = AuthError;
 
     // Wrapper around from_request in order to get some kind of logging
-    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
-        match _from_request(request) {
-            o @ Outcome::Success(_) => o,
-            Outcome::Failure((s, e)) => {
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match _from_request(request).await {
+            Outcome::Success(auth) => {
+                metrics::record_auth("TokenAuth", true);
+                security_headers::mark_authenticated(request);
+                if let Some(id) = &auth.0.id {
+                    logging::set_user(&id.to_hex());
+                }
+                Outcome::Success(auth)
+            }
+            Outcome::Error((s, e)) => {
                 info!("TokenAuth failed with: {} - {}", s, e);
-                Outcome::Failure((s, e))
+                metrics::record_auth("TokenAuth", false);
+                ApiError::from(&e).remember(request);
+                Outcome::Error((s, e))
             }
             o @ Outcome::Forward(_) => o,
         }
@@ -32,20 +45,22 @@ impl TokenAuth {
     }
 }
 
-fn _from_request(request: &Request) -> Outcome<TokenAuth, AuthError> {
-    let mut cookies = match request.guard::<Cookies>() {
-        Outcome::Success(c) => c,
-        _ => {
-            error!("Failed to collect cookies (no Cookies Guard found)");
-            return Outcome::Failure((Status::InternalServerError, AuthError::Unspecified));
-        }
-    };
-    let token_cookie = match cookies.get_private("auth_token") {
+async fn _from_request(request: &Request<'_>) -> Outcome<TokenAuth, AuthError> {
+    let token_cookie = match request.cookies().get_private(session::AUTH_COOKIE) {
         Some(c) => c,
-        None => return Outcome::Failure((Status::Unauthorized, AuthError::MissingToken)),
+        None => return Outcome::Error((Status::Unauthorized, AuthError::MissingToken)),
+    };
+
+    let auth = match authorize(token_cookie.value(), request).await {
+        Outcome::Success(auth) => auth,
+        o => return o,
     };
 
-    authorize(token_cookie.value(), request)
+    // Browsers attach the cookie to requests made by any site
+    match csrf::verify(request, &auth.0) {
+        Ok(()) => Outcome::Success(auth),
+        Err(e) => Outcome::Error((Status::Forbidden, e)),
+    }
 }
 
 /// Given a user token, look up the user and authenticate
@@ -56,23 +71,25 @@ fn _from_request(request: &Request) -> Outcome<TokenAuth, AuthError> {
 ///
 /// * `token` - The token to look up a user with
 /// * `request` - The active request to authenticate for
-fn authorize(token: &str, request: &Request) -> Outcome<TokenAuth, AuthError> {
+async fn authorize(token: &str, request: &Request<'_>) -> Outcome<TokenAuth, AuthError> {
     // Get db
-    let db = request
-        .guard::<State<Database>>()
-        .expect("No managed db connection");
+    let db = match request.guard::<&State<Database>>().await {
+        Outcome::Success(db) => db,
+        _ => {
+            error!("No managed db connection");
+            return Outcome::Error((Status::InternalServerError, AuthError::Unspecified));
+        }
+    };
 
-    let query = json! {{
-        "auth_token": token
-    }};
+    let query = User::fields().auth_token.eq(token.to_string());
 
-    let user = db.find_one::<User>("users", &query);
+    let user = db.find_one::<User>("users", &query).await;
 
     let user = match user {
         Ok(Some(u)) => u,
-        Ok(None) => return Outcome::Failure((Status::Unauthorized, AuthError::BadToken)),
+        Ok(None) => return Outcome::Error((Status::Unauthorized, AuthError::BadToken)),
         Err(e) => {
-            return Outcome::Failure((Status::ServiceUnavailable, AuthError::DBError { source: e }))
+            return Outcome::Error((Status::ServiceUnavailable, AuthError::DBError { source: e }))
         }
     };
 
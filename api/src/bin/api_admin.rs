@@ -0,0 +1,145 @@
+use api::admin::{self, Role};
+use api::db::migrations::Migrator;
+use api::db::{Database, ResilienceOptions};
+use rocket::serde::json::{serde_json, Value};
+use serde::Serialize;
+use std::io::{self, BufRead};
+use std::process;
+
+const USAGE: &str = "Usage: api_admin [--json] <command>
+
+Commands:
+  user create <username> <email> [--admin]
+  user reset-password <username>
+  user revoke-sessions <username>
+  role grant <username> <role>
+  role revoke <username> <role>
+  migrate <up|status>
+  indexes
+  stats
+
+Passwords are read from the first line of stdin. With --json the result of
+every command is printed as JSON.";
+
+#[rocket::main]
+async fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let json = match args.iter().position(|arg| arg == "--json") {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    };
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let figment = rocket::Config::figment();
+    let db = api::connect_database(ResilienceOptions::from_figment(&figment)).await;
+
+    let result = run(&db, &args).await;
+    match result {
+        Ok(value) if json => println!("{}", value),
+        Ok(value) => print_text(&value, 0),
+        Err(Failure::Usage) => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+        Err(Failure::Error(e)) if json => {
+            eprintln!("{}", serde_json::json!({ "error": e }));
+            process::exit(1);
+        }
+        Err(Failure::Error(e)) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+enum Failure {
+    Usage,
+    Error(String),
+}
+
+impl<E: std::fmt::Display> From<E> for Failure {
+    fn from(e: E) -> Self {
+        Failure::Error(e.to_string())
+    }
+}
+
+/// Runs the command given by `args`, returning its result
+async fn run(db: &Database, args: &[&str]) -> Result<Value, Failure> {
+    match args {
+        ["user", "create", username, email, flags @ ..] => {
+            let admin = match flags {
+                [] => false,
+                ["--admin"] => true,
+                _ => return Err(Failure::Usage),
+            };
+            let password = read_password()?;
+            to_value(admin::create_user(db, username, email, &password, admin).await?)
+        }
+        ["user", "reset-password", username] => {
+            let password = read_password()?;
+            to_value(admin::reset_password(db, username, &password).await?)
+        }
+        ["user", "revoke-sessions", username] => {
+            to_value(admin::revoke_sessions(db, username).await?)
+        }
+        ["role", action @ ("grant" | "revoke"), username, role] => {
+            let role: Role = role.parse()?;
+            to_value(admin::set_role(db, username, role, *action == "grant").await?)
+        }
+        ["migrate", "up"] => to_value(Migrator::new(db).up().await?),
+        ["migrate", "status"] => to_value(Migrator::new(db).status().await?),
+        ["indexes"] => to_value(admin::ensure_indexes(db).await?),
+        ["stats"] => to_value(admin::stats(db).await?),
+        _ => Err(Failure::Usage),
+    }
+}
+
+fn to_value(value: impl Serialize) -> Result<Value, Failure> {
+    Ok(serde_json::to_value(value)?)
+}
+
+/// Reads a password from the first line of stdin, so it doesn't end up in
+/// the shell history or the process list
+fn read_password() -> Result<String, Failure> {
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+
+    let password = line.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err(Failure::Error("No password was given on stdin".into()));
+    }
+    Ok(password.into())
+}
+
+/// Prints `value` as indented `key: value` lines
+fn print_text(value: &Value, indent: usize) {
+    let pad = "  ".repeat(indent);
+    match value {
+        Value::Object(fields) => fields.iter().for_each(|(key, value)| match value {
+            Value::Object(_) | Value::Array(_) => {
+                println!("{}{}:", pad, key);
+                print_text(value, indent + 1);
+            }
+            value => println!("{}{}: {}", pad, key, scalar(value)),
+        }),
+        Value::Array(items) => items.iter().for_each(|item| match item {
+            Value::Object(_) | Value::Array(_) => {
+                println!("{}-", pad);
+                print_text(item, indent + 1);
+            }
+            item => println!("{}- {}", pad, scalar(item)),
+        }),
+        value => println!("{}{}", pad, scalar(value)),
+    }
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => "-".into(),
+        value => value.to_string(),
+    }
+}
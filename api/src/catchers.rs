@@ -1,33 +1,70 @@
 //! This module specifies catchers for returning status code responses
-//! as JSON instead of HTML
+//! as JSON instead of HTML. Every status the server emits has a catcher,
+//! and each responds with an `ApiError`. Any other status, such as one
+//! returned by Rocket itself, is handled by the `default` catcher
 
+use crate::error::ApiError;
 use rocket::catch;
-use rocket_contrib::json;
-use rocket_contrib::json::JsonValue;
+use rocket::http::Status;
+use rocket::request::Request;
+
+#[catch(400)]
+pub fn bad_request(req: &Request) -> ApiError {
+    ApiError::for_catcher(req, Status::BadRequest)
+}
+
+#[catch(401)]
+pub fn unauthorized(req: &Request) -> ApiError {
+    ApiError::for_catcher(req, Status::Unauthorized)
+}
+
+#[catch(403)]
+pub fn forbidden(req: &Request) -> ApiError {
+    ApiError::for_catcher(req, Status::Forbidden)
+}
 
 #[catch(404)]
-pub fn not_found() -> JsonValue {
-    json!({
-        "status": "error",
-        "status_code": 404,
-        "message": "Resource was not found"
-    })
+pub fn not_found(req: &Request) -> ApiError {
+    ApiError::for_catcher(req, Status::NotFound)
+}
+
+#[catch(409)]
+pub fn conflict(req: &Request) -> ApiError {
+    ApiError::for_catcher(req, Status::Conflict)
+}
+
+#[catch(412)]
+pub fn precondition_failed(req: &Request) -> ApiError {
+    ApiError::for_catcher(req, Status::PreconditionFailed)
+}
+
+#[catch(413)]
+pub fn payload_too_large(req: &Request) -> ApiError {
+    ApiError::for_catcher(req, Status::PayloadTooLarge)
+}
+
+#[catch(415)]
+pub fn unsupported_media_type(req: &Request) -> ApiError {
+    ApiError::for_catcher(req, Status::UnsupportedMediaType)
+}
+
+#[catch(422)]
+pub fn unprocessable_entity(req: &Request) -> ApiError {
+    ApiError::for_catcher(req, Status::UnprocessableEntity)
 }
 
 #[catch(500)]
-pub fn internal_server_error() -> JsonValue {
-    json!({
-        "status": "error",
-        "status_code": 500,
-        "message": "The server encountered an internal error while processing this request"
-    })
+pub fn internal_server_error(req: &Request) -> ApiError {
+    ApiError::for_catcher(req, Status::InternalServerError)
 }
 
-#[catch(401)]
-pub fn unauthorized() -> JsonValue {
-    json!({
-        "status": "error",
-        "status_code": 401,
-        "message": "The request requires user authentication"
-    })
+/// Also sets `Retry-After` while the database circuit breaker is open
+#[catch(503)]
+pub fn service_unavailable(req: &Request) -> ApiError {
+    ApiError::for_catcher(req, Status::ServiceUnavailable)
+}
+
+#[catch(default)]
+pub fn default(status: Status, req: &Request) -> ApiError {
+    ApiError::for_catcher(req, status)
 }
@@ -0,0 +1,351 @@
+//! This module contains Cross-Origin Resource Sharing support, so browser
+//! front-ends served from another origin can call the API with the
+//! `auth_token` cookie
+//!
+//! Preflight `OPTIONS` requests are answered for every mounted route, and
+//! responses to allowed origins carry the `Access-Control-Allow-*` headers.
+//! The settings are read from the Rocket config (`Rocket.toml` or
+//! `ROCKET_*` environment variables):
+//!
+//! ```toml
+//! cors_allowed_origins = ["https://app.example.com"]
+//! cors_allowed_methods = ["GET", "POST", "PATCH", "DELETE"]
+//! cors_allowed_headers = ["Content-Type", "Authorization", "If-Match"]
+//! cors_exposed_headers = ["ETag", "X-Request-Id"]
+//! cors_allow_credentials = true
+//! cors_max_age = 3600
+//! ```
+//!
+//! No origin is allowed by default. Allowing every origin with `"*"` can't
+//! be combined with credentials, since any site could then act as the user.
+
+use crate::error::ApiError;
+use crate::scope;
+use log::error;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::figment::Figment;
+use rocket::http::{Method, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder};
+use rocket::{options, routes, Build, Orbit, Request, Response, Rocket};
+use std::str::FromStr;
+
+const ANY_ORIGIN: &str = "*";
+
+/// Settings deciding which cross-origin requests are allowed
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorsOptions {
+    /// Origins allowed to make requests, such as `https://app.example.com`,
+    /// or `*` for any origin
+    pub allowed_origins: Vec<String>,
+    /// Methods cross-origin requests may use
+    pub allowed_methods: Vec<Method>,
+    /// Request headers cross-origin requests may send
+    pub allowed_headers: Vec<String>,
+    /// Response headers the browser lets scripts read
+    pub exposed_headers: Vec<String>,
+    /// Whether requests may carry cookies
+    pub allow_credentials: bool,
+    /// How long browsers may cache a preflight response, in seconds
+    pub max_age: Option<u64>,
+}
+
+impl Default for CorsOptions {
+    fn default() -> Self {
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+
+        CorsOptions {
+            allowed_origins: Vec::new(),
+            allowed_methods: vec![
+                Method::Get,
+                Method::Post,
+                Method::Put,
+                Method::Patch,
+                Method::Delete,
+            ],
+            allowed_headers: strings(&[
+                "Accept",
+                "Authorization",
+                "Content-Type",
+                "If-Match",
+                "X-CSRF-Token",
+                "X-Request-Id",
+            ]),
+            exposed_headers: strings(&[
+                "ETag",
+                "X-Request-Id",
+                "Retry-After",
+                "Deprecation",
+                "Sunset",
+                "Link",
+            ]),
+            allow_credentials: true,
+            max_age: Some(3600),
+        }
+    }
+}
+
+impl CorsOptions {
+    /// Reads the options from the Rocket config, falling back to the
+    /// defaults for anything missing
+    pub fn from_figment(figment: &Figment) -> Result<Self, String> {
+        let defaults = CorsOptions::default();
+        let list = |name: &str, default: Vec<String>| {
+            figment
+                .extract_inner::<Vec<String>>(name)
+                .unwrap_or(default)
+        };
+
+        let allowed_methods = match figment.extract_inner::<Vec<String>>("cors_allowed_methods") {
+            Ok(methods) => methods
+                .iter()
+                .map(|m| Method::from_str(m).map_err(|_| format!("Unknown method {:?}", m)))
+                .collect::<Result<_, _>>()?,
+            Err(_) => defaults.allowed_methods,
+        };
+
+        let options = CorsOptions {
+            allowed_origins: list("cors_allowed_origins", defaults.allowed_origins)
+                .into_iter()
+                .map(|origin| origin.trim_end_matches('/').to_string())
+                .collect(),
+            allowed_methods,
+            allowed_headers: list("cors_allowed_headers", defaults.allowed_headers),
+            exposed_headers: list("cors_exposed_headers", defaults.exposed_headers),
+            allow_credentials: figment
+                .extract_inner("cors_allow_credentials")
+                .unwrap_or(defaults.allow_credentials),
+            max_age: match figment.extract_inner::<i64>("cors_max_age") {
+                Ok(seconds) if seconds < 0 => None,
+                Ok(seconds) => Some(seconds as u64),
+                Err(_) => defaults.max_age,
+            },
+        };
+
+        if options.allow_credentials && options.allowed_origins.iter().any(|o| o == ANY_ORIGIN) {
+            return Err(
+                "cors_allowed_origins can't contain \"*\" while credentials are allowed".into(),
+            );
+        }
+
+        Ok(options)
+    }
+
+    /// Returns true if requests from `origin` are allowed
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed == ANY_ORIGIN || allowed.eq_ignore_ascii_case(origin))
+    }
+
+    /// Returns true if every header in the comma separated `headers` is allowed
+    fn allows_headers(&self, headers: &str) -> bool {
+        headers
+            .split(',')
+            .map(str::trim)
+            .filter(|header| !header.is_empty())
+            .all(|header| {
+                self.allowed_headers
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(header))
+            })
+    }
+
+    /// Adds the headers every response to an allowed origin carries
+    fn add_origin_headers(&self, origin: &str, response: &mut Response) {
+        // The origin is echoed rather than `*`, which browsers reject for
+        // credentialed requests, so caches must key on it
+        response.set_raw_header("Access-Control-Allow-Origin", origin.to_string());
+        response.adjoin_raw_header("Vary", "Origin");
+
+        if self.allow_credentials {
+            response.set_raw_header("Access-Control-Allow-Credentials", "true");
+        }
+    }
+}
+
+/// Fairing which reads the CORS settings on ignite, mounts the preflight
+/// route and adds the CORS headers to responses to allowed origins.
+/// Launch is aborted if the settings are invalid
+pub struct Cors;
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS",
+            kind: Kind::Ignite | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        match CorsOptions::from_figment(rocket.figment()) {
+            Ok(options) => Ok(rocket
+                .manage(options)
+                .mount("/", scope::scoped(routes![preflight_endpoint]))),
+            Err(e) => {
+                error!(target: "CORS", "Invalid CORS config: {}", e);
+                Err(rocket)
+            }
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        // Preflight responses carry their own headers
+        if request.method() == Method::Options {
+            return;
+        }
+
+        let options = request.rocket().state::<CorsOptions>();
+        if let (Some(options), Some(origin)) = (options, request.headers().get_one("Origin")) {
+            if options.allows_origin(origin) {
+                options.add_origin_headers(origin, response);
+                if !options.exposed_headers.is_empty() {
+                    response.set_raw_header(
+                        "Access-Control-Expose-Headers",
+                        options.exposed_headers.join(", "),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// An allowed preflight request. As a request guard this fails with 403 if
+/// the origin, method or headers are not allowed, and with 404 if no route
+/// serves the requested method at the path
+pub struct Preflight {
+    origin: String,
+    options: CorsOptions,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Preflight {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let result = check_preflight(request);
+        match result {
+            Ok(preflight) => Outcome::Success(preflight),
+            Err(e) => {
+                e.clone().remember(request);
+                Outcome::Error((e.status(), e))
+            }
+        }
+    }
+}
+
+fn check_preflight(request: &Request) -> Result<Preflight, ApiError> {
+    let headers = request.headers();
+    let (origin, method) = match (
+        headers.get_one("Origin"),
+        headers.get_one("Access-Control-Request-Method"),
+    ) {
+        (Some(origin), Some(method)) => (origin, method),
+        _ => {
+            return Err(ApiError::bad_request(
+                "OPTIONS requests must be CORS preflights with an Origin and \
+                 Access-Control-Request-Method",
+            ))
+        }
+    };
+
+    let options = request
+        .rocket()
+        .state::<CorsOptions>()
+        .ok_or_else(|| ApiError::from(Status::InternalServerError))?;
+
+    if !options.allows_origin(origin) {
+        return Err(ApiError::new(
+            Status::Forbidden,
+            "cors_origin_not_allowed",
+            format!("Requests from {} are not allowed", origin),
+        ));
+    }
+
+    let method = Method::from_str(method).map_err(|_| ApiError::bad_request("Unknown method"))?;
+    if !options.allowed_methods.contains(&method) {
+        return Err(ApiError::new(
+            Status::Forbidden,
+            "cors_method_not_allowed",
+            format!("Cross-origin {} requests are not allowed", method),
+        ));
+    }
+
+    let requested_headers = headers
+        .get_one("Access-Control-Request-Headers")
+        .unwrap_or_default();
+    if !options.allows_headers(requested_headers) {
+        return Err(ApiError::new(
+            Status::Forbidden,
+            "cors_headers_not_allowed",
+            "A requested header is not allowed in cross-origin requests",
+        ));
+    }
+
+    if !has_route(request.rocket(), method, request.uri().path().as_str()) {
+        return Err(ApiError::not_found(
+            "No route serves this method at this path",
+        ));
+    }
+
+    Ok(Preflight {
+        origin: origin.into(),
+        options: options.clone(),
+    })
+}
+
+/// Returns true if a mounted route serves `method` at `path`
+fn has_route(rocket: &Rocket<Orbit>, method: Method, path: &str) -> bool {
+    rocket
+        .routes()
+        .filter(|route| route.method == method)
+        .any(|route| path_matches(route.uri.path(), path))
+}
+
+/// Returns true if `path` matches the route path `pattern`, in which
+/// `<name>` matches any one segment and `<name..>` any remaining segments
+fn path_matches(pattern: &str, path: &str) -> bool {
+    let mut segments = path.split('/').filter(|s| !s.is_empty());
+
+    for expected in pattern.split('/').filter(|s| !s.is_empty()) {
+        if expected.starts_with('<') && expected.ends_with("..>") {
+            return true;
+        }
+        match segments.next() {
+            Some(_) if expected.starts_with('<') => {}
+            Some(segment) if segment == expected => {}
+            _ => return false,
+        }
+    }
+
+    segments.next().is_none()
+}
+
+impl<'r> Responder<'r, 'static> for Preflight {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let options = &self.options;
+        let methods: Vec<&str> = options.allowed_methods.iter().map(|m| m.as_str()).collect();
+
+        let mut response = Response::build().status(Status::NoContent).finalize();
+        options.add_origin_headers(&self.origin, &mut response);
+        response.set_raw_header("Access-Control-Allow-Methods", methods.join(", "));
+        if !options.allowed_headers.is_empty() {
+            response.set_raw_header(
+                "Access-Control-Allow-Headers",
+                options.allowed_headers.join(", "),
+            );
+        }
+        if let Some(max_age) = options.max_age {
+            response.set_raw_header("Access-Control-Max-Age", max_age.to_string());
+        }
+
+        Ok(response)
+    }
+}
+
+/// Answers CORS preflight requests for every route
+#[options("/<_..>")]
+pub fn preflight_endpoint(preflight: Preflight) -> Preflight {
+    preflight
+}
@@ -0,0 +1,302 @@
+//! This module contains the implementation of `DatabaseAccess::bulk_write`
+//!
+//! Version 2 of the driver has no bulk write API, so operations are grouped
+//! into batches of the same kind and each batch is sent as a single
+//! `insert`, `update` or `delete` command, as the drivers' own bulk writes
+//! do. Ordered bulk writes keep consecutive operations of a kind together
+//! and stop at the first failing batch; unordered ones send one batch per
+//! kind and carry on past failures. Like MongoDB bulk writes, they are not
+//! atomic: writes made before a failure stay applied and are reported in
+//! the partial result of `DBError::BulkWriteError`.
+
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::UpdateModifications;
+use std::fmt;
+use std::future::Future;
+
+use super::database::{to_document, to_insert_document, to_update_document};
+use super::err::DBError;
+use super::IntoDocument;
+
+/// Largest number of operations sent in a single command
+const MAX_BATCH_SIZE: usize = 1000;
+
+/// A single write to be executed as part of `DatabaseAccess::bulk_write`.
+/// Documents, queries and updates can be raw json, BSON documents or the
+/// typed builders in `common::query`
+#[derive(Clone, Copy)]
+pub enum WriteOperation<'a> {
+    InsertOne {
+        document: &'a dyn IntoDocument,
+    },
+    UpdateOne {
+        query: &'a dyn IntoDocument,
+        update: &'a dyn IntoDocument,
+        upsert: bool,
+    },
+    UpdateMany {
+        query: &'a dyn IntoDocument,
+        update: &'a dyn IntoDocument,
+    },
+    DeleteOne {
+        query: &'a dyn IntoDocument,
+    },
+    DeleteMany {
+        query: &'a dyn IntoDocument,
+    },
+}
+
+impl<'a> fmt::Debug for WriteOperation<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            WriteOperation::InsertOne { .. } => "InsertOne",
+            WriteOperation::UpdateOne { .. } => "UpdateOne",
+            WriteOperation::UpdateMany { .. } => "UpdateMany",
+            WriteOperation::DeleteOne { .. } => "DeleteOne",
+            WriteOperation::DeleteMany { .. } => "DeleteMany",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Summary of the writes performed by `DatabaseAccess::bulk_write`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BulkWriteResult {
+    pub inserted_count: u64,
+    pub matched_count: u64,
+    pub modified_count: u64,
+    pub deleted_count: u64,
+    pub upserted_count: u64,
+}
+
+/// A write of a bulk write which the server rejected
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkWriteFailure {
+    /// Position of the failed operation in the list passed to
+    /// `bulk_write`, or `None` for write concern errors, which apply to
+    /// a whole batch
+    pub index: Option<usize>,
+    pub code: i32,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// Operations of a single kind sent in one command
+struct Batch {
+    kind: Kind,
+    /// Position of each statement in the list passed to `bulk_write`
+    indexes: Vec<usize>,
+    statements: Vec<Bson>,
+}
+
+impl Batch {
+    fn command(self, collection: &str, ordered: bool) -> Document {
+        let (name, key) = match self.kind {
+            Kind::Insert => ("insert", "documents"),
+            Kind::Update => ("update", "updates"),
+            Kind::Delete => ("delete", "deletes"),
+        };
+
+        let mut command = Document::new();
+        command.insert(name, collection);
+        command.insert(key, self.statements);
+        command.insert("ordered", ordered);
+        command
+    }
+}
+
+/// Converts an operation on `collection` into the statement sent for it
+fn statement(collection: &str, operation: &WriteOperation) -> Result<(Kind, Bson), DBError> {
+    let update = |query, update, upsert, multi| -> Result<_, DBError> {
+        let update = match to_update_document(collection, update)? {
+            UpdateModifications::Document(update) => Bson::Document(update),
+            UpdateModifications::Pipeline(stages) => stages.into(),
+            _ => unreachable!("Updates are either documents or pipelines"),
+        };
+
+        Ok(doc! {
+            "q": to_document(query)?,
+            "u": update,
+            "upsert": upsert,
+            "multi": multi,
+        })
+    };
+
+    let (kind, statement) = match *operation {
+        WriteOperation::InsertOne { document } => {
+            // Ids are generated here, as drivers do, rather than by the server
+            let mut document = to_insert_document(collection, to_document(document)?);
+            if !document.contains_key("_id") {
+                document.insert("_id", ObjectId::new());
+            }
+            (Kind::Insert, document)
+        }
+        WriteOperation::UpdateOne {
+            query,
+            update: u,
+            upsert,
+        } => (Kind::Update, update(query, u, upsert, false)?),
+        WriteOperation::UpdateMany { query, update: u } => {
+            (Kind::Update, update(query, u, false, true)?)
+        }
+        WriteOperation::DeleteOne { query } => {
+            (Kind::Delete, doc! { "q": to_document(query)?, "limit": 1 })
+        }
+        WriteOperation::DeleteMany { query } => {
+            (Kind::Delete, doc! { "q": to_document(query)?, "limit": 0 })
+        }
+    };
+
+    Ok((kind, Bson::Document(statement)))
+}
+
+/// Groups `operations` on `collection` into the batches to send. Ordered
+/// writes keep the order of the operations, unordered ones only need a
+/// batch per kind
+fn batches(
+    collection: &str,
+    operations: &[WriteOperation],
+    ordered: bool,
+) -> Result<Vec<Batch>, DBError> {
+    let mut batches: Vec<Batch> = Vec::new();
+
+    for (index, operation) in operations.iter().enumerate() {
+        let (kind, statement) = statement(collection, operation)?;
+
+        let open = |batch: &Batch| batch.kind == kind && batch.statements.len() < MAX_BATCH_SIZE;
+        let batch = if ordered {
+            batches.last_mut().filter(|batch| open(batch))
+        } else {
+            batches.iter_mut().rev().find(|batch| open(batch))
+        };
+
+        match batch {
+            Some(batch) => {
+                batch.indexes.push(index);
+                batch.statements.push(statement);
+            }
+            None => batches.push(Batch {
+                kind,
+                indexes: vec![index],
+                statements: vec![statement],
+            }),
+        }
+    }
+
+    Ok(batches)
+}
+
+/// Reads a count from a command reply, which may hold any integer type
+fn count(reply: &Document, key: &str) -> u64 {
+    match reply.get(key) {
+        Some(Bson::Int32(n)) => *n as u64,
+        Some(Bson::Int64(n)) => *n as u64,
+        Some(Bson::Double(n)) => *n as u64,
+        _ => 0,
+    }
+}
+
+/// Adds the outcome of a batch to `result`, returning its failures
+fn record(
+    kind: Kind,
+    indexes: &[usize],
+    reply: &Document,
+    result: &mut BulkWriteResult,
+) -> Vec<BulkWriteFailure> {
+    let n = count(reply, "n");
+    match kind {
+        Kind::Insert => result.inserted_count += n,
+        Kind::Update => {
+            let upserted = reply.get_array("upserted").map(Vec::len).unwrap_or(0) as u64;
+            result.upserted_count += upserted;
+            result.matched_count += n.saturating_sub(upserted);
+            result.modified_count += count(reply, "nModified");
+        }
+        Kind::Delete => result.deleted_count += n,
+    }
+
+    let failure = |error: &Document, index| BulkWriteFailure {
+        index,
+        code: error.get_i32("code").unwrap_or_default(),
+        message: error.get_str("errmsg").unwrap_or_default().into(),
+    };
+
+    let mut failures: Vec<BulkWriteFailure> = reply
+        .get_array("writeErrors")
+        .map(|errors| errors.iter().filter_map(Bson::as_document).collect())
+        .unwrap_or_else(|_| Vec::new())
+        .into_iter()
+        .map(|error| {
+            let index = error
+                .get_i32("index")
+                .ok()
+                .and_then(|i| indexes.get(i as usize).copied());
+            failure(error, index)
+        })
+        .collect();
+
+    if let Ok(error) = reply.get_document("writeConcernError") {
+        failures.push(failure(error, None));
+    }
+
+    failures
+}
+
+/// Runs `operations` against `collection`, sending each command with `run`
+///
+/// # Arguments
+///
+/// * `collection` - Mongo collection to write to
+/// * `operations` - Writes to execute
+/// * `ordered` - Whether to stop at the first failure
+/// * `run` - Sends a command, returning the server's reply
+pub(super) async fn bulk_write<F, Fut>(
+    collection: &str,
+    operations: &[WriteOperation<'_>],
+    ordered: bool,
+    mut run: F,
+) -> Result<BulkWriteResult, DBError>
+where
+    F: FnMut(Document) -> Fut,
+    Fut: Future<Output = Result<Document, DBError>>,
+{
+    let mut result = BulkWriteResult::default();
+    let mut failures = Vec::new();
+    let mut sent = false;
+
+    for batch in batches(collection, operations, ordered)? {
+        let (kind, indexes) = (batch.kind, batch.indexes.clone());
+        let reply = match run(batch.command(collection, ordered)).await {
+            Ok(reply) => reply,
+            // Earlier batches were applied, so their counts are reported
+            Err(e) if sent => {
+                failures.push(BulkWriteFailure {
+                    index: indexes.first().copied(),
+                    code: 0,
+                    message: e.to_string(),
+                });
+                return Err(DBError::BulkWriteError { result, failures });
+            }
+            Err(e) => return Err(e),
+        };
+        sent = true;
+
+        failures.extend(record(kind, &indexes, &reply, &mut result));
+        if ordered && !failures.is_empty() {
+            break;
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(result)
+    } else {
+        Err(DBError::BulkWriteError { result, failures })
+    }
+}
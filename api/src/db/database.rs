@@ -2,55 +2,81 @@
 //! for use throughout the codebase without directly working with
 //! mongodb objects.
 //!
-//! While Rocket v0.4 provides a mongodb client for use with Rocket
-//! with connection pooling provided by `r2d2`, this client is pretty
-//! heavily out of date and more recent mongodb crates implement connection
-//! pooling internally. So there's no need to use the internal mongodb client
-//! since managing a mongodb connection yourself is incredibly simple.
-//! 
+//! The mongodb crate implements connection pooling internally, so there's
+//! no need for a separate pool such as `rocket_db_pools` since managing a
+//! mongodb connection yourself is incredibly simple.
+//!
 //! Adding a fully managed mongo client to rocket is as simple as the following:
+//! ```no_run
+//! # rocket::execute(async {
+//! let client = mongodb::Client::with_uri_str("mongodb://localhost:27017/")
+//!     .await
+//!     .unwrap();
+//! rocket::build().manage(client.database("appdb")).launch().await.unwrap();
+//! # });
 //! ```
-//! let client = mongodb::sync::Client::with_uri_str("mongodb://localhost:27017/").unwrap();
-//! rocket::ignite().manage(client.database("appdb")).launch();
-//! ```
-//! 
+//!
 //! This connection can then be fetched in request guards with
-//! 
+//!
 //! ```ignore
 //! let db = request
-//!     .guard::<State<mongodb::sync::Database>>()
+//!     .guard::<&State<mongodb::Database>>()
+//!     .await
 //!     .expect("No managed db connection");
 //! ```
-//! 
+//!
 //! or in endpoints with
-//! 
+//!
 //! ```ignore
 //! #[get("/endpoint")]
-//! pub fn endpoint(db: State<mongodb::sync::Database>) -> Status {
+//! pub async fn endpoint(db: &State<mongodb::Database>) -> Status {
 //!     // ...
 //! }
 //! ```
-//! 
+//!
 //! This is essentially how the database is hooked up to rocket in this crate
 //! except the mongodb `Client` and `Database` are wrapped by our own
 //! custom types to provide abstration.
 
+use crate::{metrics, telemetry};
+use common::query::{Filter, Sort, Update};
 use log::{error, info};
-use mongodb::bson::{self, Bson};
-use mongodb::sync::{Client, Database as MongoDatabase};
-use rocket_contrib::json::JsonValue;
-use std::backtrace::Backtrace;
+use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::options::{
+    FindOneAndUpdateOptions, FindOptions, ReturnDocument, UpdateModifications, UpdateOptions,
+};
+use mongodb::{Client, Database as MongoDatabase};
+use opentelemetry::KeyValue;
+use rocket::async_trait;
+use rocket::futures::TryStreamExt;
+use serde_json::Value as JsonValue;
+use std::future::Future;
+use std::sync::Mutex;
 
+use super::bulk::{self, BulkWriteResult, WriteOperation};
 use super::err::DBError;
+use super::resilience::{Resilience, ResilienceOptions};
+
+/// Name of the counter incremented by every update made through
+/// `DatabaseAccess`, used for optimistic concurrency checks
+pub const VERSION_FIELD: &str = "version";
+
+/// Name of the time set by every update made through `DatabaseAccess`
+pub const UPDATED_FIELD: &str = "updated";
+
+/// Collections whose models declare `VERSION_FIELD` and `UPDATED_FIELD`.
+/// Writes to any other collection are sent unchanged
+pub const VERSIONED_COLLECTIONS: &[&str] = &["users"];
 
 /// Represents a connection to a mongodb instance
 pub struct DBClient(Client);
 
-/// Represents a database
-struct _Database(MongoDatabase);
+/// Represents a database along with the client it belongs to, which is
+/// needed to start sessions, and whether the deployment supports transactions
+struct _Database(MongoDatabase, Client, Mutex<Option<bool>>);
 
-/// Provides logging on database operations
-pub struct Database(_Database);
+/// Provides logging, retries and circuit breaking on database operations
+pub struct Database(_Database, Resilience);
 
 impl DBClient {
     /// Returns a wrapped mongodb client connection with the given uri
@@ -63,13 +89,17 @@ impl DBClient {
     ///
     /// ```
     /// use api::db::DBClient;
-    /// let client = DBClient::init("mongodb://localhost:27017/");
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
+    /// # });
     /// ```
-    pub fn init(uri: &str) -> Self {
+    pub async fn init(uri: &str) -> Self {
         info!(target: "Database", "Creating db client to {}", uri);
-        DBClient {
-            0: Client::with_uri_str(uri).unwrap_or_else(|_| panic!("Invalid mongodb uri: {}", uri)),
-        }
+        DBClient(
+            Client::with_uri_str(uri)
+                .await
+                .unwrap_or_else(|_| panic!("Invalid mongodb uri: {}", uri)),
+        )
     }
 
     /// Returns a wrapped database connection
@@ -82,16 +112,42 @@ impl DBClient {
     ///
     /// ```
     /// use api::db::DBClient;
-    /// let client = DBClient::init("mongodb://localhost:27017/");
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
     /// let db = client.get_database("appdb");
+    /// # });
     /// ```
     pub fn get_database(&self, name: &str) -> Database {
+        self.get_database_with_options(name, ResilienceOptions::default())
+    }
+
+    /// Returns a wrapped database connection using the given retry and
+    /// circuit breaker settings
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the database to connect to
+    /// * `options` - Retry and circuit breaker settings
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use api::db::{DBClient, ResilienceOptions};
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
+    /// let options = ResilienceOptions {
+    ///     read_retries: 5,
+    ///     ..ResilienceOptions::default()
+    /// };
+    /// let db = client.get_database_with_options("appdb", options);
+    /// # });
+    /// ```
+    pub fn get_database_with_options(&self, name: &str, options: ResilienceOptions) -> Database {
         info! {target: "Database", "Creating db connection {}", name};
-        Database {
-            0: _Database {
-                0: self.0.database(name),
-            },
-        }
+        Database(
+            _Database(self.0.database(name), self.0.clone(), Mutex::new(None)),
+            Resilience::new(options),
+        )
     }
 }
 
@@ -105,41 +161,251 @@ impl Database {
     pub fn to_inner(&self) -> &MongoDatabase {
         &self.0 .0
     }
+
+    /// returns the client this database was created from
+    pub(super) fn client(&self) -> &Client {
+        &self.0 .1
+    }
+
+    /// returns whether the deployment supports transactions, if known yet
+    pub(super) fn transaction_support(&self) -> &Mutex<Option<bool>> {
+        &self.0 .2
+    }
+
+    /// Runs an operation inside a tracing span, recording its timing and
+    /// outcome in the metrics
+    pub(super) async fn instrumented<T>(
+        &self,
+        collection: &str,
+        operation: &str,
+        op: impl Future<Output = Result<T, DBError>>,
+    ) -> Result<T, DBError> {
+        let attributes = vec![
+            KeyValue::new("db.system", "mongodb"),
+            KeyValue::new("db.mongodb.collection", collection.to_string()),
+            KeyValue::new("db.operation", operation.to_string()),
+        ];
+
+        let name = format!("{} {}", operation, collection);
+        telemetry::in_span(name, attributes, async {
+            let result = metrics::observe_db(collection, operation, op).await;
+            telemetry::record_result(&result);
+            result
+        })
+        .await
+    }
+
+    /// returns the retry and circuit breaker policies
+    pub(super) fn resilience(&self) -> &Resilience {
+        &self.1
+    }
+}
+
+/// Implemented by anything that can be used as a query or update document:
+/// raw json, BSON documents or the typed builders in `common::query`.
+/// Queries are held across awaits, so they must be `Sync`
+pub trait IntoDocument: Sync {
+    fn to_document(&self) -> Result<Document, DBError>;
+}
+
+impl IntoDocument for JsonValue {
+    fn to_document(&self) -> Result<Document, DBError> {
+        Ok(bson::to_bson(self)?
+            .as_document()
+            .ok_or(DBError::BsonDocumentError)?
+            .clone())
+    }
 }
 
+impl IntoDocument for Document {
+    fn to_document(&self) -> Result<Document, DBError> {
+        Ok(self.clone())
+    }
+}
+
+impl<M> IntoDocument for Filter<M> {
+    fn to_document(&self) -> Result<Document, DBError> {
+        Ok(Filter::to_document(self)?)
+    }
+}
+
+impl<M> IntoDocument for Sort<M> {
+    fn to_document(&self) -> Result<Document, DBError> {
+        Ok(Sort::to_document(self))
+    }
+}
+
+impl<M> IntoDocument for Update<M> {
+    fn to_document(&self) -> Result<Document, DBError> {
+        Ok(Update::to_document(self)?)
+    }
+}
+
+/// Converts a query or update into a BSON document
+pub(super) fn to_document(value: &dyn IntoDocument) -> Result<Document, DBError> {
+    value.to_document()
+}
+
+/// Serializes a model into the document it is stored as, with its dates
+/// as native BSON datetimes
+pub(super) fn to_stored_document<T: serde::Serialize>(item: &T) -> Result<Document, DBError> {
+    match common::query::to_bson(item)? {
+        Bson::Document(doc) => Ok(doc),
+        _ => Err(DBError::BsonDocumentError),
+    }
+}
+
+/// Returns true if the documents of `collection` declare `VERSION_FIELD`
+/// and `UPDATED_FIELD`, which every write to them then maintains
+pub(super) fn is_versioned(collection: &str) -> bool {
+    VERSIONED_COLLECTIONS.contains(&collection)
+}
+
+/// Prepares a document for insertion into `collection`, starting its
+/// version counter and setting its `updated` time if it is versioned
+pub(super) fn to_insert_document(collection: &str, mut doc: Document) -> Document {
+    if is_versioned(collection) {
+        doc.insert(VERSION_FIELD, 0i64);
+        doc.insert(UPDATED_FIELD, bson::DateTime::now());
+    }
+    doc
+}
+
+/// Converts an update for `collection` into the modifications sent to
+/// MongoDB. Updates of versioned collections also increment the document's
+/// version counter and set its `updated` time, unless they already modify
+/// them. Replacement documents are sent as a pipeline, which keeps the
+/// document's `_id` and lets the counter be incremented
+pub(super) fn to_update_document(
+    collection: &str,
+    update: &dyn IntoDocument,
+) -> Result<UpdateModifications, DBError> {
+    let mut update = update.to_document()?;
+    let versioned = is_versioned(collection);
+
+    let replacement = update.keys().any(|key| !key.starts_with('$'));
+    if replacement {
+        let mut fields = vec![
+            Bson::Document(doc! { "_id": "$_id" }),
+            Bson::Document(doc! { "$literal": update }),
+        ];
+        if versioned {
+            fields.push(Bson::Document(doc! {
+                VERSION_FIELD: { "$add": [{ "$ifNull": [format!("${}", VERSION_FIELD), 0] }, 1] },
+                UPDATED_FIELD: "$$NOW",
+            }));
+        }
+        let stage = doc! { "$replaceWith": { "$mergeObjects": fields } };
+        return Ok(UpdateModifications::Pipeline(vec![stage]));
+    }
+
+    if !versioned {
+        return Ok(UpdateModifications::Document(update));
+    }
+
+    let touches = |field| {
+        update
+            .values()
+            .filter_map(Bson::as_document)
+            .any(|fields| fields.contains_key(field))
+    };
+    let touches_version = touches(VERSION_FIELD);
+    let touches_updated = touches(UPDATED_FIELD);
+
+    if !touches_version {
+        operator(&mut update, "$inc").insert(VERSION_FIELD, 1);
+    }
+    if !touches_updated {
+        operator(&mut update, "$currentDate").insert(UPDATED_FIELD, true);
+    }
+
+    Ok(UpdateModifications::Document(update))
+}
+
+/// Returns the fields of the `op` operator of an update document, adding
+/// the operator if it is missing
+fn operator<'a>(update: &'a mut Document, op: &str) -> &'a mut Document {
+    if !update.contains_key(op) {
+        update.insert(op, Document::new());
+    }
+    update
+        .get_document_mut(op)
+        .expect("Update operator is not a document")
+}
+
+#[async_trait]
 pub trait DatabaseAccess {
-    fn find_one<T>(&self, collection: &str, query: &JsonValue) -> Result<Option<T>, DBError>
+    async fn find_one<T>(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+    ) -> Result<Option<T>, DBError>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync;
+
+    async fn find<T>(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        sort: Option<&dyn IntoDocument>,
+        limit: Option<i64>,
+    ) -> Result<Vec<T>, DBError>
     where
-        T: serde::Serialize + serde::de::DeserializeOwned;
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync;
 
-    fn insert_one<T>(&self, collection: &str, item: &T) -> Result<T, DBError>
+    async fn insert_one<T>(&self, collection: &str, item: &T) -> Result<T, DBError>
     where
-        T: serde::Serialize + serde::de::DeserializeOwned;
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync;
 
-    fn update_one(
+    async fn update_one(
         &self,
         collection: &str,
-        query: &JsonValue,
-        update: &JsonValue,
+        query: &dyn IntoDocument,
+        update: &dyn IntoDocument,
     ) -> Result<(), DBError>;
+
+    async fn find_one_and_update<T>(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        update: &dyn IntoDocument,
+    ) -> Result<Option<T>, DBError>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync;
+
+    async fn upsert(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        update: &dyn IntoDocument,
+    ) -> Result<(), DBError>;
+
+    async fn delete_one(&self, collection: &str, query: &dyn IntoDocument) -> Result<u64, DBError>;
+
+    async fn delete_many(&self, collection: &str, query: &dyn IntoDocument)
+        -> Result<u64, DBError>;
+
+    async fn bulk_write(
+        &self,
+        collection: &str,
+        operations: &[WriteOperation<'_>],
+        ordered: bool,
+    ) -> Result<BulkWriteResult, DBError>;
 }
 
+#[async_trait]
 impl DatabaseAccess for _Database {
-    fn find_one<T>(&self, collection: &str, query: &JsonValue) -> Result<Option<T>, DBError>
+    async fn find_one<T>(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+    ) -> Result<Option<T>, DBError>
     where
-        T: serde::Serialize + serde::de::DeserializeOwned,
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync,
     {
-        let collection = self.0.collection(collection);
-
-        let item = collection.find_one(
-            bson::to_bson(&query)?
-                .as_document()
-                .ok_or(DBError::BsonDocumentError {
-                    backtrace: Backtrace::capture(),
-                })?
-                .clone(),
-            None,
-        )?;
+        let collection = self.0.collection::<Document>(collection);
+
+        let item = collection.find_one(to_document(query)?, None).await?;
 
         match item {
             Some(doc) => {
@@ -150,64 +416,158 @@ impl DatabaseAccess for _Database {
         }
     }
 
-    fn insert_one<T>(&self, collection: &str, item: &T) -> Result<T, DBError>
+    async fn find<T>(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        sort: Option<&dyn IntoDocument>,
+        limit: Option<i64>,
+    ) -> Result<Vec<T>, DBError>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync,
+    {
+        let collection = self.0.collection::<Document>(collection);
+        let options = FindOptions::builder()
+            .sort(sort.map(to_document).transpose()?)
+            .limit(limit)
+            .build();
+
+        let docs: Vec<Document> = collection
+            .find(to_document(query)?, options)
+            .await?
+            .try_collect()
+            .await?;
+
+        docs.into_iter()
+            .map(|doc| Ok(bson::from_document(doc)?))
+            .collect()
+    }
+
+    async fn insert_one<T>(&self, collection: &str, item: &T) -> Result<T, DBError>
     where
-        T: serde::Serialize + serde::de::DeserializeOwned,
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync,
     {
-        let mut user_bson = bson::to_bson(&item)?;
-
-        let collection = self.0.collection(collection);
-
-        let user_bson = user_bson
-            .as_document_mut()
-            .ok_or(DBError::BsonDocumentError {
-                backtrace: Backtrace::capture(),
-            })?;
-
-        let result = collection.insert_one(user_bson.clone(), None)?;
-        match result.inserted_id {
-            bson::Bson::ObjectId(id) => {
-                user_bson.insert("_id", id);
-                let item = bson::from_bson::<T>(Bson::Document(user_bson.clone()))?;
-                Ok(item)
+        let mut user_bson = to_insert_document(collection, to_stored_document(item)?);
+
+        let collection = self.0.collection::<Document>(collection);
+
+        let result = collection.insert_one(user_bson.clone(), None).await?;
+        user_bson.insert("_id", result.inserted_id);
+        let item = bson::from_bson::<T>(Bson::Document(user_bson))?;
+        Ok(item)
+    }
+
+    async fn update_one(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        update: &dyn IntoDocument,
+    ) -> Result<(), DBError> {
+        let collection = self.0.collection::<Document>(collection);
+
+        let _ = collection
+            .update_one(
+                to_document(query)?,
+                to_update_document(collection.name(), update)?,
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn find_one_and_update<T>(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        update: &dyn IntoDocument,
+    ) -> Result<Option<T>, DBError>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync,
+    {
+        let collection = self.0.collection::<Document>(collection);
+
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let item = collection
+            .find_one_and_update(
+                to_document(query)?,
+                to_update_document(collection.name(), update)?,
+                options,
+            )
+            .await?;
+
+        match item {
+            Some(doc) => {
+                let item: T = bson::from_bson(Bson::Document(doc))?;
+                Ok(Some(item))
             }
-            _ => Err(DBError::UnknownError {
-                backtrace: Backtrace::capture(),
-            }),
+            None => Ok(None),
         }
     }
 
-    fn update_one(
+    async fn upsert(
         &self,
         collection: &str,
-        query: &JsonValue,
-        update: &JsonValue,
+        query: &dyn IntoDocument,
+        update: &dyn IntoDocument,
     ) -> Result<(), DBError> {
-        let collection = self.0.collection(collection);
-
-        let _ = collection.update_one(
-            bson::to_bson(&query)?
-                .as_document()
-                .ok_or(DBError::BsonDocumentError {
-                    backtrace: Backtrace::capture(),
-                })?
-                .clone(),
-            bson::to_bson(&update)?
-                .as_document()
-                .ok_or(DBError::BsonDocumentError {
-                    backtrace: Backtrace::capture(),
-                })?
-                .clone(),
-            None,
-        )?;
+        let collection = self.0.collection::<Document>(collection);
+
+        let options = UpdateOptions::builder().upsert(true).build();
+
+        let _ = collection
+            .update_one(
+                to_document(query)?,
+                to_update_document(collection.name(), update)?,
+                options,
+            )
+            .await?;
 
         Ok(())
     }
+
+    async fn delete_one(&self, collection: &str, query: &dyn IntoDocument) -> Result<u64, DBError> {
+        let collection = self.0.collection::<Document>(collection);
+
+        let result = collection.delete_one(to_document(query)?, None).await?;
+
+        Ok(result.deleted_count)
+    }
+
+    async fn delete_many(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+    ) -> Result<u64, DBError> {
+        let collection = self.0.collection::<Document>(collection);
+
+        let result = collection.delete_many(to_document(query)?, None).await?;
+
+        Ok(result.deleted_count)
+    }
+
+    async fn bulk_write(
+        &self,
+        collection: &str,
+        operations: &[WriteOperation<'_>],
+        ordered: bool,
+    ) -> Result<BulkWriteResult, DBError> {
+        let db = &self.0;
+
+        bulk::bulk_write(collection, operations, ordered, |command| async move {
+            Ok(db.run_command(command, None).await?)
+        })
+        .await
+    }
 }
 
+#[async_trait]
 impl DatabaseAccess for Database {
     /// Fetches a single item from the database given the collection,
-    /// query, and type `T: serde::Serialize + serde::de::DeserializeOwned`
+    /// query, and type `T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync`
     ///
     /// # Arguments
     ///
@@ -219,7 +579,7 @@ impl DatabaseAccess for Database {
     /// ```
     /// use api::db::{DBClient, DatabaseAccess};
     /// use serde::{Serialize, Deserialize};
-    /// use rocket_contrib::json;
+    /// use serde_json::json;
     ///
     /// #[derive(Debug, Serialize, Deserialize)]
     /// struct Person {
@@ -228,30 +588,106 @@ impl DatabaseAccess for Database {
     ///   pub name: String,
     /// }
     ///
-    /// let client = DBClient::init("mongodb://localhost:27017/");
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
     /// let db = client.get_database("appdb");
     ///
     /// let query = json! {{
     ///   "name": "Foo"
     /// }};
     ///
-    /// let person: Option<Person> = db.find_one("people", &query).unwrap();
+    /// let person: Option<Person> = db.find_one("people", &query).await.unwrap();
+    /// # });
     /// ```
-    fn find_one<T>(&self, collection: &str, query: &JsonValue) -> Result<Option<T>, DBError>
+    async fn find_one<T>(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+    ) -> Result<Option<T>, DBError>
     where
-        T: serde::Serialize + serde::de::DeserializeOwned,
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync,
     {
-        let result = self.0.find_one(collection, query);
+        let result = self
+            .instrumented(
+                collection,
+                "find_one",
+                self.1.read(|| self.0.find_one(collection, query)),
+            )
+            .await;
 
         if let Err(e) = &result {
-            error!("Error fetching from db {:#?}", e)
+            error!(target: "Database", "Error fetching from {}: {}", collection, e)
+        };
+
+        result
+    }
+
+    /// Fetches every item matching the query from the database given the
+    /// collection, an optional sort order and limit, and type
+    /// `T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync`
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - Mongo collection to search in
+    /// * `query` - Query to filter results by
+    /// * `sort` - Order to return results in
+    /// * `limit` - Maximum number of results to return
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use api::db::{DBClient, DatabaseAccess};
+    /// use serde::{Serialize, Deserialize};
+    /// use serde_json::json;
+    ///
+    /// #[derive(Debug, Serialize, Deserialize)]
+    /// struct Person {
+    ///   #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    ///   pub id: Option<mongodb::bson::oid::ObjectId>,
+    ///   pub name: String,
+    /// }
+    ///
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
+    /// let db = client.get_database("appdb");
+    ///
+    /// let query = json! {{
+    ///   "name": "Foo"
+    /// }};
+    /// let sort = json! {{
+    ///   "_id": -1
+    /// }};
+    ///
+    /// let people: Vec<Person> = db.find("people", &query, Some(&sort), Some(10)).await.unwrap();
+    /// # });
+    /// ```
+    async fn find<T>(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        sort: Option<&dyn IntoDocument>,
+        limit: Option<i64>,
+    ) -> Result<Vec<T>, DBError>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync,
+    {
+        let result = self
+            .instrumented(
+                collection,
+                "find",
+                self.1.read(|| self.0.find(collection, query, sort, limit)),
+            )
+            .await;
+
+        if let Err(e) = &result {
+            error!(target: "Database", "Error fetching from {}: {}", collection, e)
         };
 
         result
     }
 
     /// Inserts a single item into the database given the collection,
-    /// and type `T: serde::Serialize + serde::de::DeserializeOwned`
+    /// and type `T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync`
     ///
     /// # Arguments
     ///
@@ -271,21 +707,29 @@ impl DatabaseAccess for Database {
     ///   pub name: String,
     /// }
     ///
-    /// let client = DBClient::init("mongodb://localhost:27017/");
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
     /// let db = client.get_database("appdb");
     ///
     /// let p = Person{ id: None, name: "Foo".into() };
     ///
-    /// db.insert_one("people", &p).unwrap();
+    /// db.insert_one("people", &p).await.unwrap();
+    /// # });
     /// ```
-    fn insert_one<T>(&self, collection: &str, item: &T) -> Result<T, DBError>
+    async fn insert_one<T>(&self, collection: &str, item: &T) -> Result<T, DBError>
     where
-        T: serde::Serialize + serde::de::DeserializeOwned,
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync,
     {
-        let result = self.0.insert_one(collection, item);
+        let result = self
+            .instrumented(
+                collection,
+                "insert_one",
+                self.1.call(self.0.insert_one(collection, item)),
+            )
+            .await;
 
         if let Err(e) = &result {
-            error!("Error inserting to db {:#?}", e)
+            error!(target: "Database", "Error inserting to {}: {}", collection, e)
         };
 
         result
@@ -305,7 +749,72 @@ impl DatabaseAccess for Database {
     /// ```
     /// use api::db::{DBClient, DatabaseAccess};
     /// use serde::{Serialize, Deserialize};
-    /// use rocket_contrib::json;
+    /// use serde_json::json;
+    ///
+    /// #[derive(Debug, Serialize, Deserialize)]
+    /// struct Person {
+    ///   #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    ///   pub id: Option<mongodb::bson::oid::ObjectId>,
+    ///   pub name: String,
+    /// }
+    ///
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
+    /// let db = client.get_database("appdb");
+    ///
+    /// let p = Person{ id: None, name: "Foo".into() };
+    ///
+    /// db.insert_one("people", &p).await.unwrap();
+    ///
+    /// let query = json! {{
+    ///   "name": "Foo"
+    /// }};
+    ///
+    /// let update = json! {{
+    ///   "$set": {
+    ///     "name": "Bar"
+    ///   }
+    /// }};
+    ///
+    /// db.update_one("people", &query, &update).await.unwrap();
+    /// # });
+    /// ```
+    async fn update_one(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        update: &dyn IntoDocument,
+    ) -> Result<(), DBError> {
+        let result = self
+            .instrumented(
+                collection,
+                "update_one",
+                self.1.call(self.0.update_one(collection, query, update)),
+            )
+            .await;
+
+        if let Err(e) = &result {
+            error!(target: "Database", "Error updating {}: {}", collection, e)
+        };
+
+        result
+    }
+
+    /// Atomically updates a single item in the database and returns the
+    /// updated item, or `None` if nothing matched the lookup query
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - Mongo collection to update in
+    /// * `query` - Lookup query
+    /// * `update` - Fields to update
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use api::db::{DBClient, DatabaseAccess};
+    /// use serde::{Serialize, Deserialize};
+    /// use serde_json::json;
     ///
     /// #[derive(Debug, Serialize, Deserialize)]
     /// struct Person {
@@ -314,12 +823,13 @@ impl DatabaseAccess for Database {
     ///   pub name: String,
     /// }
     ///
-    /// let client = DBClient::init("mongodb://localhost:27017/");
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
     /// let db = client.get_database("appdb");
     ///
     /// let p = Person{ id: None, name: "Foo".into() };
     ///
-    /// db.insert_one("people", &p).unwrap();
+    /// db.insert_one("people", &p).await.unwrap();
     ///
     /// let query = json! {{
     ///   "name": "Foo"
@@ -331,18 +841,229 @@ impl DatabaseAccess for Database {
     ///   }
     /// }};
     ///
-    /// db.update_one("people", &query, &update).unwrap();
+    /// let person: Option<Person> = db.find_one_and_update("people", &query, &update).await.unwrap();
+    /// # });
+    /// ```
+    async fn find_one_and_update<T>(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        update: &dyn IntoDocument,
+    ) -> Result<Option<T>, DBError>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync,
+    {
+        let result = self
+            .instrumented(
+                collection,
+                "find_one_and_update",
+                self.1
+                    .call(self.0.find_one_and_update(collection, query, update)),
+            )
+            .await;
+
+        if let Err(e) = &result {
+            error!(target: "Database", "Error updating {}: {}", collection, e)
+        };
+
+        result
+    }
+
+    /// Updates a single item in the database given the collection, lookup
+    /// query, and update, inserting a new item if nothing matched the query
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - Mongo collection to upsert to
+    /// * `query` - Lookup query
+    /// * `update` - Fields to update or insert
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use api::db::{DBClient, DatabaseAccess};
+    /// use serde_json::json;
+    ///
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
+    /// let db = client.get_database("appdb");
+    ///
+    /// let query = json! {{
+    ///   "name": "Foo"
+    /// }};
+    ///
+    /// let update = json! {{
+    ///   "$set": {
+    ///     "age": 30
+    ///   }
+    /// }};
+    ///
+    /// db.upsert("people", &query, &update).await.unwrap();
+    /// # });
     /// ```
-    fn update_one(
+    async fn upsert(
         &self,
         collection: &str,
-        query: &JsonValue,
-        update: &JsonValue,
+        query: &dyn IntoDocument,
+        update: &dyn IntoDocument,
     ) -> Result<(), DBError> {
-        let result = self.0.update_one(collection, query, update);
+        let result = self
+            .instrumented(
+                collection,
+                "upsert",
+                self.1.call(self.0.upsert(collection, query, update)),
+            )
+            .await;
+
+        if let Err(e) = &result {
+            error!(target: "Database", "Error upserting to {}: {}", collection, e)
+        };
+
+        result
+    }
+
+    /// Deletes a single item from the database given the collection and
+    /// lookup query. Returns the number of deleted items
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - Mongo collection to delete from
+    /// * `query` - Lookup query
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use api::db::{DBClient, DatabaseAccess};
+    /// use serde_json::json;
+    ///
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
+    /// let db = client.get_database("appdb");
+    ///
+    /// let query = json! {{
+    ///   "name": "Foo"
+    /// }};
+    ///
+    /// let deleted = db.delete_one("people", &query).await.unwrap();
+    /// # });
+    /// ```
+    async fn delete_one(&self, collection: &str, query: &dyn IntoDocument) -> Result<u64, DBError> {
+        let result = self
+            .instrumented(
+                collection,
+                "delete_one",
+                self.1.call(self.0.delete_one(collection, query)),
+            )
+            .await;
+
+        if let Err(e) = &result {
+            error!(target: "Database", "Error deleting from {}: {}", collection, e)
+        };
+
+        result
+    }
+
+    /// Deletes every item matching the lookup query from the given
+    /// collection. Returns the number of deleted items
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - Mongo collection to delete from
+    /// * `query` - Lookup query
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use api::db::{DBClient, DatabaseAccess};
+    /// use serde_json::json;
+    ///
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
+    /// let db = client.get_database("appdb");
+    ///
+    /// let query = json! {{
+    ///   "name": "Foo"
+    /// }};
+    ///
+    /// let deleted = db.delete_many("people", &query).await.unwrap();
+    /// # });
+    /// ```
+    async fn delete_many(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+    ) -> Result<u64, DBError> {
+        let result = self
+            .instrumented(
+                collection,
+                "delete_many",
+                self.1.call(self.0.delete_many(collection, query)),
+            )
+            .await;
+
+        if let Err(e) = &result {
+            error!(target: "Database", "Error deleting from {}: {}", collection, e)
+        };
+
+        result
+    }
+
+    /// Executes a list of writes against a single collection, sending
+    /// consecutive writes of the same kind in a single command. Ordered
+    /// writes stop at the first failure, unordered ones attempt every
+    /// write. The writes are not atomic: if some fail, the error holds the
+    /// counts of those applied and the position of each failed write
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - Mongo collection to write to
+    /// * `operations` - Writes to execute
+    /// * `ordered` - Whether to stop at the first failure
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use api::db::{DBClient, DatabaseAccess, WriteOperation};
+    /// use serde_json::json;
+    ///
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
+    /// let db = client.get_database("appdb");
+    ///
+    /// let (foo, bar) = (json! {{ "name": "Foo" }}, json! {{ "name": "Bar" }});
+    /// let rename = json! {{ "$set": { "name": "Bar" } }};
+    ///
+    /// let operations = vec![
+    ///     WriteOperation::InsertOne { document: &foo },
+    ///     WriteOperation::UpdateOne {
+    ///         query: &foo,
+    ///         update: &rename,
+    ///         upsert: false,
+    ///     },
+    ///     WriteOperation::DeleteMany { query: &bar },
+    /// ];
+    ///
+    /// let result = db.bulk_write("people", &operations, true).await.unwrap();
+    /// assert_eq!(result.inserted_count, 1);
+    /// # });
+    /// ```
+    async fn bulk_write(
+        &self,
+        collection: &str,
+        operations: &[WriteOperation<'_>],
+        ordered: bool,
+    ) -> Result<BulkWriteResult, DBError> {
+        let result = self
+            .instrumented(
+                collection,
+                "bulk_write",
+                self.1
+                    .call(self.0.bulk_write(collection, operations, ordered)),
+            )
+            .await;
 
         if let Err(e) = &result {
-            error!("Error fetching from db {:#?}", e)
+            error!(target: "Database", "Error writing to {}: {}", collection, e)
         };
 
         result
@@ -1,42 +1,76 @@
 //! This module contains error information for database operations
 
+use super::bulk::{BulkWriteFailure, BulkWriteResult};
+use common::query::QueryError;
 use mongodb::bson::de::Error as BsonDeserializationError;
 use mongodb::bson::ser::Error as BsonSerializationError;
-use mongodb::error::Error as MongoError;
+use mongodb::error::{Error as MongoError, ErrorKind, WriteFailure};
 use rocket::http::Status;
-use std::backtrace::Backtrace;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum DBError {
     #[error("An unknown error occurred")]
-    UnknownError { backtrace: Backtrace },
+    UnknownError,
     #[error("An error originated from within MongoDB: {source:?}")]
     MongoError {
         #[from]
         source: MongoError,
-        backtrace: Backtrace,
     },
     #[error("Could not deserialize BSON: {source:?}")]
     BsonDeserializationError {
         #[from]
         source: BsonDeserializationError,
-        backtrace: Backtrace,
     },
     #[error("Could not serialize into BSON: {source:?}")]
     BsonSerializationError {
         #[from]
         source: BsonSerializationError,
-        backtrace: Backtrace,
     },
     #[error("Could not convert BSON to Document")]
-    BsonDocumentError { backtrace: Backtrace },
+    BsonDocumentError,
+    #[error("The database is unavailable, retry after {retry_after:?}")]
+    Unavailable { retry_after: Duration },
+    #[error("{} writes of a bulk write failed: {failures:?}", failures.len())]
+    BulkWriteError {
+        /// The writes which were applied
+        result: BulkWriteResult,
+        failures: Vec<BulkWriteFailure>,
+    },
+    #[error("Stored data is invalid: {0}")]
+    InvalidData(String),
+    #[error("Could not build query: {source}")]
+    QueryError {
+        #[from]
+        source: QueryError,
+    },
+}
+
+/// MongoDB error code for a write violating a unique index
+const DUPLICATE_KEY: i32 = 11000;
+
+impl DBError {
+    /// Returns true if this error was caused by a write violating a unique index
+    pub fn is_duplicate_key(&self) -> bool {
+        match self {
+            DBError::BulkWriteError { failures, .. } => {
+                failures.iter().any(|failure| failure.code == DUPLICATE_KEY)
+            }
+            DBError::MongoError { source, .. } => matches!(
+                source.kind.as_ref(),
+                ErrorKind::Write(WriteFailure::WriteError(e)) if e.code == DUPLICATE_KEY
+            ),
+            _ => false,
+        }
+    }
 }
 
 impl From<DBError> for Status {
     fn from(e: DBError) -> Status {
         match e {
             DBError::MongoError { .. } => Status::ServiceUnavailable,
+            DBError::Unavailable { .. } => Status::ServiceUnavailable,
             _ => Status::InternalServerError,
         }
     }
@@ -0,0 +1,378 @@
+//! This module contains versioned schema migrations for the MongoDB
+//! collections along with the runner which applies them
+//!
+//! Migrations are plain rust functions registered in `all()` in ascending
+//! version order. Every applied migration is recorded in the `migrations`
+//! collection so it only ever runs once, and a lock document in the
+//! `migration_lock` collection prevents several processes from applying
+//! migrations at the same time.
+//!
+//! Migrations may be interrupted part way through (a crash, or a failure in
+//! a later step), so they should be written to be safe to run again.
+
+use crate::db::err::DBError;
+use crate::db::{Database, DatabaseAccess};
+use chrono::{DateTime, Duration, Utc};
+use common::query::{Model, Update};
+use common::security;
+use log::{error, info, warn};
+use rocket::fairing::AdHoc;
+use rocket::futures::future::{self, BoxFuture, Either};
+use rocket::tokio::time;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+mod v001_unset_null_auth_tokens;
+mod v002_user_indexes;
+mod v003_audit_event_indexes;
+mod v004_native_datetimes;
+
+const MIGRATIONS: &str = "migrations";
+const MIGRATION_LOCK: &str = "migration_lock";
+const LOCK_ID: &str = "lock";
+
+/// How long a lock is honoured for before it is considered abandoned
+const LOCK_TIMEOUT_MINUTES: i64 = 10;
+
+/// How often a runner renews its lock while migrations are applied, well
+/// within `LOCK_TIMEOUT_MINUTES` so long migrations keep the lock
+const LOCK_RENEW_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Returns a fairing which applies pending migrations when the rocket is
+/// ignited, if `auto_migrate` is enabled in the Rocket config. Launch is
+/// aborted if a migration fails
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Migrations", |rocket| async {
+        let enabled = rocket
+            .figment()
+            .extract_inner::<bool>("auto_migrate")
+            .unwrap_or(false);
+        if !enabled {
+            return Ok(rocket);
+        }
+
+        let result = {
+            let db = rocket
+                .state::<Database>()
+                .expect("No managed db connection");
+            Migrator::new(db).up().await
+        };
+
+        match result {
+            Ok(applied) => {
+                info!(target: "Migrations", "Applied migrations: {:?}", applied);
+                Ok(rocket)
+            }
+            Err(e) => {
+                error!(target: "Migrations", "Failed to apply migrations: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+/// Applies a migration. Migrations are async, so they return a boxed future
+/// which borrows the database
+pub type MigrationFn = for<'a> fn(&'a Database) -> BoxFuture<'a, Result<(), DBError>>;
+
+/// A single versioned change to the stored data
+pub struct Migration {
+    /// Ordering key, must be unique and increasing
+    pub version: u32,
+    pub name: &'static str,
+    pub up: MigrationFn,
+}
+
+/// Returns every known migration in the order they must be applied
+pub fn all() -> Vec<Migration> {
+    vec![
+        Migration {
+            version: 1,
+            name: "unset_null_auth_tokens",
+            up: v001_unset_null_auth_tokens::up,
+        },
+        Migration {
+            version: 2,
+            name: "user_indexes",
+            up: v002_user_indexes::up,
+        },
+        Migration {
+            version: 3,
+            name: "audit_event_indexes",
+            up: v003_audit_event_indexes::up,
+        },
+        Migration {
+            version: 4,
+            name: "native_datetimes",
+            up: v004_native_datetimes::up,
+        },
+    ]
+}
+
+/// Creates the indexes added by migrations again, in case they were
+/// dropped. Creating an index which already exists does nothing
+///
+/// # Arguments
+///
+/// * `db` - The database to create the indexes in
+pub async fn ensure_indexes(db: &Database) -> Result<(), DBError> {
+    v002_user_indexes::up(db).await?;
+    v003_audit_event_indexes::up(db).await
+}
+
+/// Record of an applied migration as stored in the `migrations` collection
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Model)]
+pub struct MigrationRecord {
+    #[serde(rename = "_id")]
+    pub version: u32,
+    pub name: String,
+    #[serde(with = "common::datetime")]
+    pub applied: DateTime<Utc>,
+}
+
+/// Lock document preventing concurrent migration runners
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Model)]
+struct MigrationLock {
+    #[serde(rename = "_id")]
+    id: String,
+    owner: String,
+    #[serde(with = "common::datetime")]
+    acquired: DateTime<Utc>,
+}
+
+/// Whether a known migration has been applied
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MigrationStatus {
+    pub version: u32,
+    pub name: &'static str,
+    pub applied: Option<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    #[error("Migrations are locked by another runner: {0}")]
+    Locked(String),
+
+    #[error("The migration lock was taken over by another runner")]
+    LockLost,
+
+    #[error("Migration {version} ({name}) failed: {source}")]
+    Failed {
+        version: u32,
+        name: &'static str,
+        source: DBError,
+    },
+
+    #[error("An issue occurred with the db: {source}")]
+    DBError {
+        #[from]
+        source: DBError,
+    },
+}
+
+/// Applies migrations to a database
+pub struct Migrator<'a> {
+    db: &'a Database,
+    migrations: Vec<Migration>,
+    owner: String,
+    renew_interval: std::time::Duration,
+}
+
+impl<'a> Migrator<'a> {
+    /// Returns a migrator for every migration registered in `all()`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use api::db::DBClient;
+    /// use api::db::migrations::Migrator;
+    ///
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
+    /// let db = client.get_database("appdb");
+    ///
+    /// let applied = Migrator::new(&db).up().await.unwrap();
+    /// # });
+    /// ```
+    pub fn new(db: &'a Database) -> Self {
+        Self::with_migrations(db, all())
+    }
+
+    /// Returns a migrator for the given migrations
+    pub fn with_migrations(db: &'a Database, mut migrations: Vec<Migration>) -> Self {
+        migrations.sort_by_key(|m| m.version);
+
+        Migrator {
+            db,
+            migrations,
+            owner: format!(
+                "{}-{}",
+                std::process::id(),
+                security::generate_auth_token(16)
+            ),
+            renew_interval: LOCK_RENEW_INTERVAL,
+        }
+    }
+
+    /// Sets how often the lock is renewed while migrations are applied
+    pub fn with_lock_renewal(mut self, interval: std::time::Duration) -> Self {
+        self.renew_interval = interval;
+        self
+    }
+
+    /// Returns the status of every known migration
+    pub async fn status(&self) -> Result<Vec<MigrationStatus>, MigrationError> {
+        let fields = MigrationRecord::fields();
+        let mut status = Vec::with_capacity(self.migrations.len());
+
+        for m in &self.migrations {
+            let record = self
+                .db
+                .find_one::<MigrationRecord>(MIGRATIONS, &fields.version.eq(m.version))
+                .await?;
+
+            status.push(MigrationStatus {
+                version: m.version,
+                name: m.name,
+                applied: record.map(|r| r.applied.to_rfc3339()),
+            });
+        }
+
+        Ok(status)
+    }
+
+    /// Applies every pending migration in order, returning the versions
+    /// which were applied
+    pub async fn up(&self) -> Result<Vec<u32>, MigrationError> {
+        self.acquire_lock().await?;
+
+        // Migrations stop if the lock is lost, as another runner may be
+        // applying them too
+        let apply = Box::pin(self.apply_pending());
+        let result = match future::select(apply, Box::pin(self.renew_lock())).await {
+            Either::Left((result, _)) => result,
+            Either::Right((e, _)) => Err(e),
+        };
+
+        if let Err(e) = self.release_lock().await {
+            warn!(target: "Migrations", "Failed to release migration lock: {}", e);
+        }
+
+        result
+    }
+
+    async fn apply_pending(&self) -> Result<Vec<u32>, MigrationError> {
+        let fields = MigrationRecord::fields();
+        let mut applied = Vec::new();
+
+        for m in &self.migrations {
+            let query = fields.version.eq(m.version);
+            if self
+                .db
+                .find_one::<MigrationRecord>(MIGRATIONS, &query)
+                .await?
+                .is_some()
+            {
+                continue;
+            }
+
+            info!(target: "Migrations", "Applying migration {} ({})", m.version, m.name);
+
+            (m.up)(self.db)
+                .await
+                .map_err(|source| MigrationError::Failed {
+                    version: m.version,
+                    name: m.name,
+                    source,
+                })?;
+
+            let record = MigrationRecord {
+                version: m.version,
+                name: m.name.into(),
+                applied: Utc::now(),
+            };
+            self.db.insert_one(MIGRATIONS, &record).await?;
+
+            applied.push(m.version);
+        }
+
+        Ok(applied)
+    }
+
+    async fn acquire_lock(&self) -> Result<(), MigrationError> {
+        let fields = MigrationLock::fields();
+        let lock = MigrationLock {
+            id: LOCK_ID.into(),
+            owner: self.owner.clone(),
+            acquired: Utc::now(),
+        };
+
+        match self.db.insert_one(MIGRATION_LOCK, &lock).await {
+            Ok(_) => return Ok(()),
+            Err(e) if e.is_duplicate_key() => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        // The lock is held, so take it over only if it has been abandoned
+        let cutoff = Utc::now() - Duration::minutes(LOCK_TIMEOUT_MINUTES);
+        let stale = fields.id.eq(LOCK_ID).and(fields.acquired.lt(cutoff));
+
+        if self.db.delete_one(MIGRATION_LOCK, &stale).await? == 1 {
+            warn!(target: "Migrations", "Removed an abandoned migration lock");
+            match self.db.insert_one(MIGRATION_LOCK, &lock).await {
+                Ok(_) => return Ok(()),
+                Err(e) if !e.is_duplicate_key() => return Err(e.into()),
+                Err(_) => {}
+            }
+        }
+
+        let holder = self
+            .db
+            .find_one::<MigrationLock>(MIGRATION_LOCK, &fields.id.eq(LOCK_ID))
+            .await?
+            .map(|l| l.owner)
+            .unwrap_or_default();
+
+        Err(MigrationError::Locked(holder))
+    }
+
+    /// Keeps the lock from looking abandoned by refreshing its acquired
+    /// time. Only returns once the lock has been lost
+    async fn renew_lock(&self) -> MigrationError {
+        let fields = MigrationLock::fields();
+        let query = fields
+            .id
+            .eq(LOCK_ID)
+            .and(fields.owner.eq(self.owner.clone()));
+
+        loop {
+            time::sleep(self.renew_interval).await;
+
+            let update = Update::new().set(fields.acquired, Utc::now());
+            match self
+                .db
+                .find_one_and_update::<MigrationLock>(MIGRATION_LOCK, &query, &update)
+                .await
+            {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    error!(target: "Migrations", "Lost the migration lock");
+                    return MigrationError::LockLost;
+                }
+                // The lock is only lost once it times out, so keep trying
+                Err(e) => warn!(target: "Migrations", "Failed to renew migration lock: {}", e),
+            }
+        }
+    }
+
+    async fn release_lock(&self) -> Result<(), DBError> {
+        let fields = MigrationLock::fields();
+        let query = fields
+            .id
+            .eq(LOCK_ID)
+            .and(fields.owner.eq(self.owner.clone()));
+
+        self.db.delete_one(MIGRATION_LOCK, &query).await?;
+        Ok(())
+    }
+}
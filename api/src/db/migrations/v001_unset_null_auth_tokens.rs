@@ -0,0 +1,22 @@
+//! `User::auth_token` became optional and is omitted when unset. Documents
+//! written before that change may store an explicit `null` instead, which
+//! would match lookups for a missing token.
+
+use crate::db::err::DBError;
+use crate::db::{Database, DatabaseAccess, WriteOperation};
+use rocket::futures::future::BoxFuture;
+use serde_json::json;
+
+pub fn up(db: &Database) -> BoxFuture<'_, Result<(), DBError>> {
+    Box::pin(async move {
+        let query = json! {{ "auth_token": { "$type": "null" } }};
+        let update = json! {{ "$unset": { "auth_token": "" } }};
+        let operations = vec![WriteOperation::UpdateMany {
+            query: &query,
+            update: &update,
+        }];
+
+        db.bulk_write("users", &operations, true).await?;
+        Ok(())
+    })
+}
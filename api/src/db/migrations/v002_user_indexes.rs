@@ -0,0 +1,30 @@
+//! Adds the indexes the user lookups rely on. Usernames are made unique so
+//! concurrent signups cannot create duplicate accounts.
+
+use crate::db::err::DBError;
+use crate::db::Database;
+use mongodb::bson::{doc, Document};
+use mongodb::options::IndexOptions;
+use mongodb::IndexModel;
+use rocket::futures::future::BoxFuture;
+
+pub fn up(db: &Database) -> BoxFuture<'_, Result<(), DBError>> {
+    Box::pin(async move {
+        let users = db.to_inner().collection::<Document>("users");
+
+        let username = IndexModel::builder()
+            .keys(doc! { "username": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+
+        let auth_token = IndexModel::builder()
+            .keys(doc! { "auth_token": 1 })
+            .options(IndexOptions::builder().sparse(true).build())
+            .build();
+
+        users
+            .create_indexes(vec![username, auth_token], None)
+            .await?;
+        Ok(())
+    })
+}
@@ -0,0 +1,26 @@
+//! Adds the indexes used to page through the audit log, both per account
+//! and across every account.
+
+use crate::audit::AUDIT_EVENTS;
+use crate::db::err::DBError;
+use crate::db::Database;
+use mongodb::bson::{doc, Document};
+use mongodb::IndexModel;
+use rocket::futures::future::BoxFuture;
+
+pub fn up(db: &Database) -> BoxFuture<'_, Result<(), DBError>> {
+    Box::pin(async move {
+        let events = db.to_inner().collection::<Document>(AUDIT_EVENTS);
+
+        let by_user = IndexModel::builder()
+            .keys(doc! { "user_id": 1, "timestamp": -1, "_id": -1 })
+            .build();
+
+        let by_time = IndexModel::builder()
+            .keys(doc! { "timestamp": -1, "_id": -1 })
+            .build();
+
+        events.create_indexes(vec![by_user, by_time], None).await?;
+        Ok(())
+    })
+}
@@ -0,0 +1,81 @@
+//! Dates used to be stored as `%Y-%m-%d %H:%M:%S` strings, which can't be
+//! range-queried or TTL-indexed and drop sub-second precision. They are now
+//! written as native BSON datetimes, and this converts the ones stored
+//! before, along with the RFC 3339 strings and epoch milliseconds the
+//! models also read. The migration fails if a date is left in any other
+//! form, so it can be fixed and the migration run again.
+
+use crate::audit::AUDIT_EVENTS;
+use crate::db::err::DBError;
+use crate::db::Database;
+use common::datetime::LEGACY_FORMAT;
+use mongodb::bson::{doc, Document};
+use rocket::futures::future::BoxFuture;
+
+/// The date fields of every collection
+const DATES: &[(&str, &[&str])] = &[
+    ("users", &["last_login", "created", "updated"]),
+    (AUDIT_EVENTS, &["timestamp"]),
+    ("migrations", &["applied"]),
+];
+
+/// Returns the expression converting `value` to a date. Unparseable strings
+/// are left as they are
+fn to_date(value: &str) -> Document {
+    let rfc3339 = doc! {
+        "$dateFromString": {
+            "dateString": value,
+            "onError": value,
+        }
+    };
+
+    doc! {
+        "$switch": {
+            "branches": [
+                {
+                    "case": { "$eq": [{ "$type": value }, "string"] },
+                    "then": {
+                        "$dateFromString": {
+                            "dateString": value,
+                            "format": LEGACY_FORMAT,
+                            "timezone": "UTC",
+                            "onError": rfc3339,
+                        }
+                    },
+                },
+                {
+                    "case": { "$in": [{ "$type": value }, ["int", "long", "double", "decimal"]] },
+                    "then": { "$toDate": value },
+                },
+            ],
+            "default": value,
+        }
+    }
+}
+
+pub fn up(db: &Database) -> BoxFuture<'_, Result<(), DBError>> {
+    Box::pin(async move {
+        for (name, fields) in DATES {
+            let collection = db.to_inner().collection::<Document>(name);
+
+            for field in fields.iter().copied() {
+                let unconverted = doc! { field: { "$type": ["string", "number"] } };
+                let convert = doc! { "$set": { field: to_date(&format!("${}", field)) } };
+
+                collection
+                    .update_many(unconverted.clone(), vec![convert], None)
+                    .await?;
+
+                let left = collection.count_documents(unconverted, None).await?;
+                if left > 0 {
+                    return Err(DBError::InvalidData(format!(
+                        "{} documents of {} hold a {} which is not a date",
+                        left, name, field
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    })
+}
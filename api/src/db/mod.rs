@@ -1,6 +1,16 @@
 //! This module contains everything needed for database access
 
+mod bulk;
 mod database;
 pub mod err;
+pub mod migrations;
+pub mod resilience;
+mod transaction;
 
-pub use database::{DBClient, Database, DatabaseAccess};
+pub use bulk::{BulkWriteFailure, BulkWriteResult, WriteOperation};
+pub use database::{
+    DBClient, Database, DatabaseAccess, IntoDocument, UPDATED_FIELD, VERSIONED_COLLECTIONS,
+    VERSION_FIELD,
+};
+pub use resilience::ResilienceOptions;
+pub use transaction::Transaction;
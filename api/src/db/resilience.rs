@@ -0,0 +1,283 @@
+//! This module contains the policies which keep the server usable while
+//! MongoDB is slow or unreachable: a connectivity check at startup, retries
+//! of idempotent reads after transient errors, and a circuit breaker which
+//! fails requests fast once the database has been failing for a while
+//!
+//! All settings can be changed through the Rocket config (`Rocket.toml` or
+//! `ROCKET_*` environment variables), for example `db_read_retries = 3`.
+
+use log::{error, info, warn};
+use mongodb::bson::doc;
+use mongodb::error::ErrorKind;
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
+use rocket::tokio::time;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::err::DBError;
+use super::Database;
+
+/// Tunable settings for connecting to and retrying against the database
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResilienceOptions {
+    /// How many times the startup connectivity check is retried
+    pub connect_retries: u32,
+    /// Delay before the first connectivity retry, doubled on every attempt
+    pub connect_backoff: Duration,
+    /// How many times an idempotent read is retried after a transient error
+    pub read_retries: u32,
+    /// Delay before the first read retry, doubled on every attempt
+    pub read_backoff: Duration,
+    /// Consecutive connectivity failures after which the breaker opens
+    pub breaker_threshold: u32,
+    /// How long the breaker stays open before letting a request through
+    pub breaker_cooldown: Duration,
+}
+
+impl Default for ResilienceOptions {
+    fn default() -> Self {
+        ResilienceOptions {
+            connect_retries: 5,
+            connect_backoff: Duration::from_millis(500),
+            read_retries: 2,
+            read_backoff: Duration::from_millis(100),
+            breaker_threshold: 5,
+            breaker_cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ResilienceOptions {
+    /// Reads the options from the Rocket config, falling back to the
+    /// defaults for anything missing
+    pub fn from_figment(figment: &Figment) -> Self {
+        let defaults = ResilienceOptions::default();
+        let count = |name: &str, default: u32| {
+            figment
+                .extract_inner::<i64>(name)
+                .map(|v| v.max(0) as u32)
+                .unwrap_or(default)
+        };
+        let millis = |name: &str, default: Duration| {
+            figment
+                .extract_inner::<i64>(name)
+                .map(|v| Duration::from_millis(v.max(0) as u64))
+                .unwrap_or(default)
+        };
+
+        ResilienceOptions {
+            connect_retries: count("db_connect_retries", defaults.connect_retries),
+            connect_backoff: millis("db_connect_backoff_ms", defaults.connect_backoff),
+            read_retries: count("db_read_retries", defaults.read_retries),
+            read_backoff: millis("db_read_backoff_ms", defaults.read_backoff),
+            breaker_threshold: count("db_breaker_threshold", defaults.breaker_threshold),
+            breaker_cooldown: millis("db_breaker_cooldown_ms", defaults.breaker_cooldown),
+        }
+    }
+}
+
+/// Returns a fairing which checks that the database is reachable when the
+/// rocket is ignited, retrying with backoff. Launch is aborted if it never
+/// responds
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Database connectivity", |rocket| async {
+        let result = rocket
+            .state::<Database>()
+            .expect("No managed db connection")
+            .wait_until_available()
+            .await;
+
+        match result {
+            Ok(()) => Ok(rocket),
+            Err(e) => {
+                error!(target: "Database", "Database is unreachable: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+/// Returns `base` doubled `attempt` times, capped to avoid overflowing
+fn backoff(base: Duration, attempt: u32) -> Duration {
+    base * 2u32.pow(attempt.min(10))
+}
+
+/// Returns true if `e` means the database could not be reached, as opposed
+/// to the database rejecting the operation. Rejections by the open circuit
+/// breaker are not, so they are neither retried nor counted as failures
+pub(super) fn is_connectivity_error(e: &DBError) -> bool {
+    match e {
+        DBError::MongoError { source, .. } => matches!(
+            source.kind.as_ref(),
+            ErrorKind::Io(_)
+                | ErrorKind::ConnectionPoolCleared { .. }
+                | ErrorKind::ServerSelection { .. }
+        ),
+        _ => false,
+    }
+}
+
+#[derive(Default)]
+struct BreakerState {
+    failures: u32,
+    opened_at: Option<Instant>,
+}
+
+/// Applies the retry and circuit breaker policies to database operations
+pub(super) struct Resilience {
+    options: ResilienceOptions,
+    breaker: Mutex<BreakerState>,
+}
+
+impl Resilience {
+    pub(super) fn new(options: ResilienceOptions) -> Self {
+        Resilience {
+            options,
+            breaker: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Runs an operation which must not be repeated
+    pub(super) async fn call<T>(
+        &self,
+        op: impl Future<Output = Result<T, DBError>>,
+    ) -> Result<T, DBError> {
+        self.admit()?;
+
+        let result = op.await;
+        self.record(&result);
+
+        result
+    }
+
+    /// Runs an idempotent read, retrying it after transient errors. `op`
+    /// is called again to start every retry
+    pub(super) async fn read<T, F>(&self, mut op: impl FnMut() -> F) -> Result<T, DBError>
+    where
+        F: Future<Output = Result<T, DBError>>,
+    {
+        let mut attempt = 0;
+
+        loop {
+            match self.call(op()).await {
+                Err(e) if attempt < self.options.read_retries && is_connectivity_error(&e) => {
+                    let delay = backoff(self.options.read_backoff, attempt);
+                    attempt += 1;
+                    warn!(
+                        target: "Database",
+                        "Retrying read in {:?} (attempt {}) after {}", delay, attempt, e
+                    );
+                    time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Returns how long until the breaker lets requests through again, or
+    /// `None` if it is closed
+    pub(super) fn retry_after(&self) -> Option<Duration> {
+        let breaker = self.breaker.lock().expect("Poisoned circuit breaker");
+
+        breaker
+            .opened_at
+            .map(|opened| self.options.breaker_cooldown.checked_sub(opened.elapsed()))
+            .map(|remaining| remaining.unwrap_or_default())
+    }
+
+    /// Fails fast while the breaker is open. Once the cooldown has passed a
+    /// single request is let through to probe the database, and the
+    /// cooldown restarts for everyone else
+    fn admit(&self) -> Result<(), DBError> {
+        let mut breaker = self.breaker.lock().expect("Poisoned circuit breaker");
+
+        if let Some(opened) = breaker.opened_at {
+            let elapsed = opened.elapsed();
+            if elapsed < self.options.breaker_cooldown {
+                return Err(DBError::Unavailable {
+                    retry_after: self.options.breaker_cooldown - elapsed,
+                });
+            }
+            breaker.opened_at = Some(Instant::now());
+        }
+
+        Ok(())
+    }
+
+    fn record<T>(&self, result: &Result<T, DBError>) {
+        let mut breaker = self.breaker.lock().expect("Poisoned circuit breaker");
+
+        match result {
+            Err(e) if is_connectivity_error(e) => {
+                breaker.failures += 1;
+                if breaker.failures >= self.options.breaker_threshold {
+                    if breaker.opened_at.is_none() {
+                        error!(
+                            target: "Database",
+                            "Opening circuit breaker after {} failures", breaker.failures
+                        );
+                    }
+                    breaker.opened_at = Some(Instant::now());
+                }
+            }
+            // Raised by a breaker rather than the database, so it says
+            // nothing about whether the database is back
+            Err(DBError::Unavailable { .. }) => {}
+            _ => {
+                if breaker.opened_at.is_some() {
+                    info!(target: "Database", "Closing circuit breaker");
+                }
+                *breaker = BreakerState::default();
+            }
+        }
+    }
+}
+
+impl Database {
+    /// Pings the database until it responds, retrying with exponential
+    /// backoff up to the configured number of times
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use api::db::DBClient;
+    ///
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
+    /// let db = client.get_database("appdb");
+    ///
+    /// db.wait_until_available().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn wait_until_available(&self) -> Result<(), DBError> {
+        let options = &self.resilience().options;
+        let mut attempt = 0;
+
+        loop {
+            match self.to_inner().run_command(doc! { "ping": 1 }, None).await {
+                Ok(_) => {
+                    info!(target: "Database", "Connected to database");
+                    return Ok(());
+                }
+                Err(e) if attempt < options.connect_retries => {
+                    let delay = backoff(options.connect_backoff, attempt);
+                    attempt += 1;
+                    warn!(
+                        target: "Database",
+                        "Database unreachable, retrying in {:?} (attempt {}): {}", delay, attempt, e
+                    );
+                    time::sleep(delay).await;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Returns how long until the circuit breaker lets requests through
+    /// again, or `None` if the database is considered available
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.resilience().retry_after()
+    }
+}
@@ -0,0 +1,611 @@
+//! This module contains support for running several database operations
+//! atomically inside a MongoDB multi-document transaction
+//!
+//! Transactions require a replica set or sharded cluster. When connected to
+//! a standalone server (such as the one in `docker-compose.yml`) the
+//! operations are executed directly instead, so code written against
+//! `Database::transaction` keeps working without transaction support.
+
+use crate::telemetry;
+use log::{error, info, warn};
+use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::error::{TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT};
+use mongodb::options::{
+    FindOneAndUpdateOptions, FindOptions, ReturnDocument, UpdateModifications, UpdateOptions,
+};
+use mongodb::results::{DeleteResult, UpdateResult};
+use mongodb::{ClientSession, Collection};
+use opentelemetry::KeyValue;
+use rocket::async_trait;
+use rocket::futures::future::BoxFuture;
+use rocket::futures::TryStreamExt;
+use rocket::tokio::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::bulk::{self, BulkWriteResult, WriteOperation};
+use super::database::{to_document, to_insert_document, to_stored_document, to_update_document};
+use super::err::DBError;
+use super::{Database, DatabaseAccess, IntoDocument};
+
+/// How long a transaction is retried for after transient errors
+const RETRY_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A handle to an in-progress transaction. Operations performed through
+/// its `DatabaseAccess` implementation are committed or aborted together,
+/// and are traced and measured like those made through `Database`
+pub struct Transaction<'a> {
+    db: &'a Database,
+    session: Option<Mutex<ClientSession>>,
+}
+
+impl Database {
+    /// Runs `f` inside a transaction, committing if it returns `Ok` and
+    /// aborting if it returns `Err`. The whole transaction is retried when
+    /// MongoDB reports a transient error, so `f` may be called more than
+    /// once. Without transaction support the operations run directly
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Operations to run inside the transaction
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use api::db::{DBClient, DatabaseAccess};
+    /// use serde_json::json;
+    ///
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
+    /// let db = client.get_database("appdb");
+    ///
+    /// db.transaction(|tx| {
+    ///     Box::pin(async move {
+    ///         tx.delete_many("sessions", &json! {{ "username": "Foo" }}).await?;
+    ///         tx.delete_one("people", &json! {{ "name": "Foo" }}).await
+    ///     })
+    /// })
+    /// .await
+    /// .unwrap();
+    /// # });
+    /// ```
+    pub async fn transaction<'a, R, F>(&'a self, mut f: F) -> Result<R, DBError>
+    where
+        F: for<'t> FnMut(&'t Transaction<'a>) -> BoxFuture<'t, Result<R, DBError>>,
+    {
+        let attributes = vec![KeyValue::new("db.system", "mongodb")];
+        let result = telemetry::in_span("transaction".into(), attributes, async {
+            let result = self.resilience().call(self.run_transaction(&mut f)).await;
+            telemetry::record_result(&result);
+            result
+        })
+        .await;
+
+        if let Err(e) = &result {
+            error!(target: "Database", "Error running transaction: {}", e)
+        };
+
+        result
+    }
+
+    async fn run_transaction<'a, R, F>(&'a self, f: &mut F) -> Result<R, DBError>
+    where
+        F: for<'t> FnMut(&'t Transaction<'a>) -> BoxFuture<'t, Result<R, DBError>>,
+    {
+        if !self.supports_transactions().await? {
+            return f(&Transaction {
+                db: self,
+                session: None,
+            })
+            .await;
+        }
+
+        let tx = Transaction {
+            db: self,
+            session: Some(Mutex::new(self.client().start_session(None).await?)),
+        };
+        let session = tx.session.as_ref().expect("Transaction without a session");
+        let start = Instant::now();
+
+        'transaction: loop {
+            session.lock().await.start_transaction(None).await?;
+
+            let value = match f(&tx).await {
+                Ok(value) => value,
+                Err(e) => {
+                    // The server may already have aborted the transaction
+                    let _ = session.lock().await.abort_transaction().await;
+
+                    if has_label(&e, TRANSIENT_TRANSACTION_ERROR) && start.elapsed() < RETRY_TIMEOUT
+                    {
+                        warn!(target: "Database", "Retrying transaction after {}", e);
+                        continue 'transaction;
+                    }
+                    return Err(e);
+                }
+            };
+
+            loop {
+                let e = match session.lock().await.commit_transaction().await {
+                    Ok(()) => return Ok(value),
+                    Err(e) => e,
+                };
+
+                if start.elapsed() >= RETRY_TIMEOUT {
+                    return Err(e.into());
+                }
+
+                if e.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) {
+                    warn!(target: "Database", "Retrying commit after {}", e);
+                } else if e.contains_label(TRANSIENT_TRANSACTION_ERROR) {
+                    warn!(target: "Database", "Retrying transaction after {}", e);
+                    continue 'transaction;
+                } else {
+                    return Err(e.into());
+                }
+            }
+        }
+    }
+
+    /// Returns whether the connected deployment is a replica set or sharded
+    /// cluster, and so supports transactions. The answer is cached after
+    /// the first successful check
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use api::db::DBClient;
+    ///
+    /// # rocket::execute(async {
+    /// let client = DBClient::init("mongodb://localhost:27017/").await;
+    /// let db = client.get_database("appdb");
+    ///
+    /// let atomic = db.supports_transactions().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn supports_transactions(&self) -> Result<bool, DBError> {
+        let cached = *self
+            .transaction_support()
+            .lock()
+            .expect("Poisoned transaction support lock");
+
+        if let Some(supported) = cached {
+            return Ok(supported);
+        }
+
+        let reply = self
+            .to_inner()
+            .run_command(doc! { "isMaster": 1 }, None)
+            .await?;
+        let supported =
+            reply.contains_key("setName") || reply.get_str("msg").ok() == Some("isdbgrid");
+
+        if !supported {
+            info!(
+                target: "Database",
+                "Deployment does not support transactions, running them without a session"
+            );
+        }
+
+        *self
+            .transaction_support()
+            .lock()
+            .expect("Poisoned transaction support lock") = Some(supported);
+        Ok(supported)
+    }
+}
+
+/// Returns true if `e` originated from MongoDB with the given error label
+fn has_label(e: &DBError, label: &str) -> bool {
+    match e {
+        DBError::MongoError { source, .. } => source.contains_label(label),
+        _ => false,
+    }
+}
+
+/// Logs the error of a failed operation inside a transaction
+fn logged<T>(result: Result<T, DBError>, action: &str) -> Result<T, DBError> {
+    if let Err(e) = &result {
+        error!(target: "Database", "Error {} in transaction: {}", action, e)
+    };
+
+    result
+}
+
+impl<'a> Transaction<'a> {
+    fn collection(&self, name: &str) -> Collection<Document> {
+        self.db.to_inner().collection::<Document>(name)
+    }
+
+    async fn run_command(&self, command: Document) -> Result<Document, DBError> {
+        let db = self.db.to_inner();
+
+        Ok(match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                db.run_command_with_session(command, None, &mut session)
+                    .await?
+            }
+            None => db.run_command(command, None).await?,
+        })
+    }
+
+    async fn find_document(
+        &self,
+        collection: &str,
+        query: Document,
+    ) -> Result<Option<Document>, DBError> {
+        let collection = self.collection(collection);
+
+        Ok(match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                collection
+                    .find_one_with_session(query, None, &mut session)
+                    .await?
+            }
+            None => collection.find_one(query, None).await?,
+        })
+    }
+
+    async fn find_documents(
+        &self,
+        collection: &str,
+        query: Document,
+        options: FindOptions,
+    ) -> Result<Vec<Document>, DBError> {
+        let collection = self.collection(collection);
+
+        Ok(match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                let mut cursor = collection
+                    .find_with_session(query, options, &mut session)
+                    .await?;
+                let docs = cursor.stream(&mut session).try_collect().await;
+                docs?
+            }
+            None => collection.find(query, options).await?.try_collect().await?,
+        })
+    }
+
+    async fn insert_document(&self, collection: &str, doc: Document) -> Result<Bson, DBError> {
+        let collection = self.collection(collection);
+
+        let result = match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                collection
+                    .insert_one_with_session(doc, None, &mut session)
+                    .await?
+            }
+            None => collection.insert_one(doc, None).await?,
+        };
+
+        Ok(result.inserted_id)
+    }
+
+    async fn update_documents(
+        &self,
+        collection: &str,
+        query: Document,
+        update: UpdateModifications,
+        many: bool,
+        upsert: bool,
+    ) -> Result<UpdateResult, DBError> {
+        let collection = self.collection(collection);
+        let options = UpdateOptions::builder().upsert(upsert).build();
+
+        Ok(match (&self.session, many) {
+            (Some(session), false) => {
+                let mut session = session.lock().await;
+                collection
+                    .update_one_with_session(query, update, options, &mut session)
+                    .await?
+            }
+            (Some(session), true) => {
+                let mut session = session.lock().await;
+                collection
+                    .update_many_with_session(query, update, options, &mut session)
+                    .await?
+            }
+            (None, false) => collection.update_one(query, update, options).await?,
+            (None, true) => collection.update_many(query, update, options).await?,
+        })
+    }
+
+    async fn delete_documents(
+        &self,
+        collection: &str,
+        query: Document,
+        many: bool,
+    ) -> Result<DeleteResult, DBError> {
+        let collection = self.collection(collection);
+
+        Ok(match (&self.session, many) {
+            (Some(session), false) => {
+                let mut session = session.lock().await;
+                collection
+                    .delete_one_with_session(query, None, &mut session)
+                    .await?
+            }
+            (Some(session), true) => {
+                let mut session = session.lock().await;
+                collection
+                    .delete_many_with_session(query, None, &mut session)
+                    .await?
+            }
+            (None, false) => collection.delete_one(query, None).await?,
+            (None, true) => collection.delete_many(query, None).await?,
+        })
+    }
+
+    async fn _find_one<T>(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+    ) -> Result<Option<T>, DBError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let doc = self.find_document(collection, to_document(query)?).await?;
+        Ok(doc.map(bson::from_document).transpose()?)
+    }
+
+    async fn _find<T>(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        sort: Option<&dyn IntoDocument>,
+        limit: Option<i64>,
+    ) -> Result<Vec<T>, DBError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let sort = sort.map(to_document).transpose()?;
+        let options = FindOptions::builder().sort(sort).limit(limit).build();
+
+        self.find_documents(collection, to_document(query)?, options)
+            .await?
+            .into_iter()
+            .map(|doc| Ok(bson::from_document(doc)?))
+            .collect()
+    }
+
+    async fn _find_one_and_update<T>(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        update: &dyn IntoDocument,
+    ) -> Result<Option<T>, DBError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let (query, update) = (to_document(query)?, to_update_document(collection, update)?);
+        let collection = self.collection(collection);
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let doc = match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                collection
+                    .find_one_and_update_with_session(query, update, options, &mut session)
+                    .await?
+            }
+            None => {
+                collection
+                    .find_one_and_update(query, update, options)
+                    .await?
+            }
+        };
+
+        Ok(doc.map(bson::from_document).transpose()?)
+    }
+
+    async fn _insert_one<T>(&self, collection: &str, item: &T) -> Result<T, DBError>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned,
+    {
+        let mut doc = to_insert_document(collection, to_stored_document(item)?);
+
+        let id = self.insert_document(collection, doc.clone()).await?;
+        doc.insert("_id", id);
+        Ok(bson::from_document(doc)?)
+    }
+
+    async fn _update(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        update: &dyn IntoDocument,
+        upsert: bool,
+    ) -> Result<(), DBError> {
+        let (query, update) = (to_document(query)?, to_update_document(collection, update)?);
+
+        self.update_documents(collection, query, update, false, upsert)
+            .await
+            .map(|_| ())
+    }
+
+    async fn _delete(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        many: bool,
+    ) -> Result<u64, DBError> {
+        self.delete_documents(collection, to_document(query)?, many)
+            .await
+            .map(|result| result.deleted_count)
+    }
+
+    async fn _bulk_write(
+        &self,
+        collection: &str,
+        operations: &[WriteOperation<'_>],
+        ordered: bool,
+    ) -> Result<BulkWriteResult, DBError> {
+        bulk::bulk_write(collection, operations, ordered, |command| {
+            self.run_command(command)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl<'a> DatabaseAccess for Transaction<'a> {
+    async fn find_one<T>(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+    ) -> Result<Option<T>, DBError>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync,
+    {
+        let result = self
+            .db
+            .instrumented(collection, "find_one", self._find_one(collection, query))
+            .await;
+
+        logged(result, "fetching from db")
+    }
+
+    async fn find<T>(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        sort: Option<&dyn IntoDocument>,
+        limit: Option<i64>,
+    ) -> Result<Vec<T>, DBError>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync,
+    {
+        let result = self
+            .db
+            .instrumented(
+                collection,
+                "find",
+                self._find(collection, query, sort, limit),
+            )
+            .await;
+
+        logged(result, "fetching from db")
+    }
+
+    async fn insert_one<T>(&self, collection: &str, item: &T) -> Result<T, DBError>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync,
+    {
+        let result = self
+            .db
+            .instrumented(collection, "insert_one", self._insert_one(collection, item))
+            .await;
+
+        logged(result, "inserting to db")
+    }
+
+    async fn update_one(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        update: &dyn IntoDocument,
+    ) -> Result<(), DBError> {
+        let result = self
+            .db
+            .instrumented(
+                collection,
+                "update_one",
+                self._update(collection, query, update, false),
+            )
+            .await;
+
+        logged(result, "updating db")
+    }
+
+    async fn find_one_and_update<T>(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        update: &dyn IntoDocument,
+    ) -> Result<Option<T>, DBError>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync,
+    {
+        let result = self
+            .db
+            .instrumented(
+                collection,
+                "find_one_and_update",
+                self._find_one_and_update(collection, query, update),
+            )
+            .await;
+
+        logged(result, "updating db")
+    }
+
+    async fn upsert(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+        update: &dyn IntoDocument,
+    ) -> Result<(), DBError> {
+        let result = self
+            .db
+            .instrumented(
+                collection,
+                "upsert",
+                self._update(collection, query, update, true),
+            )
+            .await;
+
+        logged(result, "upserting to db")
+    }
+
+    async fn delete_one(&self, collection: &str, query: &dyn IntoDocument) -> Result<u64, DBError> {
+        let result = self
+            .db
+            .instrumented(
+                collection,
+                "delete_one",
+                self._delete(collection, query, false),
+            )
+            .await;
+
+        logged(result, "deleting from db")
+    }
+
+    async fn delete_many(
+        &self,
+        collection: &str,
+        query: &dyn IntoDocument,
+    ) -> Result<u64, DBError> {
+        let result = self
+            .db
+            .instrumented(
+                collection,
+                "delete_many",
+                self._delete(collection, query, true),
+            )
+            .await;
+
+        logged(result, "deleting from db")
+    }
+
+    async fn bulk_write(
+        &self,
+        collection: &str,
+        operations: &[WriteOperation<'_>],
+        ordered: bool,
+    ) -> Result<BulkWriteResult, DBError> {
+        let result = self
+            .db
+            .instrumented(
+                collection,
+                "bulk_write",
+                self._bulk_write(collection, operations, ordered),
+            )
+            .await;
+
+        logged(result, "writing to db")
+    }
+}
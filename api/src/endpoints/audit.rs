@@ -0,0 +1,181 @@
+//! This module contains the endpoints for reading the security audit log
+
+use crate::audit::{self, AuditContext, AUDIT_EVENTS};
+use crate::auth::admin_auth::AdminAuth;
+use crate::auth::token_auth::TokenAuth;
+use crate::db::{Database, DatabaseAccess};
+use crate::error::ApiError;
+use common::audit::{AuditEvent, AuditEventBrief, AuditEventKind};
+use common::query::{Filter, Model};
+use mongodb::bson::oid::ObjectId;
+use rocket::get;
+use rocket::serde::json::Json;
+use rocket::State;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+/// Fetches a page of events matching `query`, newest first. `before` is the
+/// id of the last event of the previous page; the page continues after it
+/// in the same `timestamp`, `_id` order, so events sharing a timestamp are
+/// neither skipped nor repeated
+async fn find_events(
+    db: &Database,
+    query: Filter<AuditEvent>,
+    limit: Option<i64>,
+    before: Option<String>,
+) -> Result<Vec<AuditEventBrief>, ApiError> {
+    let fields = AuditEvent::fields();
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let query = match before {
+        Some(before) => {
+            let invalid = || ApiError::bad_request("before must be an event id");
+            let before = ObjectId::parse_str(&before).map_err(|_| invalid())?;
+            let last: AuditEvent = db
+                .find_one(AUDIT_EVENTS, &query.clone().and(fields.id.eq(before)))
+                .await?
+                .ok_or_else(invalid)?;
+
+            query.and(
+                fields.timestamp.lt(last.timestamp).or(fields
+                    .timestamp
+                    .eq(last.timestamp)
+                    .and(fields.id.lt(before))),
+            )
+        }
+        None => query,
+    };
+    let sort = fields.timestamp.descending().then(fields.id.descending());
+
+    let events: Vec<AuditEvent> = db
+        .find(AUDIT_EVENTS, &query, Some(&sort), Some(limit))
+        .await?;
+    Ok(events.into_iter().map(AuditEventBrief::from).collect())
+}
+
+/// Fetch the security events of the logged in account, such as logins,
+/// failed logins and password changes, newest first
+///
+/// Example:
+/// `GET /v1/self/security-events?limit=2`
+///
+/// Content-type: application/json
+/// Response code: 200
+/// Response body:
+/// ```json
+/// [
+///   {
+///     "_id": "5fed7d1b00d0e1b4002a6a87",
+///     "kind": "login_succeeded",
+///     "user_id": "5fed7d1900d0e1b4002a6a86",
+///     "username": "Foo",
+///     "reason": null,
+///     "ip": "127.0.0.1",
+///     "user_agent": "curl/7.74.0",
+///     "request_id": "0dWQ5TgyH3k8p6zZ",
+///     "timestamp": "2020-12-31T12:00:00Z"
+///   }
+/// ]
+/// ```
+///
+/// Pass the `_id` of the last event as `before` to fetch the next page
+///
+/// *Datetimes given in UTC
+#[utoipa::path(
+    get,
+    path = "/self/security-events",
+    tag = "audit",
+    security(("auth_token" = [])),
+    params(
+        ("limit" = Option<i64>, Query, description = "Page size, at most 200"),
+        ("before" = Option<String>, Query, description = "Id of the last event of the previous page"),
+    ),
+    responses(
+        (status = 200, description = "Events of the logged in account", body = [AuditEventBrief]),
+        (status = 400, description = "`before` is not an event id", body = ErrorBody),
+        (status = 401, description = "The auth token is missing or invalid", body = ErrorBody),
+    )
+)]
+#[get("/self/security-events?<limit>&<before>")]
+pub async fn security_events_endpoint(
+    db: &State<Database>,
+    token_auth: TokenAuth,
+    limit: Option<i64>,
+    before: Option<String>,
+) -> Result<Json<Vec<AuditEventBrief>>, ApiError> {
+    let user = token_auth.into_inner();
+    let query = AuditEvent::fields().user_id.eq(user.id);
+
+    Ok(Json(find_events(db, query, limit, before).await?))
+}
+
+/// Fetch audit events of every account, optionally filtered by `user_id`
+/// and `kind`, newest first. Only available to admins, and the lookup is
+/// itself audited
+///
+/// Example:
+/// `GET /v1/admin/audit-events?kind=login_failed&limit=20`
+///
+/// Content-type: application/json
+/// Response code: 200
+/// Response body: a list of events, as returned by `GET /self/security-events`
+#[utoipa::path(
+    get,
+    path = "/admin/audit-events",
+    tag = "audit",
+    security(("auth_token" = [])),
+    params(
+        ("user_id" = Option<String>, Query, description = "Only return events of this account"),
+        ("kind" = Option<AuditEventKind>, Query, description = "Only return events of this kind"),
+        ("limit" = Option<i64>, Query, description = "Page size, at most 200"),
+        ("before" = Option<String>, Query, description = "Id of the last event of the previous page"),
+    ),
+    responses(
+        (status = 200, description = "Matching events", body = [AuditEventBrief]),
+        (status = 400, description = "A query parameter is malformed", body = ErrorBody),
+        (status = 401, description = "The auth token is missing or invalid", body = ErrorBody),
+        (status = 403, description = "The account is not an admin", body = ErrorBody),
+    )
+)]
+#[get("/admin/audit-events?<user_id>&<kind>&<limit>&<before>")]
+pub async fn admin_audit_events_endpoint(
+    db: &State<Database>,
+    admin_auth: AdminAuth,
+    audit_context: AuditContext,
+    user_id: Option<String>,
+    kind: Option<String>,
+    limit: Option<i64>,
+    before: Option<String>,
+) -> Result<Json<Vec<AuditEventBrief>>, ApiError> {
+    let admin = admin_auth.into_inner();
+    let fields = AuditEvent::fields();
+    let mut query = Filter::all();
+
+    if let Some(user_id) = &user_id {
+        let user_id = ObjectId::parse_str(user_id)
+            .map_err(|_| ApiError::bad_request("user_id must be an account id"))?;
+        query = query.and(fields.user_id.eq(user_id));
+    }
+    if let Some(kind) = &kind {
+        let kind: AuditEventKind = serde_json::from_value(kind.as_str().into())
+            .map_err(|_| ApiError::bad_request(format!("Unknown event kind {}", kind)))?;
+        query = query.and(fields.kind.eq(kind));
+    }
+
+    let events = find_events(db, query, limit, before).await?;
+
+    let event = AuditEvent {
+        user_id: admin.id,
+        username: Some(admin.username),
+        reason: Some(format!(
+            "viewed_audit_events user_id={} kind={}",
+            user_id.as_deref().unwrap_or("*"),
+            kind.as_deref().unwrap_or("*")
+        )),
+        ..audit_context.event(AuditEventKind::AdminAction)
+    };
+    audit::record(db, event).await;
+
+    Ok(Json(events))
+}
@@ -0,0 +1,166 @@
+//! This module contains the liveness and readiness endpoints used by
+//! container orchestrators. Neither requires authentication
+
+use crate::db::migrations::Migrator;
+use crate::db::{Database, ResilienceOptions};
+use mongodb::bson::doc;
+use rocket::get;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::{json, Value};
+use rocket::State;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Instant;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum CheckStatus {
+    Up,
+    Down,
+}
+
+/// Outcome of a single readiness check
+#[derive(Serialize, Debug)]
+struct Check {
+    status: CheckStatus,
+    latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Check {
+    /// Runs `check`, timing it and recording any error it returns
+    async fn run(check: impl Future<Output = Result<(), String>>) -> Self {
+        let start = Instant::now();
+        let result = check.await;
+        let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        match result {
+            Ok(()) => Check {
+                status: CheckStatus::Up,
+                latency_ms,
+                error: None,
+            },
+            Err(e) => Check {
+                status: CheckStatus::Down,
+                latency_ms,
+                error: Some(e),
+            },
+        }
+    }
+}
+
+/// Reports that the process is up and serving requests. This never touches
+/// the database, so a slow database will not get the container restarted
+///
+/// Example:
+/// `GET /health/live`
+///
+/// Response code: 200
+/// Response body:
+/// ```json
+/// {
+///   "status": "up"
+/// }
+/// ```
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "operations",
+    responses((status = 200, description = "The server is up")),
+)]
+#[get("/live")]
+pub fn live_endpoint() -> Value {
+    json!({ "status": "up" })
+}
+
+/// Reports whether the server is ready to take traffic: the database
+/// answers a ping, every migration has been applied and the config was
+/// loaded. Responds with 503 if any check is down
+///
+/// Example:
+/// `GET /health/ready`
+///
+/// Response code: 200 or 503
+/// Response body:
+/// ```json
+/// {
+///   "status": "up",
+///   "checks": {
+///     "config": { "status": "up", "latency_ms": 0.001 },
+///     "database": { "status": "up", "latency_ms": 0.8 },
+///     "migrations": { "status": "up", "latency_ms": 1.2 }
+///   }
+/// }
+/// ```
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Every check is up"),
+        (status = 503, description = "At least one check is down"),
+    )
+)]
+#[get("/ready")]
+pub async fn ready_endpoint(
+    db: &State<Database>,
+    options: Option<&State<ResilienceOptions>>,
+) -> Custom<Value> {
+    let mut checks = BTreeMap::new();
+
+    checks.insert(
+        "config",
+        Check::run(async {
+            match options {
+                Some(_) => Ok(()),
+                None => Err("Config was not loaded".into()),
+            }
+        })
+        .await,
+    );
+
+    checks.insert(
+        "database",
+        Check::run(async {
+            db.to_inner()
+                .run_command(doc! { "ping": 1 }, None)
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+        .await,
+    );
+
+    checks.insert(
+        "migrations",
+        Check::run(async {
+            let pending: Vec<u32> = Migrator::new(db)
+                .status()
+                .await
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .filter(|m| m.applied.is_none())
+                .map(|m| m.version)
+                .collect();
+
+            if pending.is_empty() {
+                Ok(())
+            } else {
+                Err(format!("Pending migrations: {:?}", pending))
+            }
+        })
+        .await,
+    );
+
+    let ready = checks.values().all(|c| c.status == CheckStatus::Up);
+    let (status, overall) = if ready {
+        (Status::Ok, CheckStatus::Up)
+    } else {
+        (Status::ServiceUnavailable, CheckStatus::Down)
+    };
+
+    Custom(status, json!({ "status": overall, "checks": checks }))
+}
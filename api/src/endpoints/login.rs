@@ -1,20 +1,30 @@
 //! This module contains the endpoints relating to logins
 
+use crate::audit::{self, AuditContext};
+use crate::auth::csrf;
 use crate::auth::login_auth::LoginAuth;
+use crate::auth::session::{self, SessionOptions};
+use crate::auth::token_auth::TokenAuth;
 use crate::db::{Database, DatabaseAccess};
+use crate::error::ApiError;
+use chrono::Utc;
+use common::audit::{AuditEvent, AuditEventKind};
+use common::query::{Model, Update};
 use common::security;
-use common::user::UserBrief;
-use rocket::http::{Cookie, Cookies, Status};
+use common::user::{User, UserBrief};
+use rocket::http::{CookieJar, Status};
 use rocket::post;
-use rocket::request::State;
-use rocket_contrib::json;
-use rocket_contrib::json::Json;
+use rocket::serde::json::Json;
+use rocket::State;
 
 /// Log in to the server using Basic Auth. This endpoint generates an
-/// auth token for the user and sets it as a private cookie `auth_token`
+/// auth token for the user and sets it as a private cookie `auth_token`,
+/// along with a readable cookie `csrf_token` holding the token other
+/// state-changing requests must send in the `X-CSRF-Token` header. The
+/// login's time, address and user agent are recorded on the account
 ///
 /// Example:
-/// `POST /login`
+/// `POST /v1/login`
 ///
 /// Body:
 /// ```json
@@ -25,44 +35,134 @@ use rocket_contrib::json::Json;
 /// Response body:
 /// ```json
 /// {
-///   "_id": "ObjectId",
+///   "_id": "5fed7d1900d0e1b4002a6a86",
 ///   "username": "Foo",
 ///   "email": "foo@example.com",
-///   "last_login": "2020-12-31 12:00:00",
-///   "created": "2020-12-31 12:00:00",
-///   "updated": "2020-12-31 12:00:00",
+///   "last_login": "2020-12-31T12:00:00Z",
+///   "created": "2020-12-31T12:00:00Z",
+///   "updated": "2020-12-31T12:00:00Z",
 /// }
 /// ```
 ///
 /// *Datetimes given in UTC
+#[utoipa::path(
+    post,
+    path = "/login",
+    tag = "session",
+    security(("credentials" = [])),
+    responses(
+        (status = 200, description = "Logged in, the `auth_token` and `csrf_token` cookies are set", body = UserBrief),
+        (status = 400, description = "More than one Authorization header was sent", body = ErrorBody),
+        (status = 401, description = "The credentials are missing or incorrect", body = ErrorBody),
+    )
+)]
 #[post("/login")]
-pub fn login_endpoint(
-    db: State<Database>,
+pub async fn login_endpoint(
+    db: &State<Database>,
     login: LoginAuth,
-    mut cookies: Cookies,
-) -> Result<Json<UserBrief>, Status> {
+    cookies: &CookieJar<'_>,
+    session: &State<SessionOptions>,
+    audit_context: AuditContext,
+) -> Result<Json<UserBrief>, ApiError> {
+    let user = login_user(db, login, cookies, session, &audit_context).await?;
+    Ok(Json(user.into()))
+}
+
+/// Issues a new auth token and CSRF token to the logged in account and
+/// records the login on it and in the audit log, returning the account as
+/// updated. Shared by every version of `POST /login`
+pub(crate) async fn login_user(
+    db: &Database,
+    login: LoginAuth,
+    cookies: &CookieJar<'_>,
+    session: &SessionOptions,
+    audit_context: &AuditContext,
+) -> Result<User, ApiError> {
     let user = login.into_inner();
 
     let token = security::generate_auth_token(256);
+    let csrf_token = csrf::generate_token();
 
-    let query = json! {{
-        "_id": user.id,
-    }};
+    let fields = User::fields();
 
-    let update = json! {{
-        "$set": {
-            "auth_token": token
-        }
-    }};
+    let query = fields.id.eq(user.id);
 
-    let cookie = Cookie::build("auth_token", token)
-        .path("/")
-        .secure(true)
-        .http_only(true)
-        .finish();
+    let update = Update::new()
+        .set(fields.auth_token, token.clone())
+        .set(fields.csrf_token, csrf_token.clone())
+        .set(fields.last_login, Utc::now())
+        .set_or_unset(fields.last_login_ip, audit_context.ip().map(String::from))
+        .set_or_unset(
+            fields.last_login_user_agent,
+            audit_context.user_agent().map(String::from),
+        )
+        .inc(fields.login_count, 1);
 
-    cookies.add_private(cookie);
+    let user = db
+        .find_one_and_update::<User>("users", &query, &update)
+        .await?
+        .ok_or_else(|| ApiError::not_found("The account no longer exists"))?;
 
-    db.update_one("users", &query, &update)?;
-    Ok(Json(user.into()))
+    session.start(cookies, token, csrf_token);
+
+    let event = AuditEvent {
+        user_id: user.id,
+        username: Some(user.username.clone()),
+        ..audit_context.event(AuditEventKind::LoginSucceeded)
+    };
+    audit::record(db, event).await;
+
+    Ok(user)
+}
+
+/// Log out of the server. This revokes the auth token of the logged in
+/// account, so every session using it ends, and removes the
+/// `auth_token` and `csrf_token` cookies
+///
+/// Example:
+/// `POST /v1/logout`
+///
+/// Headers: `X-CSRF-Token` with the value of the `csrf_token` cookie
+///
+/// Response code: 204
+#[utoipa::path(
+    post,
+    path = "/logout",
+    tag = "session",
+    security(("auth_token" = [])),
+    params(
+        ("X-CSRF-Token" = String, Header, description = "Value of the `csrf_token` cookie"),
+    ),
+    responses(
+        (status = 204, description = "Logged out"),
+        (status = 401, description = "The auth token is missing or invalid", body = ErrorBody),
+        (status = 403, description = "The CSRF token is missing or invalid", body = ErrorBody),
+    )
+)]
+#[post("/logout")]
+pub async fn logout_endpoint(
+    db: &State<Database>,
+    token_auth: TokenAuth,
+    cookies: &CookieJar<'_>,
+    audit_context: AuditContext,
+) -> Result<Status, ApiError> {
+    let user = token_auth.into_inner();
+    let fields = User::fields();
+
+    let query = fields.id.eq(user.id);
+    let update = Update::new()
+        .unset(fields.auth_token)
+        .unset(fields.csrf_token);
+
+    db.update_one("users", &query, &update).await?;
+    session::end(cookies);
+
+    let event = AuditEvent {
+        user_id: user.id,
+        username: Some(user.username),
+        ..audit_context.event(AuditEventKind::Logout)
+    };
+    audit::record(db, event).await;
+
+    Ok(Status::NoContent)
 }
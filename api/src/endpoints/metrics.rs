@@ -0,0 +1,33 @@
+//! This module contains the endpoint exposing metrics to Prometheus
+
+use crate::metrics;
+use rocket::get;
+
+/// Fetch every collected metric in the Prometheus text exposition format.
+/// Like the health endpoints, this requires no authentication, so it should
+/// not be exposed outside the internal network
+///
+/// Example:
+/// `GET /metrics`
+///
+/// Content-type: text/plain
+/// Response code: 200
+/// Response body:
+/// ```text
+/// # HELP http_requests_total Number of HTTP requests handled
+/// # TYPE http_requests_total counter
+/// http_requests_total{method="GET",route="/self",status="200"} 3
+/// ```
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String,
+            content_type = "text/plain"),
+    )
+)]
+#[get("/metrics")]
+pub fn metrics_endpoint() -> String {
+    metrics::render()
+}
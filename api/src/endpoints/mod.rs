@@ -1,5 +1,9 @@
 //! This module organizes all endpoints into a single place
 
+pub mod audit;
+pub mod health;
 pub mod login;
+pub mod metrics;
+pub mod openapi;
 pub mod signup;
 pub mod user;
@@ -0,0 +1,79 @@
+//! This module contains the endpoints serving the OpenAPI document and the
+//! Swagger UI which renders it
+
+use crate::openapi;
+use rocket::get;
+use rocket::http::ContentType;
+use rocket::response::content::{RawHtml, RawJson};
+
+/// Swagger UI page. Its assets are Swagger UI 5.17.14, vendored in
+/// `static/swagger-ui` and served by `docs_asset_endpoint`, so the page
+/// loads nothing from other origins and runs no inline script
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>Simple Rocket API Server</title>
+  <link rel="stylesheet" href="/docs/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="/docs/swagger-ui-bundle.js"></script>
+  <script src="/docs/swagger-initializer.js"></script>
+</body>
+</html>
+"##;
+
+/// Renders the OpenAPI document once the bundle has loaded
+const SWAGGER_INITIALIZER: &str =
+    "window.ui = SwaggerUIBundle({ url: \"/openapi.json\", dom_id: \"#swagger-ui\" });\n";
+
+const SWAGGER_UI_BUNDLE: &str = include_str!("../../static/swagger-ui/swagger-ui-bundle.js");
+const SWAGGER_UI_CSS: &str = include_str!("../../static/swagger-ui/swagger-ui.css");
+
+/// Content Security Policy of the Swagger UI page, which only loads
+/// scripts, styles and the document from this server
+pub const DOCS_CONTENT_SECURITY_POLICY: &str = "default-src 'none'; \
+     script-src 'self'; style-src 'self'; \
+     img-src 'self' data:; connect-src 'self'; frame-ancestors 'none'";
+
+/// Fetch the OpenAPI 3 document describing every endpoint
+///
+/// Example:
+/// `GET /openapi.json`
+///
+/// Content-type: application/json
+/// Response code: 200
+#[get("/openapi.json")]
+pub fn openapi_endpoint() -> RawJson<String> {
+    RawJson(openapi::spec())
+}
+
+/// Browse the OpenAPI document with Swagger UI
+///
+/// Example:
+/// `GET /docs`
+///
+/// Content-type: text/html
+/// Response code: 200
+#[get("/docs")]
+pub fn docs_endpoint() -> RawHtml<&'static str> {
+    RawHtml(SWAGGER_UI)
+}
+
+/// Fetch the scripts and styles of the Swagger UI page
+///
+/// Example:
+/// `GET /docs/swagger-ui-bundle.js`
+///
+/// Content-type: text/javascript
+/// Response code: 200
+#[get("/docs/<asset>")]
+pub fn docs_asset_endpoint(asset: &str) -> Option<(ContentType, &'static str)> {
+    match asset {
+        "swagger-ui-bundle.js" => Some((ContentType::JavaScript, SWAGGER_UI_BUNDLE)),
+        "swagger-initializer.js" => Some((ContentType::JavaScript, SWAGGER_INITIALIZER)),
+        "swagger-ui.css" => Some((ContentType::CSS, SWAGGER_UI_CSS)),
+        _ => None,
+    }
+}
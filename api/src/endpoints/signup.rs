@@ -1,17 +1,18 @@
 //! This module contains signup endpoints
 
 use crate::db::{Database, DatabaseAccess};
+use crate::error::ApiError;
+use common::query::Model;
 use common::user::{SignupUser, User, UserBrief};
 use rocket::http::Status;
 use rocket::post;
-use rocket::request::State;
-use rocket_contrib::json;
-use rocket_contrib::json::Json;
+use rocket::serde::json::Json;
+use rocket::State;
 
 /// Adds a new user to the server
 ///
 /// Example:
-/// `POST /signup`
+/// `POST /v1/signup`
 ///
 /// Body:
 /// ```json
@@ -26,33 +27,58 @@ use rocket_contrib::json::Json;
 /// Response body:
 /// ```json
 /// {
-///   "_id": "ObjectId",
+///   "_id": "5fed7d1900d0e1b4002a6a86",
 ///   "username": "Foo",
 ///   "email": "foo@example.com",
-///   "last_login": "2020-12-31 12:00:00",
-///   "created": "2020-12-31 12:00:00",
-///   "updated": "2020-12-31 12:00:00",
+///   "last_login": "2020-12-31T12:00:00Z",
+///   "created": "2020-12-31T12:00:00Z",
+///   "updated": "2020-12-31T12:00:00Z",
 /// }
 /// ```
 ///
 /// *Datetimes given in UTC
+#[utoipa::path(
+    post,
+    path = "/signup",
+    tag = "account",
+    request_body = SignupUser,
+    responses(
+        (status = 200, description = "The account was created", body = UserBrief),
+        (status = 412, description = "The username is taken", body = ErrorBody),
+    )
+)]
 #[post("/signup", data = "<data>")]
-pub fn signup_endpoint(
+pub async fn signup_endpoint(
     data: Json<SignupUser>,
-    db: State<Database>,
-) -> Result<Json<UserBrief>, Status> {
-    let user = User::from(data.into_inner());
+    db: &State<Database>,
+) -> Result<Json<UserBrief>, ApiError> {
+    let user = signup(db, data.into_inner()).await?;
+    Ok(Json(user.into()))
+}
+
+/// Creates an account. Shared by every version of `POST /signup`, which
+/// only differ in the DTOs they convert from and to
+pub(crate) async fn signup(db: &Database, data: SignupUser) -> Result<User, ApiError> {
+    let user = User::from(data);
+
+    let query = User::fields().username.eq(user.username.clone());
 
-    let query = json! {{
-        "username": user.username
-    }};
+    if db.find_one::<User>("users", &query).await?.is_some() {
+        return Err(username_taken());
+    }
 
-    match db.find_one::<User>("users", &query) {
-        Ok(Some(_)) => Err(Status::PreconditionFailed),
-        Ok(None) => match db.insert_one("users", &user) {
-            Ok(user) => Ok(Json(UserBrief::from(user))),
-            Err(_) => Err(Status::InternalServerError),
-        },
-        Err(_) => Err(Status::InternalServerError),
+    match db.insert_one("users", &user).await {
+        Ok(user) => Ok(user),
+        // Lost a race with a concurrent signup for the same username
+        Err(e) if e.is_duplicate_key() => Err(username_taken()),
+        Err(e) => Err(e.into()),
     }
 }
+
+fn username_taken() -> ApiError {
+    ApiError::new(
+        Status::PreconditionFailed,
+        "username_taken",
+        "An account with this username already exists",
+    )
+}
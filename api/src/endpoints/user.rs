@@ -1,96 +1,238 @@
 //! This module contains endpoints relating to user account management
 
+use crate::audit::{self, AuditContext};
 use crate::auth::login_auth::LoginAuth;
+use crate::auth::session;
 use crate::auth::token_auth::TokenAuth;
 use crate::db::{Database, DatabaseAccess};
+use crate::error::ApiError;
+use crate::etag::{ETagged, IfMatch};
+use common::audit::{AuditEvent, AuditEventKind};
+use common::query::{Model, Update};
 use common::security;
-use common::user::{UpdateUser, UpdateUserPassword, UserBrief};
-use rocket::http::{Cookie, Cookies, Status};
+use common::user::{UpdateUser, UpdateUserPassword, User, UserBrief};
+use rocket::http::CookieJar;
 use rocket::response::Redirect;
+use rocket::serde::json::Json;
 use rocket::{get, patch, State};
-use rocket_contrib::json;
-use rocket_contrib::json::Json;
 
-/// Fetch the logged in account (specified by the auth token)
+/// Fetch the logged in account (specified by the auth token). The response
+/// carries an `ETag` header which can be sent back as `If-Match` when
+/// updating the account
 ///
 /// Example:
-/// `GET /self`
+/// `GET /v1/self`
 ///
 /// Content-type: application/json
 /// Response code: 200
 /// Response body:
 /// ```json
 /// {
-///   "_id": "ObjectId",
+///   "_id": "5fed7d1900d0e1b4002a6a86",
 ///   "username": "Foo",
 ///   "email": "foo@example.com",
-///   "last_login": "2020-12-31 12:00:00",
-///   "created": "2020-12-31 12:00:00",
-///   "updated": "2020-12-31 12:00:00",
+///   "last_login": "2020-12-31T12:00:00Z",
+///   "created": "2020-12-31T12:00:00Z",
+///   "updated": "2020-12-31T12:00:00Z",
 /// }
 /// ```
 ///
 /// *Datetimes given in UTC
+#[utoipa::path(
+    get,
+    path = "/self",
+    tag = "account",
+    security(("auth_token" = [])),
+    responses(
+        (status = 200, description = "The logged in account", body = UserBrief,
+            headers(("ETag" = String, description = "Version of the account"))),
+        (status = 401, description = "The auth token is missing or invalid", body = ErrorBody),
+    )
+)]
 #[get("/self")]
-pub fn self_endpoint(token_auth: TokenAuth) -> Result<Json<UserBrief>, Status> {
-    Ok(Json(token_auth.into_inner().into()))
+pub fn self_endpoint(token_auth: TokenAuth) -> Result<ETagged<Json<UserBrief>>, ApiError> {
+    let user = token_auth.into_inner();
+    let version = user.version;
+    Ok(ETagged(Json(user.into()), version))
 }
 
+/// Update the logged in account's username and/or email. If an `If-Match`
+/// header is given the update only happens if the account has not changed
+/// since that `ETag` was issued, otherwise `412 Precondition Failed` is
+/// returned
+///
+/// Example:
+/// `PATCH /v1/self`
+///
+/// Body:
+/// ```json
+/// {
+///   "email": "bar@example.com"
+/// }
+/// ```
+/// Content-type: application/json
+/// Response code: 200
+/// Headers: `X-CSRF-Token` with the value of the `csrf_token` cookie
+/// Response body: the updated account, as returned by `GET /self`
+#[utoipa::path(
+    patch,
+    path = "/self",
+    tag = "account",
+    security(("auth_token" = [])),
+    request_body = UpdateUser,
+    params(
+        ("If-Match" = Option<String>, Header, description = "ETag of the account as last fetched"),
+        ("X-CSRF-Token" = String, Header, description = "Value of the `csrf_token` cookie"),
+    ),
+    responses(
+        (status = 200, description = "The updated account", body = UserBrief,
+            headers(("ETag" = String, description = "Version of the account"))),
+        (status = 401, description = "The auth token is missing or invalid", body = ErrorBody),
+        (status = 403, description = "The CSRF token is missing or invalid", body = ErrorBody),
+        (status = 412, description = "The account changed since the If-Match tag was issued", body = ErrorBody),
+    )
+)]
 #[patch("/self", data = "<data>")]
-pub fn update_user_endpoint(
+pub async fn update_user_endpoint(
     data: Json<UpdateUser>,
-    db: State<Database>,
+    db: &State<Database>,
     token_auth: TokenAuth,
-) -> Result<Json<UserBrief>, Status> {
-    let data = data.into_inner();
-    let query = json! {{
-        "_id": token_auth.into_inner().id,
-    }};
+    if_match: IfMatch,
+    audit_context: AuditContext,
+) -> Result<ETagged<Json<UserBrief>>, ApiError> {
+    let user = update_user(db, token_auth, data.into_inner(), if_match, audit_context).await?;
+    let version = user.version;
+    Ok(ETagged(Json(user.into()), version))
+}
+
+/// Applies `data` to the logged in account, returning the account as
+/// updated. Shared by every version of `PATCH /self`
+pub(crate) async fn update_user(
+    db: &Database,
+    token_auth: TokenAuth,
+    data: UpdateUser,
+    if_match: IfMatch,
+    audit_context: AuditContext,
+) -> Result<User, ApiError> {
+    let user = token_auth.into_inner();
+    let fields = User::fields();
+
+    let update = Update::new()
+        .set_if_some(fields.username, data.username)
+        .set_if_some(fields.email, data.email);
+
+    if update.is_empty() {
+        if !if_match.matches(user.version) {
+            return Err(ApiError::precondition_failed(
+                "The account has changed since the If-Match tag was issued",
+            ));
+        }
+        return Ok(user);
+    }
 
-    let update = json! {{
-        "$set": data,
-    }};
+    let mut query = fields.id.eq(user.id);
+    if let Some(filter) = if_match.filter(fields.version) {
+        query = query.and(filter);
+    }
 
-    db.update_one("users", &query, &update)?;
+    let updated = match db
+        .find_one_and_update::<User>("users", &query, &update)
+        .await?
+    {
+        Some(updated) => updated,
+        None => return Err(if_match.failure()),
+    };
 
-    match db.find_one::<UserBrief>("users", &query)? {
-        Some(user) => Ok(Json(user)),
-        None => Err(Status::NotFound),
+    let changes = [
+        (user.email != updated.email, AuditEventKind::EmailChanged),
+        (
+            user.username != updated.username,
+            AuditEventKind::UsernameChanged,
+        ),
+    ];
+    for (_, kind) in changes.iter().filter(|(changed, _)| *changed) {
+        let event = AuditEvent {
+            user_id: updated.id,
+            username: Some(updated.username.clone()),
+            ..audit_context.event(*kind)
+        };
+        audit::record(db, event).await;
     }
+
+    Ok(updated)
 }
 
+/// Change the password of the account given by Basic Auth. Every session
+/// of the account is logged out
+///
+/// Example:
+/// `PATCH /v1/self/password`
+///
+/// Body:
+/// ```json
+/// {
+///   "password": "password5678"
+/// }
+/// ```
+/// Content-type: application/json
+/// Response code: 303
+#[utoipa::path(
+    patch,
+    path = "/self/password",
+    tag = "account",
+    security(("credentials" = [])),
+    request_body = UpdateUserPassword,
+    responses(
+        (status = 303, description = "The password was changed"),
+        (status = 401, description = "The credentials are missing or incorrect", body = ErrorBody),
+        (status = 404, description = "The account no longer exists", body = ErrorBody),
+    )
+)]
 #[patch("/self/password", data = "<data>")]
-pub fn update_user_password_endpoint(
+pub async fn update_user_password_endpoint(
     data: Json<UpdateUserPassword>,
-    db: State<Database>,
+    db: &State<Database>,
     auth: LoginAuth,
-    mut cookies: Cookies,
-) -> Result<Redirect, Status> {
+    cookies: &CookieJar<'_>,
+    audit_context: AuditContext,
+) -> Result<Redirect, ApiError> {
     let data = data.into_inner();
 
     let salt = security::generate_salt(256);
     let password_hash = security::hash(&salt, &data.password);
 
-    let query = json! {{
-        "_id": auth.into_inner().id,
-    }};
+    let fields = User::fields();
 
-    let update = json! {{
-        "$set": {
-            "salt": salt,
-            "password_hash": password_hash,
-        },
-        "$unset": {
-            "auth_token": 1,
-        }
-    }};
+    let query = fields.id.eq(auth.into_inner().id);
 
-    db.update_one("users", &query, &update)?;
+    let update = Update::new()
+        .set(fields.salt, salt)
+        .set(fields.password_hash, password_hash)
+        .unset(fields.auth_token)
+        .unset(fields.csrf_token);
 
-    cookies.remove_private(Cookie::named("auth_token"));
+    let user = db
+        .find_one_and_update::<User>("users", &query, &update)
+        .await?;
 
-    match db.find_one::<UserBrief>("users", &query)? {
-        Some(_) => Ok(Redirect::to("/")),
-        None => Err(Status::NotFound),
-    }
+    session::end(cookies);
+
+    let user = user.ok_or_else(|| ApiError::not_found("The account no longer exists"))?;
+
+    let event = |kind| AuditEvent {
+        user_id: user.id,
+        username: Some(user.username.clone()),
+        ..audit_context.event(kind)
+    };
+    audit::record(db, event(AuditEventKind::PasswordChanged)).await;
+    audit::record(
+        db,
+        AuditEvent {
+            reason: Some("password_changed".into()),
+            ..event(AuditEventKind::SessionRevoked)
+        },
+    )
+    .await;
+
+    Ok(Redirect::to("/"))
 }
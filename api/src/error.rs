@@ -0,0 +1,308 @@
+//! This module contains `ApiError`, the single error type every endpoint
+//! and catcher responds with. Each error carries a stable machine-readable
+//! `code` which clients can match on, a human readable message and the id
+//! of the request it occurred in
+//!
+//! Errors are returned as JSON:
+//!
+//! ```json
+//! {
+//!   "status": "error",
+//!   "status_code": 401,
+//!   "code": "invalid_credentials",
+//!   "message": "The username or password is incorrect",
+//!   "request_id": "0dWQ5TgyH3k8p6zZ"
+//! }
+//! ```
+//!
+//! or, if the client sends `Accept: application/problem+json`, as an
+//! RFC 7807 problem document with `code` and `request_id` as extension
+//! members.
+
+use crate::auth::err::AuthError;
+use crate::db::err::DBError;
+use crate::db::Database;
+use crate::request_id::RequestId;
+use rocket::http::{ContentType, Status, StatusClass};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use serde::Serialize;
+use serde_json::json;
+use std::io::Cursor;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const PROBLEM_JSON: &str = "application/problem+json";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiError {
+    status: Status,
+    code: &'static str,
+    message: String,
+    retry_after: Option<Duration>,
+}
+
+impl ApiError {
+    pub fn new(status: Status, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(Status::BadRequest, "bad_request", message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(Status::NotFound, "not_found", message)
+    }
+
+    pub fn precondition_failed(message: impl Into<String>) -> Self {
+        Self::new(Status::PreconditionFailed, "precondition_failed", message)
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    /// Returns the error a catcher for `status` should respond with: the
+    /// error a request guard failed with, if it failed with that status,
+    /// or else the generic error for the status
+    pub fn for_catcher(request: &Request, status: Status) -> Self {
+        let pending = request
+            .local_cache(|| PendingError(Mutex::new(None)))
+            .0
+            .lock()
+            .expect("Poisoned pending error")
+            .take();
+
+        match pending {
+            Some(e) if e.status == status => e,
+            _ => ApiError::from(status),
+        }
+    }
+
+    /// Remembers the error a request guard failed with, so the catcher
+    /// which handles the failure can respond with its code and message
+    pub fn remember(self, request: &Request) {
+        *request
+            .local_cache(|| PendingError(Mutex::new(None)))
+            .0
+            .lock()
+            .expect("Poisoned pending error") = Some(self);
+    }
+}
+
+/// Body of an error response, as documented in the OpenAPI spec
+#[derive(Serialize, Debug, utoipa::ToSchema)]
+pub struct ErrorBody {
+    /// Always `"error"`
+    #[schema(example = "error")]
+    pub status: &'static str,
+    pub status_code: u16,
+    /// Stable machine-readable error code
+    #[schema(example = "invalid_credentials")]
+    pub code: &'static str,
+    pub message: String,
+    /// Id of the request, as echoed in the `X-Request-Id` header
+    pub request_id: String,
+}
+
+/// Error a request guard failed with, stored in the request-local cache
+struct PendingError(Mutex<Option<ApiError>>);
+
+impl From<Status> for ApiError {
+    fn from(status: Status) -> Self {
+        let (code, message) = match status.code {
+            400 => ("bad_request", "The request was malformed"),
+            401 => ("unauthorized", "The request requires user authentication"),
+            403 => (
+                "forbidden",
+                "The authenticated user is not allowed to access this resource",
+            ),
+            404 => ("not_found", "Resource was not found"),
+            405 => (
+                "method_not_allowed",
+                "The resource does not support the request method",
+            ),
+            406 => (
+                "not_acceptable",
+                "The resource can't be returned in an accepted format",
+            ),
+            409 => (
+                "conflict",
+                "The request conflicts with the current state of the resource",
+            ),
+            412 => (
+                "precondition_failed",
+                "A precondition of the request was not met",
+            ),
+            413 => ("payload_too_large", "The request body is too large"),
+            415 => (
+                "unsupported_media_type",
+                "The request body has an unsupported content type",
+            ),
+            422 => (
+                "unprocessable_entity",
+                "The request body was well-formed but contained invalid values",
+            ),
+            503 => (
+                "service_unavailable",
+                "The service is temporarily unavailable, please try again later",
+            ),
+            _ if status.class() == StatusClass::ClientError => {
+                ("client_error", "The request could not be processed")
+            }
+            _ => (
+                "internal_error",
+                "The server encountered an internal error while processing this request",
+            ),
+        };
+
+        ApiError::new(status, code, message)
+    }
+}
+
+impl From<DBError> for ApiError {
+    fn from(e: DBError) -> Self {
+        ApiError::from(&e)
+    }
+}
+
+impl From<&DBError> for ApiError {
+    fn from(e: &DBError) -> Self {
+        match e {
+            DBError::Unavailable { retry_after, .. } => ApiError {
+                retry_after: Some(*retry_after),
+                ..ApiError::new(
+                    Status::ServiceUnavailable,
+                    "database_unavailable",
+                    "The database is temporarily unavailable, please try again later",
+                )
+            },
+            e if e.is_duplicate_key() => ApiError::new(
+                Status::Conflict,
+                "conflict",
+                "The resource conflicts with an existing one",
+            ),
+            DBError::MongoError { .. } => ApiError::new(
+                Status::ServiceUnavailable,
+                "database_unavailable",
+                "The database is temporarily unavailable, please try again later",
+            ),
+            _ => ApiError::from(Status::InternalServerError),
+        }
+    }
+}
+
+impl From<&AuthError> for ApiError {
+    fn from(e: &AuthError) -> Self {
+        match e {
+            AuthError::MissingAuth => ApiError::new(
+                Status::Unauthorized,
+                "missing_credentials",
+                "An Authorization header of the form username:password is required",
+            ),
+            AuthError::MissingToken => ApiError::new(
+                Status::Unauthorized,
+                "missing_token",
+                "The request requires user authentication",
+            ),
+            // Unknown users and wrong passwords share a code so that
+            // responses don't reveal which usernames exist
+            AuthError::NoUser(_) | AuthError::WrongPassword(_) => ApiError::new(
+                Status::Unauthorized,
+                "invalid_credentials",
+                "The username or password is incorrect",
+            ),
+            AuthError::BadToken => ApiError::new(
+                Status::Unauthorized,
+                "invalid_token",
+                "The auth token is invalid or has been revoked",
+            ),
+            AuthError::MissingCsrfToken => ApiError::new(
+                Status::Forbidden,
+                "csrf_token_missing",
+                "Requests authenticated by cookie must send the X-CSRF-Token header",
+            ),
+            AuthError::BadCsrfToken => ApiError::new(
+                Status::Forbidden,
+                "csrf_token_invalid",
+                "The CSRF token does not match the session",
+            ),
+            AuthError::BadHeaderCount => ApiError::new(
+                Status::BadRequest,
+                "multiple_authorization_headers",
+                "Only one Authorization header may be sent",
+            ),
+            AuthError::NotAdmin(_) => ApiError::from(Status::Forbidden),
+            AuthError::DBError { source } => ApiError::from(source),
+            AuthError::Unspecified => ApiError::from(Status::InternalServerError),
+        }
+    }
+}
+
+/// Returns true if the client asked for RFC 7807 problem documents
+fn wants_problem(request: &Request) -> bool {
+    request
+        .headers()
+        .get("Accept")
+        .any(|accept| accept.contains(PROBLEM_JSON))
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let request_id = RequestId::of(request);
+
+        let (content_type, body) = if wants_problem(request) {
+            let body = json!({
+                "type": "about:blank",
+                "title": self.status.reason_lossy(),
+                "status": self.status.code,
+                "detail": self.message,
+                "code": self.code,
+                "request_id": request_id,
+            });
+            (ContentType::new("application", "problem+json"), body)
+        } else {
+            let body = json!(ErrorBody {
+                status: "error",
+                status_code: self.status.code,
+                code: self.code,
+                message: self.message,
+                request_id: request_id.into(),
+            });
+            (ContentType::JSON, body)
+        };
+
+        let body = body.to_string();
+        let mut response = Response::build();
+        response
+            .status(self.status)
+            .header(content_type)
+            .sized_body(body.len(), Cursor::new(body));
+
+        // Fall back to the circuit breaker for 503s raised outside the db
+        let retry_after = self.retry_after.or_else(|| {
+            request
+                .rocket()
+                .state::<Database>()
+                .and_then(|db| db.retry_after())
+        });
+
+        if let (503, Some(delay)) = (self.status.code, retry_after) {
+            // Round up so clients never retry before the breaker lets them in
+            let seconds = delay.as_secs() + u64::from(delay.subsec_nanos() > 0);
+            response.raw_header("Retry-After", seconds.max(1).to_string());
+        }
+
+        response.ok()
+    }
+}
@@ -0,0 +1,93 @@
+//! This module contains helpers for optimistic concurrency control using
+//! `ETag` and `If-Match` headers derived from a document's version counter
+
+use crate::error::ApiError;
+use common::query::{Field, Filter};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+
+/// Responder which adds an `ETag` header for the given document version
+pub struct ETagged<R>(pub R, pub i64);
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for ETagged<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        Response::build_from(self.0.respond_to(request)?)
+            .raw_header("ETag", format!("\"{}\"", self.1))
+            .ok()
+    }
+}
+
+/// Request guard for the `If-Match` header. Never fails, requests without
+/// the header are `IfMatch::Absent`
+#[derive(Debug, Clone, PartialEq)]
+pub enum IfMatch {
+    Absent,
+    Any,
+    Versions(Vec<i64>),
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let mut values = request.headers().get("If-Match").peekable();
+
+        if values.peek().is_none() {
+            return Outcome::Success(IfMatch::Absent);
+        }
+
+        let mut versions = Vec::new();
+        for tag in values.flat_map(|v| v.split(',')).map(str::trim) {
+            if tag == "*" {
+                return Outcome::Success(IfMatch::Any);
+            }
+
+            // Tags we could not have issued can never match, so they are dropped
+            if let Some(version) = parse_tag(tag) {
+                versions.push(version);
+            }
+        }
+
+        Outcome::Success(IfMatch::Versions(versions))
+    }
+}
+
+/// Parses a strong entity tag of the form `"<version>"`
+fn parse_tag(tag: &str) -> Option<i64> {
+    if tag.len() < 2 || !tag.starts_with('"') || !tag.ends_with('"') {
+        return None;
+    }
+
+    tag[1..tag.len() - 1].parse().ok()
+}
+
+impl IfMatch {
+    /// Returns true if a document at `version` satisfies the precondition
+    pub fn matches(&self, version: i64) -> bool {
+        match self {
+            IfMatch::Absent | IfMatch::Any => true,
+            IfMatch::Versions(versions) => versions.contains(&version),
+        }
+    }
+
+    /// Returns a filter restricting `field` to the versions allowed by the
+    /// precondition, or `None` if any version is allowed
+    pub fn filter<M>(&self, field: Field<M, i64>) -> Option<Filter<M>> {
+        match self {
+            IfMatch::Absent | IfMatch::Any => None,
+            IfMatch::Versions(versions) => Some(field.is_in(versions.iter().copied())),
+        }
+    }
+
+    /// Returns the error to respond with when a conditional write matched
+    /// nothing: the document is gone, or its version no longer matches
+    pub fn failure(&self) -> ApiError {
+        match self {
+            IfMatch::Absent => ApiError::not_found("Resource was not found"),
+            _ => ApiError::precondition_failed(
+                "The resource has changed since the If-Match tag was issued",
+            ),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! This module resolves the real client address and scheme of requests
+//! which reach the server through reverse proxies
+//!
+//! The `X-Forwarded-For` and `X-Forwarded-Proto` headers are only believed
+//! when the request comes from a trusted proxy, since any client can send
+//! them. The proxies are listed in the Rocket config as addresses or CIDR
+//! ranges:
+//!
+//! ```toml
+//! trusted_proxies = ["127.0.0.1", "10.0.0.0/8"]
+//! ```
+//!
+//! No proxy is trusted by default. Rocket's own `ip_header` setting is not
+//! consulted, as it believes the header whoever sends it.
+
+use ipnet::IpNet;
+use log::error;
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
+use rocket::Request;
+use std::net::IpAddr;
+
+use crate::https::TlsClients;
+
+/// Proxies whose forwarding headers are believed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    /// Reads the proxies from the Rocket config. No proxy is trusted if
+    /// `trusted_proxies` is missing
+    pub fn from_figment(figment: &Figment) -> Result<Self, String> {
+        let proxies = match figment.extract_inner::<Vec<String>>("trusted_proxies") {
+            Ok(proxies) => proxies,
+            Err(_) => return Ok(TrustedProxies::default()),
+        };
+
+        proxies
+            .iter()
+            .map(|proxy| {
+                proxy
+                    .parse::<IpNet>()
+                    .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| format!("Invalid trusted proxy {:?}", proxy))
+            })
+            .collect::<Result<_, _>>()
+            .map(TrustedProxies)
+    }
+
+    /// Returns true if `ip` belongs to a trusted proxy
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(&ip))
+    }
+}
+
+/// The client as resolved from a request, stored in the request-local cache
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Client {
+    ip: Option<IpAddr>,
+    https: bool,
+}
+
+impl Client {
+    fn of(request: &Request<'_>) -> Self {
+        // Connections through the TLS terminator come from loopback, so the
+        // client is the one the terminator accepted
+        let terminated = request
+            .remote()
+            .and_then(|addr| request.rocket().state::<TlsClients>()?.client(addr));
+        let tls = terminated.is_some() || request.rocket().config().tls_enabled();
+        let peer = terminated
+            .or_else(|| request.remote())
+            .map(|addr| addr.ip());
+
+        let proxies = request.rocket().state::<TrustedProxies>();
+        let (proxies, peer_ip) = match (proxies, peer) {
+            (Some(proxies), Some(ip)) if proxies.contains(ip) => (proxies, ip),
+            _ => {
+                return Client {
+                    ip: peer,
+                    https: tls,
+                }
+            }
+        };
+
+        // Every proxy appends the address it got the request from, so the
+        // client is the rightmost address not belonging to a proxy. If they
+        // all do, the leftmost is the furthest one that can be told about
+        let forwarded = header_values(request, "X-Forwarded-For");
+        let forwarded: Vec<IpAddr> = forwarded.iter().filter_map(|ip| ip.parse().ok()).collect();
+        let hops = forwarded
+            .iter()
+            .rev()
+            .position(|ip| !proxies.contains(*ip))
+            .unwrap_or_else(|| forwarded.len().saturating_sub(1));
+        let ip = forwarded.iter().rev().nth(hops).copied().unwrap_or(peer_ip);
+
+        // Every proxy appends the scheme it got the request over, so the
+        // client's is as many hops from the right as its address
+        let protos = header_values(request, "X-Forwarded-Proto");
+        let https = match protos.iter().rev().nth(hops).or_else(|| protos.first()) {
+            Some(proto) => proto.eq_ignore_ascii_case("https"),
+            None => tls,
+        };
+
+        Client {
+            ip: Some(ip),
+            https,
+        }
+    }
+}
+
+/// Returns the comma separated values of every `name` header of `request`
+fn header_values<'r>(request: &'r Request<'_>, name: &str) -> Vec<&'r str> {
+    request
+        .headers()
+        .get(name)
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect()
+}
+
+/// Returns the address of the client which made `request`
+pub fn client_ip(request: &Request<'_>) -> Option<IpAddr> {
+    request.local_cache(|| Client::of(request)).ip
+}
+
+/// Returns true if the client made `request` over HTTPS
+pub fn is_https(request: &Request<'_>) -> bool {
+    request.local_cache(|| Client::of(request)).https
+}
+
+/// Returns a fairing which reads the trusted proxies on ignite. Launch is
+/// aborted if one of them is invalid
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Trusted proxies", |rocket| async {
+        match TrustedProxies::from_figment(rocket.figment()) {
+            Ok(proxies) => Ok(rocket.manage(proxies)),
+            Err(e) => {
+                error!(target: "Trusted proxies", "Invalid trusted_proxies: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
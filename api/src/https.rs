@@ -0,0 +1,411 @@
+//! This module contains the serving of the API over HTTPS: terminating TLS
+//! with a certificate which is reloaded on `SIGHUP`, and the listener which
+//! redirects plain HTTP requests to HTTPS
+//!
+//! TLS is enabled by giving the certificate chain and private key in the
+//! Rocket config. `https_redirect_port` starts a second listener on the
+//! same address which answers every request with a `308 Permanent Redirect`
+//! to the same path over HTTPS on `https_redirect_host`. The host is
+//! configured rather than taken from the request, so clients can't make the
+//! redirect point elsewhere:
+//!
+//! ```toml
+//! port = 443
+//! https_redirect_port = 80
+//! https_redirect_host = "api.example.com"
+//!
+//! [default.tls]
+//! certs = "/etc/api/tls/fullchain.pem"
+//! key = "/etc/api/tls/privkey.pem"
+//! ```
+//!
+//! Rocket only reads its certificate when it launches, so TLS is terminated
+//! here instead: Rocket is launched once on a loopback port and every TLS
+//! connection is forwarded to it. On `SIGHUP` the certificate is read again
+//! and, if it is valid, used for every new connection. A broken certificate
+//! is logged and the old one is kept. Nothing else is reloaded.
+
+use log::{debug, error, info, warn};
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
+use rocket::futures::future;
+use rocket::http::uri::Origin;
+use rocket::http::Method;
+use rocket::response::Redirect;
+use rocket::route::{Handler, Outcome};
+use rocket::tokio::io::copy_bidirectional;
+use rocket::tokio::net::{TcpListener, TcpSocket, TcpStream};
+use rocket::tokio::time;
+use rocket::{Build, Config, Data, Request, Rocket, Route};
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+use tokio_rustls::TlsAcceptor;
+
+const HTTPS_PORT: u16 = 443;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const METHODS: [Method; 9] = [
+    Method::Get,
+    Method::Put,
+    Method::Post,
+    Method::Delete,
+    Method::Options,
+    Method::Head,
+    Method::Trace,
+    Method::Connect,
+    Method::Patch,
+];
+
+#[derive(Error, Debug)]
+pub enum ServeError {
+    #[error("Invalid TLS config: {0}")]
+    Tls(String),
+
+    #[error("https_redirect_host must be set with https_redirect_port")]
+    RedirectHost,
+
+    #[error("Could not listen on {address}: {source}")]
+    Bind {
+        address: SocketAddr,
+        source: io::Error,
+    },
+
+    #[error(transparent)]
+    Rocket(#[from] rocket::Error),
+}
+
+/// Launches the API, terminating TLS in front of it if a certificate is
+/// configured. Returns once the server is shut down
+pub async fn serve() -> Result<(), ServeError> {
+    let figment = Config::figment();
+    let config = Config::from(&figment);
+
+    if let Some(port) = redirect_port(&figment) {
+        let host = redirect_host(&figment).ok_or(ServeError::RedirectHost)?;
+        let redirect = redirect_listener(&config, port, &host);
+        rocket::tokio::spawn(async move {
+            if let Err(e) = redirect.launch().await {
+                error!(target: "HTTPS", "HTTP redirect listener failed: {}", e);
+            }
+        });
+    }
+
+    if !config.tls_enabled() {
+        crate::build_rocket().launch().await?;
+        return Ok(());
+    }
+
+    let terminator = TlsTerminator::new(load_tls(&figment).map_err(ServeError::Tls)?);
+    let address = SocketAddr::new(config.address, config.port);
+    let listener = TcpListener::bind(address)
+        .await
+        .map_err(|source| ServeError::Bind { address, source })?;
+    info!(target: "HTTPS", "Terminating TLS on {}", address);
+
+    // Only the terminator may reach the API, and only over plain HTTP
+    let figment = figment
+        .merge(("address", IpAddr::from(Ipv4Addr::LOCALHOST)))
+        .merge(("port", 0))
+        .merge(("tls", None::<String>));
+
+    crate::build_rocket()
+        .configure(figment)
+        .manage(terminator.clients())
+        .attach(AdHoc::on_liftoff("TLS terminator", |rocket| {
+            let api = SocketAddr::new(rocket.config().address, rocket.config().port);
+            let shutdown = rocket.shutdown();
+            let reload = terminator.clone();
+            rocket::tokio::spawn(async move {
+                let terminate = Box::pin(terminator.run(listener, api));
+                let reload = Box::pin(reload_on_hangup(reload));
+                future::select(shutdown, future::select(terminate, reload)).await;
+            });
+            Box::pin(async {})
+        }))
+        .launch()
+        .await?;
+
+    Ok(())
+}
+
+/// Waits for `SIGHUP`s, reloading the certificate of `terminator` on each
+#[cfg(unix)]
+async fn reload_on_hangup(terminator: TlsTerminator) {
+    use rocket::tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!(target: "HTTPS", "Could not listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+
+    while hangups.recv().await.is_some() {
+        match load_tls(&Config::figment()) {
+            Ok(config) => {
+                terminator.reload(config);
+                info!(target: "HTTPS", "Reloaded the TLS certificate");
+            }
+            Err(e) => error!(target: "HTTPS", "Keeping the old TLS certificate: {}", e),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn reload_on_hangup(_: TlsTerminator) {
+    future::pending().await
+}
+
+/// Reads the certificate chain and private key in the TLS config
+///
+/// # Arguments
+///
+/// * `figment` - The Rocket config
+pub fn load_tls(figment: &Figment) -> Result<ServerConfig, String> {
+    let path = |name: &str| {
+        figment
+            .extract_inner::<String>(name)
+            .map_err(|_| format!("{} is not set to a path", name))
+    };
+    let reader = |path: &str| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| format!("Could not open {}: {}", path, e))
+    };
+
+    let certs_path = path("tls.certs")?;
+    let certs = rustls_pemfile::certs(&mut reader(&certs_path)?)
+        .map_err(|e| format!("Could not read {}: {}", certs_path, e))?;
+    if certs.is_empty() {
+        return Err(format!("{} contains no certificate", certs_path));
+    }
+
+    let key_path = path("tls.key")?;
+    let key = rustls_pemfile::read_all(&mut reader(&key_path)?)
+        .map_err(|e| format!("Could not read {}: {}", key_path, e))?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(key),
+            _ => None,
+        })
+        .ok_or_else(|| format!("{} contains no private key", key_path))?;
+
+    ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            certs.into_iter().map(Certificate).collect(),
+            PrivateKey(key),
+        )
+        .map_err(|e| format!("Invalid certificate or key: {}", e))
+}
+
+/// Checks that the certificate chain and private key in the TLS config can
+/// be loaded, so reloading them won't fail
+///
+/// # Arguments
+///
+/// * `figment` - The Rocket config
+pub fn check_tls(figment: &Figment) -> Result<(), String> {
+    load_tls(figment).map(|_| ())
+}
+
+/// Clients of the connections forwarded by a [`TlsTerminator`], by the local
+/// address of the forwarded connection. The API sees the terminator as the
+/// remote of these, so it looks the client up here
+#[derive(Debug, Clone, Default)]
+pub struct TlsClients(Arc<Mutex<HashMap<SocketAddr, SocketAddr>>>);
+
+impl TlsClients {
+    /// Returns the client whose connection was forwarded from `remote`, or
+    /// `None` if `remote` isn't a connection of the terminator
+    pub fn client(&self, remote: SocketAddr) -> Option<SocketAddr> {
+        self.0
+            .lock()
+            .expect("Poisoned TLS clients")
+            .get(&remote)
+            .copied()
+    }
+
+    fn register(&self, remote: SocketAddr, client: SocketAddr) -> Registration<'_> {
+        self.0
+            .lock()
+            .expect("Poisoned TLS clients")
+            .insert(remote, client);
+        Registration {
+            clients: self,
+            remote,
+        }
+    }
+}
+
+/// Forgets a forwarded connection once it is dropped
+struct Registration<'a> {
+    clients: &'a TlsClients,
+    remote: SocketAddr,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut clients) = self.clients.0.lock() {
+            clients.remove(&self.remote);
+        }
+    }
+}
+
+/// Accepts TLS connections and forwards them to the API over loopback. The
+/// certificate can be replaced while running, which only affects new
+/// connections
+#[derive(Clone)]
+pub struct TlsTerminator {
+    config: Arc<RwLock<Arc<ServerConfig>>>,
+    clients: TlsClients,
+}
+
+impl TlsTerminator {
+    /// Returns a terminator presenting the certificate of `config`
+    pub fn new(config: ServerConfig) -> Self {
+        TlsTerminator {
+            config: Arc::new(RwLock::new(Arc::new(config))),
+            clients: TlsClients::default(),
+        }
+    }
+
+    /// Returns the clients of the forwarded connections, which must be
+    /// managed by the API
+    pub fn clients(&self) -> TlsClients {
+        self.clients.clone()
+    }
+
+    /// Presents the certificate of `config` to every new connection
+    pub fn reload(&self, config: ServerConfig) {
+        *self.config.write().expect("Poisoned TLS config") = Arc::new(config);
+    }
+
+    /// Accepts connections on `listener` and forwards them to `api` until
+    /// the returned future is dropped
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - Listener the clients connect to
+    /// * `api` - Loopback address the API listens on
+    pub async fn run(self, listener: TcpListener, api: SocketAddr) {
+        loop {
+            let (stream, client) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!(target: "HTTPS", "Could not accept a connection: {}", e);
+                    time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+
+            let acceptor =
+                TlsAcceptor::from(self.config.read().expect("Poisoned TLS config").clone());
+            let clients = self.clients.clone();
+            rocket::tokio::spawn(async move {
+                if let Err(e) = forward(acceptor, stream, client, api, &clients).await {
+                    debug!(target: "HTTPS", "Connection from {} failed: {}", client, e);
+                }
+            });
+        }
+    }
+}
+
+/// Completes the TLS handshake with `client` and forwards the connection to
+/// the API until either side closes it
+async fn forward(
+    acceptor: TlsAcceptor,
+    stream: TcpStream,
+    client: SocketAddr,
+    api: SocketAddr,
+    clients: &TlsClients,
+) -> io::Result<()> {
+    let mut stream = time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out"))??;
+
+    // The local address is registered before connecting, so the API can
+    // never see the connection without knowing its client
+    let socket = match api {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    socket.bind(SocketAddr::new(api.ip(), 0))?;
+    let _registration = clients.register(socket.local_addr()?, client);
+
+    let mut upstream = socket.connect(api).await?;
+    copy_bidirectional(&mut stream, &mut upstream).await?;
+
+    Ok(())
+}
+
+/// Returns the port of the HTTP redirect listener, if it is enabled
+fn redirect_port(figment: &Figment) -> Option<u16> {
+    figment.extract_inner("https_redirect_port").ok()
+}
+
+/// Returns the host HTTP requests are redirected to
+fn redirect_host(figment: &Figment) -> Option<String> {
+    figment.extract_inner("https_redirect_host").ok()
+}
+
+/// Returns a rocket redirecting every request to the same path over HTTPS
+///
+/// # Arguments
+///
+/// * `config` - Config of the API, whose port is redirected to
+/// * `port` - Port the redirect listener listens on
+/// * `host` - Host redirected to, whatever host the request was sent to
+pub fn redirect_listener(config: &Config, port: u16, host: &str) -> Rocket<Build> {
+    let handler = RedirectToHttps {
+        host: host.to_string(),
+        port: config.port,
+    };
+    let config = Config {
+        address: config.address,
+        port,
+        log_level: config.log_level,
+        cli_colors: config.cli_colors,
+        ..Config::default()
+    };
+    let routes: Vec<Route> = METHODS
+        .iter()
+        .map(|method| Route::new(*method, "/<_..>", handler.clone()))
+        .collect();
+
+    rocket::custom(config).mount("/", routes)
+}
+
+/// Handler redirecting to the same path on the HTTPS host and port
+#[derive(Clone)]
+struct RedirectToHttps {
+    host: String,
+    port: u16,
+}
+
+#[rocket::async_trait]
+impl Handler for RedirectToHttps {
+    async fn handle<'r>(&self, request: &'r Request<'_>, _: Data<'r>) -> Outcome<'r> {
+        Outcome::from(request, Redirect::permanent(self.url(request.uri())))
+    }
+}
+
+impl RedirectToHttps {
+    /// Returns the HTTPS URL of `origin`
+    fn url(&self, origin: &Origin) -> String {
+        match self.port {
+            HTTPS_PORT => format!("https://{}{}", self.host, origin),
+            port => format!("https://{}:{}{}", self.host, port, origin),
+        }
+    }
+}
@@ -5,31 +5,141 @@
 //! purposes and serves as an example that is at least a little more complex
 //! than the example projects Rocket comes with.
 
-#![feature(proc_macro_hygiene, decl_macro, bindings_after_at, backtrace)]
-
 pub use common;
-use rocket::{catchers, routes};
+use rocket::fairing::AdHoc;
+use rocket::{catchers, routes, Build, Rocket, Route};
 
+pub mod admin;
+pub mod audit;
 pub mod auth;
 mod catchers;
+pub mod cors;
 pub mod db;
 mod endpoints;
+pub mod error;
+mod etag;
+pub mod forwarded;
+pub mod https;
+pub mod logging;
+pub mod metrics;
+pub mod openapi;
+pub mod request_id;
+mod scope;
+pub mod security_headers;
+mod telemetry;
+pub mod versioning;
 
-pub fn build_rocket() -> rocket::Rocket {
-    let client = db::DBClient::init("mongodb://localhost:27017/");
-    let routes = routes![
+const MONGO_URI: &str = "mongodb://localhost:27017/";
+const APP_DATABASE: &str = "appdb";
+
+/// Returns a fairing which connects to MongoDB when the rocket is ignited
+/// and manages the app database and its resilience options. The driver
+/// needs a running async runtime, so this can't happen while building
+fn database_fairing() -> AdHoc {
+    AdHoc::on_ignite("Database", |rocket| async {
+        let options = db::ResilienceOptions::from_figment(rocket.figment());
+
+        rocket
+            .manage(connect_database(options.clone()).await)
+            .manage(options)
+    })
+}
+
+/// Connects to the app database, as the server does
+///
+/// # Arguments
+///
+/// * `options` - Retry and circuit breaker settings
+///
+/// # Examples
+///
+/// ```
+/// use api::db::ResilienceOptions;
+///
+/// # rocket::execute(async {
+/// let db = api::connect_database(ResilienceOptions::default()).await;
+/// # });
+/// ```
+pub async fn connect_database(options: db::ResilienceOptions) -> db::Database {
+    let client = db::DBClient::init(MONGO_URI).await;
+    client.get_database_with_options(APP_DATABASE, options)
+}
+
+/// Routes of v1 of the API, relative to the version prefix
+pub(crate) fn v1_routes() -> Vec<Route> {
+    routes![
         endpoints::signup::signup_endpoint,
         endpoints::login::login_endpoint,
+        endpoints::login::logout_endpoint,
         endpoints::user::self_endpoint,
         endpoints::user::update_user_endpoint,
         endpoints::user::update_user_password_endpoint,
+        endpoints::audit::security_events_endpoint,
+        endpoints::audit::admin_audit_events_endpoint,
+    ]
+}
+
+pub fn build_rocket() -> Rocket<Build> {
+    // Operational routes are not part of the versioned API
+    let routes = routes![
+        endpoints::metrics::metrics_endpoint,
+        endpoints::openapi::openapi_endpoint,
     ];
-    rocket::ignite()
-        .manage(client.get_database("appdb"))
-        .mount("/", routes)
-        .register(catchers![
-            catchers::not_found,
-            catchers::internal_server_error,
-            catchers::unauthorized
-        ])
+    // Swagger UI loads scripts and styles, which the default policy forbids
+    let docs = security_headers::HeaderOverrides::new()
+        .set(
+            "Content-Security-Policy",
+            endpoints::openapi::DOCS_CONTENT_SECURITY_POLICY,
+        )
+        .routes(routes![
+            endpoints::openapi::docs_endpoint,
+            endpoints::openapi::docs_asset_endpoint,
+        ]);
+    let v1 = &versioning::V1;
+    let unversioned = &versioning::UNVERSIONED;
+
+    rocket::build()
+        .attach(database_fairing())
+        .attach(auth::session::fairing())
+        .attach(forwarded::fairing())
+        .attach(db::resilience::fairing())
+        .attach(db::migrations::fairing())
+        .attach(telemetry::fairing())
+        .attach(request_id::RequestIds)
+        .attach(telemetry::RequestTracing)
+        .attach(metrics::RequestMetrics)
+        .attach(versioning::VersionHeaders)
+        .attach(cors::Cors)
+        .attach(security_headers::SecurityHeaders)
+        .mount(v1.prefix, scope::scoped(v1.routes(v1_routes())))
+        .mount(
+            unversioned.prefix,
+            scope::scoped(unversioned.routes(v1_routes())),
+        )
+        .mount("/", scope::scoped(routes))
+        .mount("/", scope::scoped(docs))
+        .mount(
+            "/health",
+            scope::scoped(routes![
+                endpoints::health::live_endpoint,
+                endpoints::health::ready_endpoint,
+            ]),
+        )
+        .register(
+            "/",
+            catchers![
+                catchers::bad_request,
+                catchers::unauthorized,
+                catchers::forbidden,
+                catchers::not_found,
+                catchers::conflict,
+                catchers::precondition_failed,
+                catchers::payload_too_large,
+                catchers::unsupported_media_type,
+                catchers::unprocessable_entity,
+                catchers::internal_server_error,
+                catchers::service_unavailable,
+                catchers::default,
+            ],
+        )
 }
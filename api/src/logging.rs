@@ -0,0 +1,218 @@
+//! This module contains a structured logger which writes every record as a
+//! single line of JSON. Records logged while a request is being handled
+//! carry the request's id, route and authenticated user id, and anything
+//! that looks like a credential is redacted before it is written
+//!
+//! Requests are handled by async tasks which may move between worker
+//! threads, so the request context is kept in a task local which is set for
+//! the duration of every route handler (see `scope`). Records logged
+//! outside a handler, such as by fairings and catchers, carry no context.
+
+use chrono::Utc;
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use rocket::tokio::task_local;
+use serde_json::json;
+use std::cell::RefCell;
+use std::future::Future;
+use std::io::Write;
+
+/// Keys whose values are never written to the logs
+const SECRET_KEYS: &[&str] = &[
+    "password",
+    "password_hash",
+    "salt",
+    "auth_token",
+    "csrf_token",
+    "x-csrf-token",
+    "authorization",
+    "cookie",
+];
+
+const REDACTED: &str = "[REDACTED]";
+
+#[derive(Default)]
+struct LogContext {
+    request_id: Option<String>,
+    route: Option<String>,
+    user_id: Option<String>,
+}
+
+task_local! {
+    static CONTEXT: RefCell<LogContext>;
+}
+
+/// Runs `f` with a request attached to every record it logs
+///
+/// # Arguments
+///
+/// * `request_id` - Id of the request
+/// * `route` - Method and path of the request
+/// * `f` - The work done for the request
+pub(crate) async fn with_request<T>(
+    request_id: &str,
+    route: &str,
+    f: impl Future<Output = T>,
+) -> T {
+    let context = LogContext {
+        request_id: Some(request_id.into()),
+        route: Some(route.into()),
+        user_id: None,
+    };
+
+    CONTEXT.scope(RefCell::new(context), f).await
+}
+
+/// Attaches the authenticated user to every record the current request
+/// logs from now on
+pub(crate) fn set_user(user_id: &str) {
+    let _ = CONTEXT.try_with(|c| c.borrow_mut().user_id = Some(user_id.into()));
+}
+
+/// Logger writing one JSON object per record to stdout
+pub struct JsonLogger {
+    level: LevelFilter,
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = |context: &LogContext| {
+            json!({
+                "timestamp": Utc::now().to_rfc3339(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": redact(&record.args().to_string()),
+                "request_id": context.request_id,
+                "route": context.route,
+                "user_id": context.user_id,
+            })
+        };
+        let line = CONTEXT
+            .try_with(|c| line(&c.borrow()))
+            .unwrap_or_else(|_| line(&LogContext::default()));
+
+        let stdout = std::io::stdout();
+        let _ = writeln!(stdout.lock(), "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Installs the JSON logger. This must happen before the rocket is built,
+/// otherwise Rocket installs its own logger first
+///
+/// # Arguments
+///
+/// * `level` - The most verbose level which is written
+///
+/// # Examples
+///
+/// ```
+/// use log::LevelFilter;
+///
+/// api::logging::init(LevelFilter::Info).unwrap();
+/// let rocket = api::build_rocket();
+/// ```
+pub fn init(level: LevelFilter) -> Result<(), SetLoggerError> {
+    log::set_boxed_logger(Box::new(JsonLogger { level }))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// Returns `message` with the values of secret keys replaced, for example
+/// `auth_token: "abc"` becomes `auth_token: "[REDACTED]"`. Keys are matched
+/// case-insensitively and may be quoted, and values may be quoted or run
+/// until the next delimiter
+///
+/// # Arguments
+///
+/// * `message` - The text to redact
+///
+/// # Examples
+///
+/// ```
+/// use api::logging::redact;
+///
+/// assert_eq!(redact(r#"{"password":"hunter2"}"#), r#"{"password":"[REDACTED]"}"#);
+/// ```
+pub fn redact(message: &str) -> String {
+    let lower = message.to_ascii_lowercase();
+    let mut result = String::with_capacity(message.len());
+    let mut copied = 0;
+    let mut i = 0;
+
+    while i < message.len() {
+        let key = SECRET_KEYS
+            .iter()
+            .filter(|key| lower[i..].starts_with(*key) && is_key_start(&lower, i))
+            .max_by_key(|key| key.len());
+
+        let value = key.and_then(|key| find_value(message, i + key.len()));
+
+        match value {
+            Some((start, end)) => {
+                result.push_str(&message[copied..start]);
+                result.push_str(REDACTED);
+                copied = end;
+                i = end;
+            }
+            None => i += message[i..].chars().next().map_or(1, char::len_utf8),
+        }
+    }
+
+    result.push_str(&message[copied..]);
+    result
+}
+
+/// Returns true if a key starting at `i` is not the tail of a longer word
+fn is_key_start(text: &str, i: usize) -> bool {
+    text[..i]
+        .chars()
+        .next_back()
+        .is_none_or(|c| !(c.is_ascii_alphanumeric() || c == '_'))
+}
+
+/// Given the index just after a key, returns the byte range of its value,
+/// excluding any quotes, or `None` if the key is not followed by a value
+fn find_value(text: &str, after_key: usize) -> Option<(usize, usize)> {
+    let bytes = text.as_bytes();
+    let mut i = after_key;
+
+    if bytes.get(i) == Some(&b'"') {
+        i += 1;
+    }
+    while bytes.get(i) == Some(&b' ') {
+        i += 1;
+    }
+    match bytes.get(i) {
+        Some(b':') | Some(b'=') => i += 1,
+        _ => return None,
+    }
+    while bytes.get(i) == Some(&b' ') {
+        i += 1;
+    }
+
+    if bytes.get(i) == Some(&b'"') {
+        let start = i + 1;
+        let mut end = start;
+        while end < bytes.len() && bytes[end] != b'"' {
+            end += if bytes[end] == b'\\' { 2 } else { 1 };
+        }
+        Some((start, end.min(bytes.len())))
+    } else {
+        let start = i;
+        let end = text[start..]
+            .find(|c: char| c.is_whitespace() || matches!(c, ',' | ';' | '}' | ')' | ']'))
+            .map_or(text.len(), |offset| start + offset);
+        Some((start, end))
+    }
+}
@@ -1,3 +1,75 @@
-fn main() {
-    api::build_rocket().launch();
+use api::db::migrations::Migrator;
+use api::db::ResilienceOptions;
+use log::LevelFilter;
+use std::process;
+
+const USAGE: &str = "Usage: api_bin [migrate <up|status>]";
+
+#[rocket::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        [] => {
+            let level = std::env::var("LOG_LEVEL")
+                .ok()
+                .and_then(|level| level.parse().ok())
+                .unwrap_or(LevelFilter::Info);
+            api::logging::init(level).expect("Could not install logger");
+            if let Err(e) = api::https::serve().await {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        ["migrate", command] => migrate(command).await,
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+/// Runs a migration command against the app database. Only the database is
+/// connected, so `status` never applies migrations as igniting the server
+/// with `auto_migrate` would
+async fn migrate(command: &str) {
+    let figment = rocket::Config::figment();
+    let db = api::connect_database(ResilienceOptions::from_figment(&figment)).await;
+    if let Err(e) = db.wait_until_available().await {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    let migrator = Migrator::new(&db);
+
+    match command {
+        "up" => match migrator.up().await {
+            Ok(applied) if applied.is_empty() => println!("No pending migrations"),
+            Ok(applied) => applied
+                .iter()
+                .for_each(|version| println!("Applied migration {}", version)),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        },
+        "status" => match migrator.status().await {
+            Ok(status) => status.iter().for_each(|m| {
+                println!(
+                    "{:>4} {:<32} {}",
+                    m.version,
+                    m.name,
+                    m.applied.as_deref().unwrap_or("pending")
+                )
+            }),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
 }
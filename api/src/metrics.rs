@@ -0,0 +1,132 @@
+//! This module contains the Prometheus metrics collected by the server:
+//! per-route request counts and latencies, per-collection database timings
+//! and errors, and authentication outcomes. They are exposed in the
+//! Prometheus text format by the `/metrics` endpoint
+
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, Encoder, HistogramVec, IntCounterVec,
+    TextEncoder,
+};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request, Response};
+use std::future::Future;
+use std::time::Instant;
+
+lazy_static! {
+    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "http_requests_total",
+        "Number of HTTP requests handled",
+        &["method", "route", "status"]
+    )
+    .expect("Could not register http_requests_total");
+    static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "http_request_duration_seconds",
+        "Time taken to handle HTTP requests",
+        &["method", "route"]
+    )
+    .expect("Could not register http_request_duration_seconds");
+    static ref DB_OPERATION_DURATION: HistogramVec = register_histogram_vec!(
+        "db_operation_duration_seconds",
+        "Time taken by database operations, including retries",
+        &["collection", "operation"]
+    )
+    .expect("Could not register db_operation_duration_seconds");
+    static ref DB_OPERATION_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "db_operation_errors_total",
+        "Number of database operations which returned an error",
+        &["collection", "operation"]
+    )
+    .expect("Could not register db_operation_errors_total");
+    static ref AUTH_ATTEMPTS: IntCounterVec = register_int_counter_vec!(
+        "auth_attempts_total",
+        "Number of authentication attempts by guard and outcome",
+        &["guard", "outcome"]
+    )
+    .expect("Could not register auth_attempts_total");
+}
+
+/// Label used for requests which did not match any route, so that arbitrary
+/// paths can't blow up the number of series
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Start time of a request, stored in the request-local cache
+struct RequestStart(Instant);
+
+/// Fairing which records the count, status and latency of every request
+pub struct RequestMetrics;
+
+#[rocket::async_trait]
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let start = request.local_cache(|| RequestStart(Instant::now()));
+        let method = request.method().as_str();
+        let route = request
+            .route()
+            .map(|r| r.uri.path())
+            .unwrap_or(UNMATCHED_ROUTE);
+        let status = response.status().code.to_string();
+
+        HTTP_REQUESTS
+            .with_label_values(&[method, route, &status])
+            .inc();
+        HTTP_REQUEST_DURATION
+            .with_label_values(&[method, route])
+            .observe(start.0.elapsed().as_secs_f64());
+    }
+}
+
+/// Runs a database operation, recording how long it took and whether it
+/// failed
+///
+/// # Arguments
+///
+/// * `collection` - Collection the operation runs against
+/// * `operation` - Name of the operation, e.g. `find_one`
+/// * `op` - The operation itself
+pub(crate) async fn observe_db<T, E>(
+    collection: &str,
+    operation: &str,
+    op: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let start = Instant::now();
+    let result = op.await;
+
+    DB_OPERATION_DURATION
+        .with_label_values(&[collection, operation])
+        .observe(start.elapsed().as_secs_f64());
+    if result.is_err() {
+        DB_OPERATION_ERRORS
+            .with_label_values(&[collection, operation])
+            .inc();
+    }
+
+    result
+}
+
+/// Counts an authentication attempt made through `guard`
+pub(crate) fn record_auth(guard: &str, success: bool) {
+    let outcome = if success { "success" } else { "failure" };
+    AUTH_ATTEMPTS.with_label_values(&[guard, outcome]).inc();
+}
+
+/// Returns every registered metric in the Prometheus text format
+pub fn render() -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .expect("Could not encode metrics");
+
+    String::from_utf8(buffer).expect("Metrics are not valid UTF-8")
+}
@@ -0,0 +1,118 @@
+//! This module contains the OpenAPI 3 document describing the server. It
+//! is generated from the `#[utoipa::path]` attributes on the endpoints and
+//! the schemas derived on the `common` request and response types, and is
+//! served at `/openapi.json` alongside a Swagger UI at `/docs`
+//!
+//! Endpoints are documented relative to their version prefix, and are then
+//! listed once under every version they are served in.
+
+use crate::error::ErrorBody;
+use crate::versioning::{UNVERSIONED, V1};
+use crate::{endpoints, v1_routes};
+use common::audit::{AuditEventBrief, AuditEventKind};
+use common::user::{SignupUser, UpdateUser, UpdateUserPassword, UserBrief};
+use std::collections::BTreeMap;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::openapi::Deprecated;
+use utoipa::{Modify, OpenApi};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Simple Rocket API Server",
+        description = "User account management backed by MongoDB"
+    ),
+    paths(
+        endpoints::signup::signup_endpoint,
+        endpoints::login::login_endpoint,
+        endpoints::login::logout_endpoint,
+        endpoints::user::self_endpoint,
+        endpoints::user::update_user_endpoint,
+        endpoints::user::update_user_password_endpoint,
+        endpoints::audit::security_events_endpoint,
+        endpoints::audit::admin_audit_events_endpoint,
+        endpoints::metrics::metrics_endpoint,
+        endpoints::health::live_endpoint,
+        endpoints::health::ready_endpoint,
+    ),
+    components(schemas(
+        UserBrief,
+        SignupUser,
+        UpdateUser,
+        UpdateUserPassword,
+        AuditEventBrief,
+        AuditEventKind,
+        ErrorBody
+    )),
+    modifiers(&SecuritySchemes, &Versions)
+)]
+pub struct ApiDoc;
+
+/// Adds the two ways of authenticating: the `auth_token` cookie set by
+/// `POST /login`, and `username:password` in the `Authorization` header
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "auth_token",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                "auth_token",
+                "Private cookie set by POST /login",
+            ))),
+        );
+        components.add_security_scheme(
+            "credentials",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "Authorization",
+                "The account's credentials in the form username:password",
+            ))),
+        );
+    }
+}
+
+/// Lists the versioned endpoints under every version prefix. Operations of
+/// deprecated versions are marked as such, and their ids get the version
+/// name appended since ids must be unique within the document
+struct Versions;
+
+impl Modify for Versions {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let versioned: Vec<String> = v1_routes()
+            .iter()
+            .map(|route| route.uri.path().to_string())
+            .collect();
+        let mut paths = BTreeMap::new();
+
+        for (path, item) in std::mem::take(&mut openapi.paths.paths) {
+            if !versioned.contains(&path) {
+                paths.insert(path, item);
+                continue;
+            }
+
+            for version in [&V1, &UNVERSIONED] {
+                let mut item = item.clone();
+                if version.is_deprecated() {
+                    for operation in item.operations.values_mut() {
+                        operation.deprecated = Some(Deprecated::True);
+                        operation.operation_id = operation
+                            .operation_id
+                            .take()
+                            .map(|id| format!("{}_{}", id, version.name));
+                    }
+                }
+                paths.insert(version.path(&path), item);
+            }
+        }
+
+        openapi.paths.paths = paths;
+    }
+}
+
+/// Returns the OpenAPI document as pretty printed JSON
+pub fn spec() -> String {
+    ApiDoc::openapi()
+        .to_pretty_json()
+        .expect("Could not serialize OpenAPI document")
+}
@@ -0,0 +1,72 @@
+//! This module contains the fairing which gives every request an id, so the
+//! log lines and error bodies of a request can be correlated
+
+use common::security;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Data, Request, Response};
+use std::convert::Infallible;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Longest client supplied id which is accepted
+const MAX_LENGTH: usize = 128;
+
+/// The id of the current request. As a request guard this never fails
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Returns the id assigned to `request`, or an empty string if the
+    /// `RequestIds` fairing is not attached
+    pub fn of<'r>(request: &'r Request) -> &'r str {
+        &request.local_cache(|| RequestId(String::new())).0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestId {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(RequestId(RequestId::of(request).into()))
+    }
+}
+
+/// Fairing which accepts the client's `X-Request-Id`, or generates one if it
+/// is missing or malformed, and echoes it back on the response
+pub struct RequestIds;
+
+#[rocket::async_trait]
+impl Fairing for RequestIds {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request ids",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let id = request
+            .headers()
+            .get_one(REQUEST_ID_HEADER)
+            .filter(|id| is_valid(id))
+            .map(String::from)
+            .unwrap_or_else(|| security::generate_auth_token(32));
+
+        request.local_cache(|| RequestId(id));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        response.set_raw_header(REQUEST_ID_HEADER, RequestId::of(request).to_string());
+    }
+}
+
+/// Only short, printable ids are accepted so they are safe to log and echo
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
@@ -0,0 +1,42 @@
+//! This module contains the wrapper which runs every route handler, request
+//! guards included, inside the request's log and trace context. Rocket runs
+//! handlers as async tasks, so the context can't be tied to a thread and
+//! has to be attached to the handler's future instead
+
+use crate::request_id::RequestId;
+use crate::{logging, telemetry};
+use opentelemetry::trace::FutureExt;
+use rocket::route::{Handler, Outcome};
+use rocket::{Data, Request, Route};
+
+/// Handler running the wrapped handler inside the request's context
+#[derive(Clone)]
+struct Scoped(Box<dyn Handler>);
+
+#[rocket::async_trait]
+impl Handler for Scoped {
+    async fn handle<'r>(&self, request: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        let route = format!("{} {}", request.method(), request.uri().path());
+        let handler = self
+            .0
+            .handle(request, data)
+            .with_context(telemetry::request_context(request));
+
+        logging::with_request(RequestId::of(request), &route, handler).await
+    }
+}
+
+/// Wraps the handlers of `routes` so they run inside the request's context
+///
+/// # Arguments
+///
+/// * `routes` - The routes to wrap, as returned by `routes!`
+pub fn scoped(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(Scoped(route.handler));
+            route
+        })
+        .collect()
+}
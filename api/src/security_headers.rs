@@ -0,0 +1,248 @@
+//! This module contains the fairing which adds the standard hardening
+//! headers to every response
+//!
+//! The values are read from the Rocket config (`Rocket.toml` or `ROCKET_*`
+//! environment variables), and an empty value omits the header:
+//!
+//! ```toml
+//! strict_transport_security = "max-age=31536000; includeSubDomains"
+//! content_security_policy = "default-src 'none'; frame-ancestors 'none'"
+//! x_content_type_options = "nosniff"
+//! referrer_policy = "no-referrer"
+//! permissions_policy = "camera=(), geolocation=(), microphone=()"
+//! no_store_authenticated = true
+//! ```
+//!
+//! `Strict-Transport-Security` is only sent over HTTPS. Responses to
+//! authenticated requests also carry `Cache-Control: no-store` unless
+//! `no_store_authenticated` is false, so shared caches and browsers never
+//! keep account data. Routes which need different values, such as a page
+//! loading scripts, wrap their routes with `HeaderOverrides`.
+
+use crate::forwarded;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::figment::Figment;
+use rocket::route::{Handler, Outcome};
+use rocket::{Build, Data, Request, Response, Rocket, Route};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Values of the hardening headers, where `None` omits the header
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecurityHeadersOptions {
+    pub strict_transport_security: Option<String>,
+    pub content_security_policy: Option<String>,
+    pub x_content_type_options: Option<String>,
+    pub referrer_policy: Option<String>,
+    pub permissions_policy: Option<String>,
+    /// Whether responses to authenticated requests carry
+    /// `Cache-Control: no-store`
+    pub no_store_authenticated: bool,
+}
+
+impl Default for SecurityHeadersOptions {
+    fn default() -> Self {
+        SecurityHeadersOptions {
+            strict_transport_security: Some("max-age=31536000; includeSubDomains".into()),
+            // The API only serves JSON, so documents it returns may load
+            // nothing and may not be framed
+            content_security_policy: Some("default-src 'none'; frame-ancestors 'none'".into()),
+            x_content_type_options: Some("nosniff".into()),
+            referrer_policy: Some("no-referrer".into()),
+            permissions_policy: Some(
+                "camera=(), geolocation=(), microphone=(), payment=(), usb=()".into(),
+            ),
+            no_store_authenticated: true,
+        }
+    }
+}
+
+impl SecurityHeadersOptions {
+    /// Reads the options from the Rocket config, falling back to the
+    /// defaults for anything missing
+    pub fn from_figment(figment: &Figment) -> Self {
+        let defaults = SecurityHeadersOptions::default();
+        let header =
+            |name: &str, default: Option<String>| match figment.extract_inner::<String>(name) {
+                Ok(value) if value.is_empty() => None,
+                Ok(value) => Some(value),
+                Err(_) => default,
+            };
+
+        SecurityHeadersOptions {
+            strict_transport_security: header(
+                "strict_transport_security",
+                defaults.strict_transport_security,
+            ),
+            content_security_policy: header(
+                "content_security_policy",
+                defaults.content_security_policy,
+            ),
+            x_content_type_options: header(
+                "x_content_type_options",
+                defaults.x_content_type_options,
+            ),
+            referrer_policy: header("referrer_policy", defaults.referrer_policy),
+            permissions_policy: header("permissions_policy", defaults.permissions_policy),
+            no_store_authenticated: figment
+                .extract_inner("no_store_authenticated")
+                .unwrap_or(defaults.no_store_authenticated),
+        }
+    }
+
+    /// Returns the headers to add, by name
+    fn headers(&self) -> [(&'static str, &Option<String>); 5] {
+        [
+            ("Strict-Transport-Security", &self.strict_transport_security),
+            ("Content-Security-Policy", &self.content_security_policy),
+            ("X-Content-Type-Options", &self.x_content_type_options),
+            ("Referrer-Policy", &self.referrer_policy),
+            ("Permissions-Policy", &self.permissions_policy),
+        ]
+    }
+}
+
+/// Header values of some routes which differ from the configured ones
+///
+/// # Examples
+///
+/// ```
+/// use api::security_headers::HeaderOverrides;
+/// use rocket::{get, routes};
+///
+/// #[get("/page")]
+/// fn page() -> &'static str {
+///     "<p>Hello</p>"
+/// }
+///
+/// let routes = HeaderOverrides::new()
+///     .set("Content-Security-Policy", "default-src 'self'")
+///     .remove("Permissions-Policy")
+///     .routes(routes![page]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HeaderOverrides(Vec<(&'static str, Option<String>)>);
+
+impl HeaderOverrides {
+    pub fn new() -> Self {
+        HeaderOverrides::default()
+    }
+
+    /// Sends `value` as the header `name` instead of the configured value
+    pub fn set(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.0.push((name, Some(value.into())));
+        self
+    }
+
+    /// Omits the header `name`
+    pub fn remove(mut self, name: &'static str) -> Self {
+        self.0.push((name, None));
+        self
+    }
+
+    /// Wraps the handlers of `routes` so their responses use these values
+    ///
+    /// # Arguments
+    ///
+    /// * `routes` - The routes to override the headers of, as returned by `routes!`
+    pub fn routes(self, routes: Vec<Route>) -> Vec<Route> {
+        let overrides = Arc::new(self);
+        routes
+            .into_iter()
+            .map(|mut route| {
+                route.handler = Box::new(Overridden(overrides.clone(), route.handler));
+                route
+            })
+            .collect()
+    }
+
+    fn apply(&self, response: &mut Response) {
+        for (name, value) in &self.0 {
+            match value {
+                Some(value) => {
+                    response.set_raw_header(*name, value.clone());
+                }
+                None => response.remove_header(name),
+            }
+        }
+    }
+}
+
+/// Overrides of the route handling the request, stored in the
+/// request-local cache
+struct RequestOverrides(Option<Arc<HeaderOverrides>>);
+
+/// Whether a request guard authenticated the request, stored in the
+/// request-local cache
+struct Authenticated(AtomicBool);
+
+/// Marks `request` as authenticated, so its response is not cached
+pub(crate) fn mark_authenticated(request: &Request) {
+    request
+        .local_cache(|| Authenticated(AtomicBool::new(false)))
+        .0
+        .store(true, Ordering::Relaxed);
+}
+
+/// Handler storing the route's overrides before running the wrapped
+/// handler
+#[derive(Clone)]
+struct Overridden(Arc<HeaderOverrides>, Box<dyn Handler>);
+
+#[rocket::async_trait]
+impl Handler for Overridden {
+    async fn handle<'r>(&self, request: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        request.local_cache(|| RequestOverrides(Some(self.0.clone())));
+        self.1.handle(request, data).await
+    }
+}
+
+/// Fairing which reads the header values on ignite and adds the headers to
+/// every response
+pub struct SecurityHeaders;
+
+#[rocket::async_trait]
+impl Fairing for SecurityHeaders {
+    fn info(&self) -> Info {
+        Info {
+            name: "Security headers",
+            kind: Kind::Ignite | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        let options = SecurityHeadersOptions::from_figment(rocket.figment());
+        Ok(rocket.manage(options))
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let options = match request.rocket().state::<SecurityHeadersOptions>() {
+            Some(options) => options,
+            None => return,
+        };
+
+        // Browsers ignore HSTS received over plain HTTP, since anyone on the
+        // path could have added it
+        let https = forwarded::is_https(request);
+        for (name, value) in options.headers() {
+            if name == "Strict-Transport-Security" && !https {
+                continue;
+            }
+            if let Some(value) = value {
+                response.set_raw_header(name, value.clone());
+            }
+        }
+
+        let authenticated = request
+            .local_cache(|| Authenticated(AtomicBool::new(false)))
+            .0
+            .load(Ordering::Relaxed);
+        if authenticated && options.no_store_authenticated {
+            response.set_raw_header("Cache-Control", "no-store");
+        }
+
+        if let Some(overrides) = &request.local_cache(|| RequestOverrides(None)).0 {
+            overrides.apply(response);
+        }
+    }
+}
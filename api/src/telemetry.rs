@@ -0,0 +1,279 @@
+//! This module contains OpenTelemetry tracing: a server span per request,
+//! continuing the caller's trace if a W3C `traceparent` header is sent, and
+//! child spans around database calls and password hashing
+//!
+//! The request span is kept in the request-local cache, and every route
+//! handler runs with it as the current context (see `scope`), so spans
+//! started while handling the request become its children.
+//!
+//! Spans are exported according to the Rocket config:
+//!
+//! * `otel_exporter = "otlp"` sends spans over OTLP/HTTP to `otel_endpoint`
+//!   (default `http://localhost:4318`)
+//! * `otel_exporter = "stdout"` writes one JSON object per span to stdout
+//! * `otel_exporter = "file"` appends the same JSON lines to `otel_file`
+//!   (default `spans.jsonl`)
+//!
+//! Tracing is disabled when `otel_exporter` is unset or `"none"`.
+
+use opentelemetry::global;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::{
+    get_active_span, FutureExt, SpanKind, Status, TraceContextExt, TraceError, Tracer,
+};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::runtime::Tokio;
+use opentelemetry_sdk::trace::{Config as TraceConfig, TracerProvider};
+use opentelemetry_sdk::Resource;
+use rocket::fairing::{AdHoc, Fairing, Info, Kind};
+use rocket::figment::Figment;
+use rocket::http::HeaderMap;
+use rocket::{Data, Request, Response};
+use serde_json::json;
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::future::{self, Future};
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+const TRACER_NAME: &str = "api";
+const SERVICE_NAME: &str = "api";
+const DEFAULT_ENDPOINT: &str = "http://localhost:4318";
+const DEFAULT_FILE: &str = "spans.jsonl";
+const TRACE_HEADERS: &[&str] = &["traceparent", "tracestate"];
+
+/// Context of the request span, stored in the request-local cache
+struct RequestContext(Context);
+
+/// Returns a fairing which installs the span exporter chosen in the Rocket
+/// config when the rocket is ignited. Launch is aborted if it can't be built
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Tracing", |rocket| async {
+        match init(rocket.figment()) {
+            Ok(()) => Ok(rocket),
+            Err(e) => {
+                log::error!(target: "Tracing", "Could not install span exporter: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+/// Installs the global tracer provider and W3C trace context propagator
+fn init(figment: &Figment) -> Result<(), TraceError> {
+    let setting = |name: &str, default: &str| {
+        figment
+            .extract_inner::<String>(name)
+            .unwrap_or_else(|_| default.into())
+    };
+    let trace_config = TraceConfig::default().with_resource(Resource::new(vec![KeyValue::new(
+        "service.name",
+        SERVICE_NAME,
+    )]));
+
+    let provider = match setting("otel_exporter", "none").as_str() {
+        "none" => return Ok(()),
+        // Spans are sent from a background task, so exporting never
+        // blocks the request being traced
+        "otlp" => {
+            let exporter = opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(setting("otel_endpoint", DEFAULT_ENDPOINT))
+                .build_span_exporter()?;
+            TracerProvider::builder()
+                .with_batch_exporter(exporter, Tokio)
+                .with_config(trace_config)
+                .build()
+        }
+        "stdout" => TracerProvider::builder()
+            .with_simple_exporter(JsonSpanExporter::new(io::stdout()))
+            .with_config(trace_config)
+            .build(),
+        "file" => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(setting("otel_file", DEFAULT_FILE))
+                .map_err(|e| TraceError::from(e.to_string()))?;
+            TracerProvider::builder()
+                .with_simple_exporter(JsonSpanExporter::new(file))
+                .with_config(trace_config)
+                .build()
+        }
+        other => return Err(format!("Unknown otel_exporter {:?}", other).into()),
+    };
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    global::set_tracer_provider(provider);
+    Ok(())
+}
+
+/// Exporter writing each finished span as a line of JSON
+#[derive(Debug)]
+struct JsonSpanExporter<W: Write + Send + std::fmt::Debug>(Mutex<W>);
+
+impl<W: Write + Send + std::fmt::Debug> JsonSpanExporter<W> {
+    fn new(writer: W) -> Self {
+        JsonSpanExporter(Mutex::new(writer))
+    }
+}
+
+impl<W: Write + Send + std::fmt::Debug> SpanExporter for JsonSpanExporter<W> {
+    fn export(
+        &mut self,
+        batch: Vec<SpanData>,
+    ) -> Pin<Box<dyn Future<Output = ExportResult> + Send>> {
+        let mut writer = self.0.lock().expect("Poisoned span writer");
+        let result = batch
+            .iter()
+            .try_for_each(|span| writeln!(writer, "{}", span_to_json(span)))
+            .and_then(|_| writer.flush())
+            .map_err(|e| TraceError::from(e.to_string()));
+
+        Box::pin(future::ready(result))
+    }
+}
+
+fn span_to_json(span: &SpanData) -> serde_json::Value {
+    let nanos = |time: std::time::SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default()
+    };
+    let attributes: serde_json::Map<String, serde_json::Value> = span
+        .attributes
+        .iter()
+        .map(|kv| (kv.key.to_string(), kv.value.to_string().into()))
+        .collect();
+    let status = match &span.status {
+        Status::Unset => json!("unset"),
+        Status::Ok => json!("ok"),
+        Status::Error { description } => json!({ "error": description }),
+    };
+
+    json!({
+        "name": span.name,
+        "trace_id": span.span_context.trace_id().to_string(),
+        "span_id": span.span_context.span_id().to_string(),
+        "parent_span_id": span.parent_span_id.to_string(),
+        "kind": format!("{:?}", span.span_kind),
+        "start_unix_nanos": nanos(span.start_time),
+        "end_unix_nanos": nanos(span.end_time),
+        "attributes": attributes,
+        "status": status,
+    })
+}
+
+/// Lets the propagator read trace headers from a Rocket request
+struct HeaderExtractor<'a, 'r>(&'a HeaderMap<'r>);
+
+impl<'a, 'r> Extractor for HeaderExtractor<'a, 'r> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get_one(key)
+    }
+
+    // Rocket can't lend out header names, so only the W3C headers the
+    // propagator reads are listed
+    fn keys(&self) -> Vec<&str> {
+        TRACE_HEADERS
+            .iter()
+            .copied()
+            .filter(|name| self.0.contains(*name))
+            .collect()
+    }
+}
+
+/// Fairing which wraps every request in a server span
+pub struct RequestTracing;
+
+#[rocket::async_trait]
+impl Fairing for RequestTracing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request tracing",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let parent = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(request.headers()))
+        });
+
+        let tracer = global::tracer(TRACER_NAME);
+        let span = tracer
+            .span_builder(format!("{} {}", request.method(), request.uri().path()))
+            .with_kind(SpanKind::Server)
+            .with_attributes(vec![
+                KeyValue::new("http.method", request.method().as_str()),
+                KeyValue::new("http.target", request.uri().to_string()),
+            ])
+            .start_with_context(&tracer, &parent);
+
+        let cx = parent.with_span(span);
+        request.local_cache(|| RequestContext(cx));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let cx = request_context(request);
+        let span = cx.span();
+
+        if let Some(route) = request.route() {
+            span.set_attribute(KeyValue::new("http.route", route.uri.path().to_string()));
+        }
+
+        let status = response.status().code;
+        span.set_attribute(KeyValue::new("http.status_code", i64::from(status)));
+        if status >= 500 {
+            span.set_status(Status::error(response.status().reason_lossy()));
+        }
+        span.end();
+    }
+}
+
+/// Returns the context holding the span of `request`, or an empty context
+/// if the `RequestTracing` fairing is not attached
+pub(crate) fn request_context(request: &Request) -> Context {
+    request
+        .local_cache(|| RequestContext(Context::new()))
+        .0
+        .clone()
+}
+
+/// Runs `f` inside a child span of the current request
+///
+/// # Arguments
+///
+/// * `name` - Name of the span
+/// * `attributes` - Attributes to attach to the span
+/// * `f` - The work to trace
+pub(crate) async fn in_span<T>(
+    name: String,
+    attributes: Vec<KeyValue>,
+    f: impl Future<Output = T>,
+) -> T {
+    let tracer = global::tracer(TRACER_NAME);
+    let span = tracer
+        .span_builder(name)
+        .with_kind(SpanKind::Internal)
+        .with_attributes(attributes)
+        .start(&tracer);
+
+    let cx = Context::current_with_span(span);
+    let result = f.with_context(cx.clone()).await;
+    cx.span().end();
+
+    result
+}
+
+/// Marks the currently active span as failed if `result` is an error
+pub(crate) fn record_result<T, E: Display>(result: &Result<T, E>) {
+    if let Err(e) = result {
+        get_active_span(|span| span.set_status(Status::error(e.to_string())));
+    }
+}
@@ -0,0 +1,162 @@
+//! This module contains the versions of the API and the fairing which
+//! marks responses of deprecated versions
+//!
+//! Every version is mounted under its own prefix, such as `/v1`, so a new
+//! version can change the shape of its responses without breaking clients
+//! of an older one. Versions share the endpoint logic and only differ in
+//! the DTOs their route handlers convert the results to.
+//!
+//! Responses of a deprecated version carry the `Deprecation` (RFC 9745)
+//! and `Sunset` (RFC 8594) headers, and a `Link` to the same resource in
+//! the version replacing it:
+//!
+//! ```text
+//! Deprecation: @1792281600
+//! Sunset: Sun, 18 Apr 2027 00:00:00 GMT
+//! Link: </v1/self>; rel="successor-version"
+//! ```
+
+use chrono::{TimeZone, Utc};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::route::{Handler, Outcome};
+use rocket::{Data, Request, Response, Route};
+
+/// A version of the API and its lifecycle
+#[derive(Debug, PartialEq)]
+pub struct ApiVersion {
+    /// Name of the version, e.g. `v1`
+    pub name: &'static str,
+    /// Prefix the version's routes are mounted under
+    pub prefix: &'static str,
+    /// When the version was deprecated, in seconds since the Unix epoch
+    pub deprecated: Option<i64>,
+    /// When the version will be removed, in seconds since the Unix epoch
+    pub sunset: Option<i64>,
+    /// The version clients should move to
+    pub successor: Option<&'static ApiVersion>,
+}
+
+pub static V1: ApiVersion = ApiVersion {
+    name: "v1",
+    prefix: "/v1",
+    deprecated: None,
+    sunset: None,
+    successor: None,
+};
+
+/// The routes as they were mounted before the API was versioned. They are
+/// served as aliases of v1 until they are sunset
+pub static UNVERSIONED: ApiVersion = ApiVersion {
+    name: "unversioned",
+    prefix: "/",
+    // 2026-10-18
+    deprecated: Some(1_792_281_600),
+    // 2027-04-18
+    sunset: Some(1_808_006_400),
+    successor: Some(&V1),
+};
+
+impl ApiVersion {
+    pub fn is_deprecated(&self) -> bool {
+        self.deprecated.is_some()
+    }
+
+    /// Returns `path` as mounted under this version
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of a route relative to the version prefix
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use api::versioning::{UNVERSIONED, V1};
+    ///
+    /// assert_eq!(V1.path("/self"), "/v1/self");
+    /// assert_eq!(UNVERSIONED.path("/self"), "/self");
+    /// ```
+    pub fn path(&self, path: &str) -> String {
+        format!("{}{}", self.prefix.trim_end_matches('/'), path)
+    }
+
+    /// Wraps the handlers of `routes` so their responses are marked with
+    /// this version. The routes still have to be mounted at `prefix`
+    ///
+    /// # Arguments
+    ///
+    /// * `routes` - The routes to serve in this version, as returned by `routes!`
+    pub fn routes(&'static self, routes: Vec<Route>) -> Vec<Route> {
+        routes
+            .into_iter()
+            .map(|mut route| {
+                route.handler = Box::new(Versioned(self, route.handler));
+                route
+            })
+            .collect()
+    }
+
+    /// Adds the deprecation headers for a response to `path`
+    fn mark_deprecated(&self, path: &str, response: &mut Response) {
+        let (deprecated, sunset) = match (self.deprecated, self.sunset) {
+            (Some(deprecated), sunset) => (deprecated, sunset),
+            (None, _) => return,
+        };
+
+        response.set_raw_header("Deprecation", format!("@{}", deprecated));
+
+        if let Some(date) = sunset.and_then(|s| Utc.timestamp_opt(s, 0).single()) {
+            response.set_raw_header(
+                "Sunset",
+                date.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+            );
+        }
+
+        if let Some(successor) = self.successor {
+            let path = path
+                .strip_prefix(self.prefix.trim_end_matches('/'))
+                .unwrap_or(path);
+            response.set_raw_header(
+                "Link",
+                format!("<{}>; rel=\"successor-version\"", successor.path(path)),
+            );
+        }
+    }
+}
+
+/// Version of the route handling the request, stored in the request-local
+/// cache. Set before any request guard runs, so it is known to the
+/// catchers too
+struct RequestVersion(Option<&'static ApiVersion>);
+
+/// Handler marking the request with its version before running the
+/// wrapped handler
+#[derive(Clone)]
+struct Versioned(&'static ApiVersion, Box<dyn Handler>);
+
+#[rocket::async_trait]
+impl Handler for Versioned {
+    async fn handle<'r>(&self, request: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        request.local_cache(|| RequestVersion(Some(self.0)));
+        self.1.handle(request, data).await
+    }
+}
+
+/// Fairing which adds the deprecation headers to responses of deprecated
+/// versions
+pub struct VersionHeaders;
+
+#[rocket::async_trait]
+impl Fairing for VersionHeaders {
+    fn info(&self) -> Info {
+        Info {
+            name: "API version headers",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if let Some(version) = request.local_cache(|| RequestVersion(None)).0 {
+            version.mark_deprecated(request.uri().path().as_str(), response);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use api::admin::{self, AdminError, Role};
+use api::common::audit::AuditEvent;
+use api::db::{Database, DatabaseAccess};
+use rocket::http::{Header, Status};
+use serde_json::json;
+
+mod common;
+
+fn db(client: &common::TestClient) -> &Database {
+    client.rocket().state::<Database>().unwrap()
+}
+
+#[test]
+fn test_create_user() {
+    let client = common::setup_untracked();
+
+    let user = common::block_on(admin::create_user(
+        db(&client),
+        "foo",
+        "foo@example.com",
+        "password1234",
+        true,
+    ))
+    .unwrap();
+    assert_eq!(user.username, "foo");
+    assert!(user.admin);
+    assert!(!user.logged_in);
+
+    // The account can log in through the API
+    let _ = common::get_mock_user_auth_token(&client);
+
+    let result = common::block_on(admin::create_user(
+        db(&client),
+        "foo",
+        "bar@example.com",
+        "password1234",
+        false,
+    ));
+    assert!(matches!(result, Err(AdminError::UsernameTaken(_))));
+}
+
+#[test]
+fn test_reset_password() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let auth_cookie = common::get_mock_user_auth_token(&client);
+
+    let user = common::block_on(admin::reset_password(db(&client), "foo", "password5678")).unwrap();
+    assert!(!user.logged_in);
+
+    let response = client.get("/v1/self").cookie(auth_cookie).dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .post("/v1/login")
+        .header(Header::new("Authorization", "foo:password5678"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let events: Vec<AuditEvent> = common::block_on(db(&client).find(
+        "audit_events",
+        &json!({ "kind": "password_changed" }),
+        None,
+        None,
+    ))
+    .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].reason.as_deref(), Some("admin_reset"));
+    assert!(events[0].request_id.starts_with("cli-"));
+}
+
+#[test]
+fn test_revoke_sessions() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let auth_cookie = common::get_mock_user_auth_token(&client);
+
+    common::block_on(admin::revoke_sessions(db(&client), "foo")).unwrap();
+
+    let response = client.get("/v1/self").cookie(auth_cookie).dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let result = common::block_on(admin::revoke_sessions(db(&client), "nobody"));
+    assert!(matches!(result, Err(AdminError::NoUser(_))));
+}
+
+#[test]
+fn test_set_role() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let auth_cookie = common::get_mock_user_auth_token(&client);
+
+    let user = common::block_on(admin::set_role(db(&client), "foo", Role::Admin, true)).unwrap();
+    assert!(user.admin);
+
+    let response = client
+        .get("/v1/admin/audit-events")
+        .cookie(auth_cookie.clone())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    common::block_on(admin::set_role(db(&client), "foo", Role::Admin, false)).unwrap();
+
+    let response = client
+        .get("/v1/admin/audit-events")
+        .cookie(auth_cookie)
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+
+    assert!("superuser".parse::<Role>().is_err());
+}
+
+#[test]
+fn test_indexes_and_stats() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+
+    let indexes = common::block_on(admin::ensure_indexes(db(&client))).unwrap();
+    let users = indexes.iter().find(|c| c.collection == "users").unwrap();
+    assert!(users.indexes.contains(&"username_1".to_string()));
+
+    let stats = common::block_on(admin::stats(db(&client))).unwrap();
+    assert_eq!(stats.users, 1);
+    assert_eq!(stats.admins, 0);
+    assert_eq!(stats.logged_in_users, 0);
+}
@@ -0,0 +1,161 @@
+use api::common::audit::{AuditEvent, AuditEventBrief, AuditEventKind};
+use api::common::user::User;
+use api::db::{Database, DatabaseAccess};
+use chrono::Utc;
+use rocket::http::{ContentType, Header, Status};
+use serde_json::json;
+
+mod common;
+
+#[test]
+fn test_security_events() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+
+    let response = client
+        .post("/login")
+        .header(Header::new("Authorization", "foo:wrongpassword"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let auth_cookie = common::get_mock_user_auth_token(&client);
+
+    let response = client
+        .get("/self/security-events")
+        .header(Header::new("User-Agent", "audit-test"))
+        .cookie(auth_cookie)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let events: Vec<AuditEventBrief> =
+        serde_json::from_str(&response.into_string().unwrap()).unwrap();
+
+    // Newest first, including the failed attempt against the account
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].kind, AuditEventKind::LoginSucceeded);
+    assert_eq!(events[0].username.as_deref(), Some("foo"));
+    assert!(!events[0].request_id.is_empty());
+    assert_eq!(events[1].kind, AuditEventKind::LoginFailed);
+    assert_eq!(events[1].username.as_deref(), Some("foo"));
+    assert_eq!(events[1].reason.as_deref(), Some("wrong_password"));
+}
+
+#[test]
+fn test_admin_audit_events() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let auth_cookie = common::get_mock_user_auth_token(&client);
+
+    let response = client
+        .get("/admin/audit-events")
+        .cookie(auth_cookie.clone())
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let db = client.rocket().state::<Database>().unwrap();
+    common::block_on(db.update_one(
+        "users",
+        &json!({ "username": "foo" }),
+        &json!({ "$set": { "admin": true } }),
+    ))
+    .unwrap();
+
+    let response = client
+        .get("/admin/audit-events?kind=login_succeeded")
+        .cookie(auth_cookie.clone())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let events: Vec<AuditEventBrief> =
+        serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    assert_eq!(events.len(), 1);
+
+    let response = client
+        .get("/admin/audit-events?kind=not_a_kind")
+        .cookie(auth_cookie)
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn test_password_change_audits_failures_only() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+
+    let response = client
+        .patch("/self/password")
+        .header(ContentType::JSON)
+        .header(Header::new("Authorization", "foo:wrongpassword"))
+        .body(r#"{"password": "password5678"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .patch("/self/password")
+        .header(ContentType::JSON)
+        .header(Header::new("Authorization", "foo:password1234"))
+        .body(r#"{"password": "password5678"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::SeeOther);
+
+    let db = client.rocket().state::<Database>().unwrap();
+    let events: Vec<AuditEvent> =
+        common::block_on(db.find("audit_events", &json!({}), None, None)).unwrap();
+    let kinds: Vec<AuditEventKind> = events.iter().map(|event| event.kind).collect();
+
+    // The wrong password is audited, the right one is not a login
+    assert_eq!(
+        kinds,
+        vec![
+            AuditEventKind::LoginFailed,
+            AuditEventKind::PasswordChanged,
+            AuditEventKind::SessionRevoked
+        ]
+    );
+    assert_eq!(events[0].user_id, events[1].user_id);
+    assert_eq!(events[0].reason.as_deref(), Some("wrong_password"));
+}
+
+#[test]
+fn test_security_events_paging() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let auth_cookie = common::get_mock_user_auth_token(&client);
+
+    // Events recorded in the same millisecond share a timestamp
+    let db = client.rocket().state::<Database>().unwrap();
+    let user: User = common::block_on(db.find_one("users", &json!({ "username": "foo" })))
+        .unwrap()
+        .unwrap();
+    let timestamp = Utc::now();
+    for _ in 0..3 {
+        let event = AuditEvent {
+            user_id: user.id,
+            timestamp,
+            ..AuditEvent::new(AuditEventKind::SessionRevoked, "paging-test")
+        };
+        common::block_on(db.insert_one("audit_events", &event)).unwrap();
+    }
+
+    let mut ids = Vec::new();
+    let mut uri = "/self/security-events?limit=1".to_string();
+    loop {
+        let response = client.get(uri).cookie(auth_cookie.clone()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let events: Vec<AuditEventBrief> =
+            serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        let id = match events.first().and_then(|event| event.id) {
+            Some(id) => id,
+            None => break,
+        };
+        ids.push(id);
+        uri = format!("/self/security-events?limit=1&before={}", id);
+    }
+
+    // The login and the three revocations, each exactly once
+    assert_eq!(ids.len(), 4);
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 4);
+}
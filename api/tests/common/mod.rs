@@ -1,7 +1,12 @@
+#![allow(dead_code)]
+
 use api::db::Database;
 use common::user::SignupUser;
+use rocket::figment::Provider;
 use rocket::http::{ContentType, Cookie, Header, Status};
-use rocket::local::Client;
+use rocket::local::blocking::Client;
+use rocket::tokio::runtime;
+use std::future::Future;
 use std::ops::Deref;
 use std::ops::Drop;
 
@@ -9,7 +14,7 @@ pub struct TestClient(Client);
 
 pub fn setup() -> TestClient {
     let rocket = api::build_rocket();
-    TestClient(Client::new(rocket).expect("Invalid rocket instance"))
+    TestClient(Client::tracked(rocket).expect("Invalid rocket instance"))
 }
 
 pub fn setup_untracked() -> TestClient {
@@ -17,6 +22,23 @@ pub fn setup_untracked() -> TestClient {
     TestClient(Client::untracked(rocket).expect("Invalid rocket instance"))
 }
 
+/// Sets up an untracked client with `config` merged into the Rocket config
+pub fn setup_with(config: impl Provider) -> TestClient {
+    let rocket = api::build_rocket();
+    let figment = rocket.figment().clone().merge(config);
+    TestClient(Client::untracked(rocket.configure(figment)).expect("Invalid rocket instance"))
+}
+
+/// Runs a future to completion, for calling the async database API
+/// directly from a test
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Could not start runtime")
+        .block_on(future)
+}
+
 pub fn setup_mock_user(client: &TestClient) {
     let signup = SignupUser {
         email: "foo@example.com".into(),
@@ -24,7 +46,7 @@ pub fn setup_mock_user(client: &TestClient) {
         password: "password1234".into(),
     };
     let response = client
-        .post("/signup")
+        .post("/v1/signup")
         .header(ContentType::JSON)
         .body(serde_json::to_string(&signup).unwrap())
         .dispatch();
@@ -32,14 +54,31 @@ pub fn setup_mock_user(client: &TestClient) {
 }
 
 pub fn get_mock_user_auth_token(client: &TestClient) -> Cookie<'static> {
+    get_mock_user_session(client).0
+}
+
+/// Logs in as the mock user, returning the `auth_token` cookie and the
+/// `X-CSRF-Token` header state-changing requests must send with it
+pub fn get_mock_user_session(client: &TestClient) -> (Cookie<'static>, Header<'static>) {
     let response = client
-        .post("/login")
+        .post("/v1/login")
         .header(ContentType::JSON)
         .header(Header::new("Authorization", "foo:password1234"))
         .dispatch();
     assert_eq!(response.status(), Status::Ok);
 
-    response.cookies()[0].clone().into_owned()
+    let cookies = response.cookies();
+    let auth_cookie = cookies
+        .get("auth_token")
+        .expect("No auth_token cookie")
+        .clone();
+    let csrf_token = cookies
+        .get("csrf_token")
+        .expect("No csrf_token cookie")
+        .value()
+        .to_string();
+
+    (auth_cookie, Header::new("X-CSRF-Token", csrf_token))
 }
 
 impl Deref for TestClient {
@@ -56,6 +95,6 @@ impl Drop for TestClient {
             .rocket()
             .state::<Database>()
             .expect("Failed to fetch db for cleanup");
-        db.to_inner().drop(None).expect("Failed to drop db");
+        block_on(db.to_inner().drop(None)).expect("Failed to drop db");
     }
 }
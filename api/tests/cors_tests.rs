@@ -0,0 +1,113 @@
+use rocket::http::{Header, Method, Status};
+
+mod common;
+
+const ORIGIN: &str = "https://app.example.com";
+
+fn setup() -> common::TestClient {
+    common::setup_with(("cors_allowed_origins", vec![ORIGIN]))
+}
+
+#[test]
+fn test_preflight() {
+    let client = setup();
+
+    let response = client
+        .req(Method::Options, "/v1/self")
+        .header(Header::new("Origin", ORIGIN))
+        .header(Header::new("Access-Control-Request-Method", "PATCH"))
+        .header(Header::new(
+            "Access-Control-Request-Headers",
+            "content-type, if-match",
+        ))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+
+    let headers = response.headers();
+    assert_eq!(headers.get_one("Access-Control-Allow-Origin"), Some(ORIGIN));
+    assert_eq!(
+        headers.get_one("Access-Control-Allow-Credentials"),
+        Some("true")
+    );
+    assert!(headers
+        .get_one("Access-Control-Allow-Methods")
+        .unwrap()
+        .contains("PATCH"));
+    assert_eq!(headers.get_one("Access-Control-Max-Age"), Some("3600"));
+    assert_eq!(headers.get_one("Vary"), Some("Origin"));
+}
+
+#[test]
+fn test_preflight_rejected() {
+    let client = setup();
+
+    let response = client
+        .req(Method::Options, "/v1/self")
+        .header(Header::new("Origin", "https://evil.example.com"))
+        .header(Header::new("Access-Control-Request-Method", "PATCH"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        None
+    );
+
+    let response = client
+        .req(Method::Options, "/v1/self")
+        .header(Header::new("Origin", ORIGIN))
+        .header(Header::new("Access-Control-Request-Method", "PATCH"))
+        .header(Header::new(
+            "Access-Control-Request-Headers",
+            "x-not-allowed",
+        ))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+
+    // No route serves DELETE /v1/self
+    let response = client
+        .req(Method::Options, "/v1/self")
+        .header(Header::new("Origin", ORIGIN))
+        .header(Header::new("Access-Control-Request-Method", "DELETE"))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn test_cors_headers_on_responses() {
+    let client = setup();
+
+    let response = client
+        .get("/v1/self")
+        .header(Header::new("Origin", ORIGIN))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        Some(ORIGIN)
+    );
+    assert!(response
+        .headers()
+        .get_one("Access-Control-Expose-Headers")
+        .unwrap()
+        .contains("X-Request-Id"));
+
+    let response = client
+        .get("/v1/self")
+        .header(Header::new("Origin", "https://evil.example.com"))
+        .dispatch();
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        None
+    );
+}
+
+#[test]
+fn test_any_origin_with_credentials_is_rejected() {
+    let rocket = api::build_rocket();
+    let figment = rocket
+        .figment()
+        .clone()
+        .merge(("cors_allowed_origins", vec!["*"]));
+
+    assert!(rocket::execute(rocket.configure(figment).ignite()).is_err());
+}
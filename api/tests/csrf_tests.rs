@@ -0,0 +1,95 @@
+use rocket::http::{ContentType, Header, Status};
+use serde_json::Value;
+
+mod common;
+
+#[test]
+fn test_login_sets_csrf_cookie() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+
+    let response = client
+        .post("/v1/login")
+        .header(Header::new("Authorization", "foo:password1234"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let cookie = response.cookies().get("csrf_token").unwrap().clone();
+    assert_eq!(cookie.http_only(), Some(false));
+    assert_eq!(cookie.secure(), Some(true));
+    assert!(!cookie.value().is_empty());
+}
+
+#[test]
+fn test_missing_csrf_token() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let auth_cookie = common::get_mock_user_auth_token(&client);
+
+    let response = client
+        .patch("/v1/self")
+        .header(ContentType::JSON)
+        .cookie(auth_cookie.clone())
+        .body(r#"{"email": "bar@example.com"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let body: Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    assert_eq!(body["code"], "csrf_token_missing");
+
+    // Safe methods don't need the token
+    let response = client.get("/v1/self").cookie(auth_cookie).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn test_bad_csrf_token() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let auth_cookie = common::get_mock_user_auth_token(&client);
+
+    let response = client
+        .post("/v1/logout")
+        .header(Header::new("X-CSRF-Token", "forged"))
+        .cookie(auth_cookie)
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let body: Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    assert_eq!(body["code"], "csrf_token_invalid");
+}
+
+#[test]
+fn test_logout_revokes_csrf_token() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let (auth_cookie, csrf_header) = common::get_mock_user_session(&client);
+
+    let response = client
+        .post("/v1/logout")
+        .header(csrf_header)
+        .cookie(auth_cookie.clone())
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+
+    let removed = response.cookies().get("csrf_token").unwrap().clone();
+    assert!(removed.value().is_empty());
+
+    let response = client.get("/v1/self").cookie(auth_cookie).dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[test]
+fn test_same_site_config() {
+    let client = common::setup_with(("session_cookie_same_site", "none"));
+    common::setup_mock_user(&client);
+
+    let response = client
+        .post("/v1/login")
+        .header(Header::new("Authorization", "foo:password1234"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let cookie = response.cookies().get("csrf_token").unwrap().clone();
+    assert_eq!(cookie.same_site(), Some(rocket::http::SameSite::None));
+}
@@ -0,0 +1,274 @@
+use api::common::query::{Model, Update};
+use api::common::user::User;
+use api::db::err::DBError;
+use api::db::{
+    BulkWriteResult, DBClient, Database, DatabaseAccess, ResilienceOptions, WriteOperation,
+};
+use mongodb::bson::{doc, Document};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+mod common;
+
+fn db(client: &common::TestClient) -> &Database {
+    client.rocket().state::<Database>().unwrap()
+}
+
+/// Inserts `_id` 1, then 1 again, then 2
+fn duplicate_inserts(
+    client: &common::TestClient,
+    ordered: bool,
+) -> Result<BulkWriteResult, DBError> {
+    let (first, second) = (doc! { "_id": 1 }, doc! { "_id": 2 });
+    let operations = vec![
+        WriteOperation::InsertOne { document: &first },
+        WriteOperation::InsertOne { document: &first },
+        WriteOperation::InsertOne { document: &second },
+    ];
+
+    common::block_on(db(client).bulk_write("people", &operations, ordered))
+}
+
+fn stored_ids(client: &common::TestClient) -> Vec<i64> {
+    let people: Vec<Value> =
+        common::block_on(db(client).find("people", &json!({}), None, None)).unwrap();
+    let mut ids: Vec<i64> = people.iter().filter_map(|p| p["_id"].as_i64()).collect();
+    ids.sort_unstable();
+    ids
+}
+
+#[test]
+fn test_bulk_write() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let db = db(&client);
+
+    let fields = User::fields();
+    let query = fields.username.eq("foo");
+    let update = Update::new().set(fields.email, "bar@example.com".to_string());
+    let (person, missing) = (json!({ "name": "Foo" }), json!({ "name": "Bar" }));
+    let upsert = json!({ "$set": { "age": 30 } });
+
+    let result = common::block_on(db.bulk_write(
+        "users",
+        &[
+            WriteOperation::UpdateOne {
+                query: &query,
+                update: &update,
+                upsert: false,
+            },
+            WriteOperation::UpdateOne {
+                query: &missing,
+                update: &upsert,
+                upsert: true,
+            },
+            WriteOperation::InsertOne { document: &person },
+            WriteOperation::DeleteMany { query: &person },
+        ],
+        true,
+    ))
+    .unwrap();
+
+    assert_eq!(
+        result,
+        BulkWriteResult {
+            inserted_count: 1,
+            matched_count: 1,
+            modified_count: 1,
+            deleted_count: 1,
+            upserted_count: 1,
+        }
+    );
+
+    let user: User = common::block_on(db.find_one("users", &query))
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.email, "bar@example.com");
+}
+
+#[test]
+fn test_ordered_bulk_write_stops_at_failure() {
+    let client = common::setup_untracked();
+
+    match duplicate_inserts(&client, true) {
+        Err(DBError::BulkWriteError { result, failures }) => {
+            assert_eq!(result.inserted_count, 1);
+            assert_eq!(failures.len(), 1);
+            assert_eq!(failures[0].index, Some(1));
+        }
+        other => panic!("Expected a bulk write error, got {:?}", other),
+    }
+    assert_eq!(stored_ids(&client), vec![1]);
+}
+
+#[test]
+fn test_unordered_bulk_write_continues_past_failure() {
+    let client = common::setup_untracked();
+
+    let error = duplicate_inserts(&client, false).unwrap_err();
+    assert!(error.is_duplicate_key());
+    match error {
+        DBError::BulkWriteError { result, failures } => {
+            assert_eq!(result.inserted_count, 2);
+            assert_eq!(failures[0].index, Some(1));
+        }
+        other => panic!("Expected a bulk write error, got {:?}", other),
+    }
+    assert_eq!(stored_ids(&client), vec![1, 2]);
+}
+
+/// Stores `{ _id: 1, name: "Foo" }`, returning whether the deployment can
+/// roll transactions back
+fn setup_transaction(client: &common::TestClient) -> bool {
+    let person = doc! { "_id": 1, "name": "Foo" };
+    common::block_on(db(client).insert_one("people", &person)).unwrap();
+
+    common::block_on(db(client).supports_transactions()).unwrap()
+}
+
+#[test]
+fn test_transaction_commits() {
+    let client = common::setup_untracked();
+    setup_transaction(&client);
+
+    let renamed = common::block_on(db(&client).transaction(|tx| {
+        Box::pin(async move {
+            let person = doc! { "_id": 2, "name": "Bar" };
+            tx.insert_one("people", &person).await?;
+            tx.update_one(
+                "people",
+                &json!({ "_id": 1 }),
+                &json!({ "$set": { "name": "Baz" } }),
+            )
+            .await?;
+            tx.find_one::<Document>("people", &json!({ "name": "Baz" }))
+                .await
+        })
+    }))
+    .unwrap();
+
+    assert!(renamed.is_some());
+    assert_eq!(stored_ids(&client), vec![1, 2]);
+}
+
+#[test]
+fn test_transaction_rolls_back_on_error() {
+    let client = common::setup_untracked();
+    let atomic = setup_transaction(&client);
+
+    let result: Result<(), DBError> = common::block_on(db(&client).transaction(|tx| {
+        Box::pin(async move {
+            let person = doc! { "_id": 2, "name": "Bar" };
+            tx.insert_one("people", &person).await?;
+            Err(DBError::BsonDocumentError)
+        })
+    }));
+
+    assert!(matches!(result, Err(DBError::BsonDocumentError)));
+    // Without transaction support the insert was made directly
+    let expected = if atomic { vec![1] } else { vec![1, 2] };
+    assert_eq!(stored_ids(&client), expected);
+}
+
+#[test]
+fn test_transaction_retries_transient_errors() {
+    let client = common::setup_untracked();
+    let atomic = setup_transaction(&client);
+    let outer = db(&client);
+    let query = json!({ "_id": 1 });
+    let mut attempts = 0;
+
+    common::block_on(outer.transaction(|tx| {
+        attempts += 1;
+        let (attempt, query) = (attempts, &query);
+
+        Box::pin(async move {
+            tx.find_one::<Document>("people", query).await?;
+            if attempt == 1 {
+                // A write made after the transaction's snapshot was taken
+                // makes its own write to the document conflict
+                let update = json!({ "$set": { "name": "Outside" } });
+                outer.update_one("people", query, &update).await?;
+            }
+            let update = json!({ "$set": { "name": "Inside" } });
+            tx.update_one("people", query, &update).await
+        })
+    }))
+    .unwrap();
+
+    assert_eq!(attempts, if atomic { 2 } else { 1 });
+    let person: Document = common::block_on(outer.find_one("people", &query))
+        .unwrap()
+        .unwrap();
+    assert_eq!(person.get_str("name").unwrap(), "Inside");
+}
+
+#[test]
+fn test_open_breaker_fails_reads_fast() {
+    common::block_on(async {
+        // Nothing listens on the discard port, so every operation fails
+        let client = DBClient::init("mongodb://127.0.0.1:9/?serverSelectionTimeoutMS=100").await;
+        let db = client.get_database_with_options(
+            "appdb",
+            ResilienceOptions {
+                read_retries: 1,
+                read_backoff: Duration::from_secs(10),
+                breaker_threshold: 1,
+                ..ResilienceOptions::default()
+            },
+        );
+
+        let person = doc! { "name": "Foo" };
+        assert!(db.insert_one("people", &person).await.is_err());
+        assert!(db.retry_after().is_some());
+
+        let start = Instant::now();
+        let result = db.find_one::<Document>("people", &person).await;
+        assert!(matches!(result, Err(DBError::Unavailable { .. })));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    });
+}
+
+#[test]
+fn test_versioned_writes() {
+    let client = common::setup_untracked();
+    let db = db(&client);
+
+    // Inserts start the counter whatever the document says
+    let person = doc! { "_id": 1, "name": "Foo", "version": 5 };
+    let inserted: Document = common::block_on(db.insert_one("users", &person)).unwrap();
+    assert_eq!(inserted.get_i64("version").unwrap(), 0);
+    assert!(inserted.get_datetime("updated").is_ok());
+
+    let query = json!({ "_id": 1 });
+    let replacement = json!({ "name": "Bar", "note": "$literal" });
+    common::block_on(db.update_one("users", &query, &replacement)).unwrap();
+    common::block_on(db.update_one("users", &query, &json!({ "$set": { "age": 30 } }))).unwrap();
+
+    let stored: Document = common::block_on(db.find_one("users", &query))
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.get_i32("_id").unwrap(), 1);
+    assert_eq!(stored.get_str("name").unwrap(), "Bar");
+    assert_eq!(stored.get_str("note").unwrap(), "$literal");
+    assert_eq!(stored.get_i64("version").unwrap(), 2);
+    assert!(stored.get_datetime("updated").unwrap() >= inserted.get_datetime("updated").unwrap());
+}
+
+#[test]
+fn test_unversioned_writes_are_unchanged() {
+    let client = common::setup_untracked();
+    let db = db(&client);
+
+    let person = doc! { "_id": 1, "name": "Foo" };
+    common::block_on(db.insert_one("people", &person)).unwrap();
+
+    let query = json!({ "_id": 1 });
+    common::block_on(db.update_one("people", &query, &json!({ "name": "Bar" }))).unwrap();
+    common::block_on(db.update_one("people", &query, &json!({ "$set": { "age": 30 } }))).unwrap();
+
+    let stored: Document = common::block_on(db.find_one("people", &query))
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored, doc! { "_id": 1, "name": "Bar", "age": 30 });
+}
@@ -0,0 +1,103 @@
+use rocket::http::{Accept, ContentType, Header, MediaType, Status};
+use rocket::local::blocking::{Client, LocalResponse};
+use rocket::{get, routes};
+use serde_json::Value;
+
+mod common;
+
+fn body(response: LocalResponse) -> Value {
+    serde_json::from_str(&response.into_string().unwrap()).unwrap()
+}
+
+#[get("/method-not-allowed")]
+fn method_not_allowed() -> Status {
+    Status::MethodNotAllowed
+}
+
+#[test]
+fn test_auth_error_codes() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+
+    let response = client
+        .post("/login")
+        .header(Header::new("Authorization", "foo:wrongpassword"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(body(response)["code"], "invalid_credentials");
+
+    let response = client
+        .post("/login")
+        .header(Header::new("Authorization", "foo:password1234"))
+        .header(Header::new("Authorization", "foo:password1234"))
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(body(response)["code"], "multiple_authorization_headers");
+}
+
+#[test]
+fn test_signup_conflict_is_json() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+
+    let response = client
+        .post("/signup")
+        .header(ContentType::JSON)
+        .body(r#"{"username": "foo", "password": "password1234", "email": "foo@example.com"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::PreconditionFailed);
+
+    let body = body(response);
+    assert_eq!(body["code"], "username_taken");
+    assert_eq!(body["status_code"], 412);
+}
+
+#[test]
+fn test_malformed_body_is_json() {
+    let client = common::setup();
+
+    let response = client
+        .post("/signup")
+        .header(ContentType::JSON)
+        .body("{not json")
+        .dispatch();
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    assert!(body(response)["code"].is_string());
+}
+
+#[test]
+fn test_problem_json() {
+    let client = common::setup();
+
+    let response = client
+        .get("/does-not-exist")
+        .header(Accept::new(vec![MediaType::new(
+            "application",
+            "problem+json",
+        )
+        .into()]))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(
+        response.content_type(),
+        Some(ContentType::new("application", "problem+json"))
+    );
+
+    let body = body(response);
+    assert_eq!(body["status"], 404);
+    assert_eq!(body["code"], "not_found");
+}
+
+#[test]
+fn test_default_catcher() {
+    let rocket = api::build_rocket().mount("/", routes![method_not_allowed]);
+    let client = Client::untracked(rocket).expect("Invalid rocket instance");
+
+    let response = client.get("/method-not-allowed").dispatch();
+    assert_eq!(response.status(), Status::MethodNotAllowed);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+
+    let body = body(response);
+    assert_eq!(body["code"], "method_not_allowed");
+    assert_eq!(body["status_code"], 405);
+}
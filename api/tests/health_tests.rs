@@ -0,0 +1,36 @@
+use api::db::migrations::Migrator;
+use api::db::Database;
+use rocket::http::Status;
+use serde_json::Value;
+
+mod common;
+
+#[test]
+fn test_live() {
+    let client = common::setup();
+
+    let response = client.get("/health/live").dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn test_ready_requires_migrations() {
+    let client = common::setup();
+
+    let response = client.get("/health/ready").dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+
+    let body: Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    assert_eq!(body["checks"]["database"]["status"], "up");
+    assert_eq!(body["checks"]["migrations"]["status"], "down");
+
+    let db = client.rocket().state::<Database>().unwrap();
+    common::block_on(Migrator::new(db).up()).unwrap();
+
+    let response = client.get("/health/ready").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let body: Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    assert_eq!(body["status"], "up");
+}
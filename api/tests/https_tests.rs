@@ -0,0 +1,210 @@
+use api::common::audit::AuditEventBrief;
+use api::https::TlsTerminator;
+use rocket::figment::Figment;
+use rocket::http::uri::Host;
+use rocket::http::{Header, Status};
+use rocket::local::blocking::{Client, LocalRequest};
+use rocket::tokio::{
+    self,
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use rocket::Config;
+use rustls::{Certificate, ClientConfig, RootCertStore, ServerName};
+use std::convert::TryFrom;
+use std::fs;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+mod common;
+
+fn redirect_client(https_port: u16) -> Client {
+    let config = Config {
+        port: https_port,
+        ..Config::debug_default()
+    };
+    Client::untracked(api::https::redirect_listener(
+        &config,
+        8080,
+        "api.example.com",
+    ))
+    .expect("Invalid rocket instance")
+}
+
+/// Sets the host of `request` as a server would from its `Host` header
+fn with_host<'c>(mut request: LocalRequest<'c>, host: &'static str) -> LocalRequest<'c> {
+    request
+        .inner_mut()
+        .set_host(Host::parse(host).expect("Invalid host"));
+    request
+}
+
+#[test]
+fn test_redirect_listener() {
+    let client = redirect_client(443);
+
+    let response =
+        with_host(client.get("/v1/self?fields=email"), "api.example.com:8080").dispatch();
+    assert_eq!(response.status(), Status::PermanentRedirect);
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some("https://api.example.com/v1/self?fields=email")
+    );
+
+    let response = with_host(client.patch("/v1/self"), "api.example.com").dispatch();
+    assert_eq!(response.status(), Status::PermanentRedirect);
+}
+
+#[test]
+fn test_redirect_listener_ignores_host() {
+    let client = redirect_client(8443);
+
+    let response = with_host(client.get("/login"), "evil.example.net").dispatch();
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some("https://api.example.com:8443/login")
+    );
+
+    let response = client.get("/").dispatch();
+    assert_eq!(response.status(), Status::PermanentRedirect);
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some("https://api.example.com:8443/")
+    );
+}
+
+fn tls_figment(certs: &str, key: &str) -> Figment {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/tls");
+    Figment::new()
+        .merge(("tls.certs", dir.join(certs).to_str().unwrap()))
+        .merge(("tls.key", dir.join(key).to_str().unwrap()))
+}
+
+#[test]
+fn test_check_tls() {
+    assert_eq!(
+        api::https::check_tls(&tls_figment("certs.pem", "key.pem")),
+        Ok(())
+    );
+    // The certificate chain is no key
+    assert!(api::https::check_tls(&tls_figment("certs.pem", "certs.pem")).is_err());
+    assert!(api::https::check_tls(&tls_figment("certs.pem", "missing.pem")).is_err());
+    assert!(api::https::check_tls(&Figment::new()).is_err());
+}
+
+/// Connects to `address` over TLS, returning the connection and the
+/// certificate the server presented
+async fn connect_tls(address: SocketAddr) -> (TlsStream<TcpStream>, Vec<u8>) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/tls");
+    let mut roots = RootCertStore::empty();
+    for name in ["certs.pem", "renewed-certs.pem"] {
+        let mut reader = BufReader::new(fs::File::open(dir.join(name)).unwrap());
+        for cert in rustls_pemfile::certs(&mut reader).unwrap() {
+            roots.add(&Certificate(cert)).unwrap();
+        }
+    }
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let stream = TcpStream::connect(address).await.unwrap();
+    let stream = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await
+        .unwrap();
+    let cert = stream.get_ref().1.peer_certificates().unwrap()[0].0.clone();
+    (stream, cert)
+}
+
+#[test]
+fn test_tls_terminator() {
+    common::block_on(async {
+        let api = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_address = api.local_addr().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let config = api::https::load_tls(&tls_figment("certs.pem", "key.pem")).unwrap();
+        let terminator = TlsTerminator::new(config);
+        let clients = terminator.clients();
+        let running = tokio::spawn(terminator.clone().run(listener, api_address));
+
+        let (mut client, first_cert) = connect_tls(address).await;
+        client.write_all(b"ping").await.unwrap();
+        let (mut forwarded, remote) = api.accept().await.unwrap();
+        let mut received = [0; 4];
+        forwarded.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"ping");
+        forwarded.write_all(b"pong").await.unwrap();
+        client.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"pong");
+
+        // The API can tell who the forwarded connection belongs to
+        let local = client.get_ref().0.local_addr().unwrap();
+        assert_eq!(clients.client(remote), Some(local));
+
+        // New connections get the new certificate, open ones carry on
+        let renewed = tls_figment("renewed-certs.pem", "renewed-key.pem");
+        terminator.reload(api::https::load_tls(&renewed).unwrap());
+        let (_, renewed_cert) = connect_tls(address).await;
+        assert_ne!(renewed_cert, first_cert);
+
+        client.write_all(b"ping").await.unwrap();
+        forwarded.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"ping");
+
+        // Connections are forgotten once closed
+        drop(client);
+        forwarded.read_to_end(&mut Vec::new()).await.unwrap();
+        drop(forwarded);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(clients.client(remote), None);
+
+        running.abort();
+    });
+}
+
+#[test]
+fn test_forwarded_client_ip() {
+    let client = common::setup_with(("trusted_proxies", vec!["10.0.0.0/8"]));
+    common::setup_mock_user(&client);
+
+    let response = client
+        .post("/v1/login")
+        .remote("10.0.0.2:4000".parse().unwrap())
+        .header(Header::new("Authorization", "foo:password1234"))
+        .header(Header::new(
+            "X-Forwarded-For",
+            "198.51.100.1, 203.0.113.7, 10.0.0.3",
+        ))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // The header is ignored from clients which aren't trusted proxies
+    let response = client
+        .post("/v1/login")
+        .remote("192.0.2.1:4000".parse().unwrap())
+        .header(Header::new("Authorization", "foo:password1234"))
+        .header(Header::new("X-Forwarded-For", "198.51.100.1"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let auth_cookie = response.cookies().get("auth_token").unwrap().clone();
+
+    let response = client
+        .get("/v1/self/security-events")
+        .cookie(auth_cookie)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let events: Vec<AuditEventBrief> =
+        serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].ip.as_deref(), Some("192.0.2.1"));
+    assert_eq!(events[1].ip.as_deref(), Some("203.0.113.7"));
+}
@@ -1,3 +1,7 @@
+use api::common::query::Model;
+use api::common::user::User;
+use api::db::{Database, DatabaseAccess};
+use chrono::Utc;
 use rocket::http::{ContentType, Header, Status};
 
 mod common;
@@ -13,9 +17,10 @@ fn test_basic_login() {
         .dispatch();
     assert_eq!(response.status(), Status::Ok);
 
-    // Check auth_token cookie is set
-    assert_eq!(response.cookies().len(), 1);
-    assert_eq!(response.cookies()[0].name(), "auth_token");
+    // Check the auth_token and csrf_token cookies are set
+    assert_eq!(response.cookies().iter().count(), 2);
+    assert!(response.cookies().get("auth_token").is_some());
+    assert!(response.cookies().get("csrf_token").is_some());
 }
 
 #[test]
@@ -141,3 +146,37 @@ fn test_tampered_cookie() {
         .dispatch();
     assert_eq!(response.status(), Status::Unauthorized);
 }
+
+#[test]
+fn test_login_records_metadata() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let db = client.rocket().state::<Database>().unwrap();
+    let query = User::fields().username.eq("foo");
+
+    let before = common::block_on(db.find_one::<User>("users", &query))
+        .unwrap()
+        .unwrap();
+    assert_eq!(before.login_count, 0);
+
+    let started = Utc::now().timestamp_millis();
+    for _ in 0..2 {
+        let response = client
+            .post("/v1/login")
+            .header(Header::new("Authorization", "foo:password1234"))
+            .header(Header::new("User-Agent", "curl/7.74.0"))
+            .remote("192.0.2.1:4000".parse().unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    let after = common::block_on(db.find_one::<User>("users", &query))
+        .unwrap()
+        .unwrap();
+    assert_eq!(after.login_count, 2);
+    assert_eq!(after.last_login_ip.as_deref(), Some("192.0.2.1"));
+    assert_eq!(after.last_login_user_agent.as_deref(), Some("curl/7.74.0"));
+    assert!(after.last_login.timestamp_millis() >= started);
+    assert!(after.updated.timestamp_millis() >= started);
+    assert_eq!(after.created, before.created);
+}
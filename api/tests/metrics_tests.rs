@@ -0,0 +1,20 @@
+use rocket::http::Status;
+
+mod common;
+
+#[test]
+fn test_metrics() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    common::get_mock_user_auth_token(&client);
+
+    let response = client.get("/metrics").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let body = response.into_string().unwrap();
+    assert!(body.contains(r#"http_requests_total{method="POST",route="/v1/login",status="200"}"#));
+    assert!(body.contains(r#"auth_attempts_total{guard="LoginAuth",outcome="success"}"#));
+    assert!(body.contains(
+        r#"db_operation_duration_seconds_count{collection="users",operation="find_one"}"#
+    ));
+}
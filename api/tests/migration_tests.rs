@@ -0,0 +1,156 @@
+use api::common::query::Model;
+use api::common::user::User;
+use api::db::err::DBError;
+use api::db::migrations::{self, MigrationError, Migrator};
+use api::db::{Database, DatabaseAccess};
+use mongodb::bson::{doc, Bson, Document};
+use rocket::futures::future::BoxFuture;
+use rocket::tokio::time;
+use std::time::Duration;
+
+mod common;
+
+#[test]
+fn test_migrations_apply_once() {
+    let client = common::setup();
+    let db = client.rocket().state::<Database>().unwrap();
+
+    let expected: Vec<u32> = migrations::all().iter().map(|m| m.version).collect();
+
+    let applied = common::block_on(Migrator::new(db).up()).unwrap();
+    assert_eq!(applied, expected);
+
+    let applied = common::block_on(Migrator::new(db).up()).unwrap();
+    assert!(applied.is_empty());
+
+    let status = common::block_on(Migrator::new(db).status()).unwrap();
+    assert!(status.iter().all(|m| m.applied.is_some()));
+}
+
+#[test]
+fn test_migrations_locked() {
+    let client = common::setup();
+    let db = client.rocket().state::<Database>().unwrap();
+
+    let blocking = migrations::Migration {
+        version: 1,
+        name: "blocking",
+        up: expect_locked,
+    };
+
+    let applied = common::block_on(Migrator::with_migrations(db, vec![blocking]).up()).unwrap();
+    assert_eq!(applied, vec![1]);
+}
+
+#[test]
+fn test_migration_lock_renewed() {
+    let client = common::setup();
+    let db = client.rocket().state::<Database>().unwrap();
+
+    let slow = migrations::Migration {
+        version: 1,
+        name: "slow",
+        up: expect_lock_renewed,
+    };
+
+    let migrator =
+        Migrator::with_migrations(db, vec![slow]).with_lock_renewal(Duration::from_millis(50));
+    let applied = common::block_on(migrator.up()).unwrap();
+    assert_eq!(applied, vec![1]);
+}
+
+#[test]
+fn test_migrations_convert_legacy_dates() {
+    let client = common::setup();
+    let db = client.rocket().state::<Database>().unwrap();
+    let users = db.to_inner().collection::<Document>("users");
+
+    let legacy = doc! {
+        "username": "foo",
+        "email": "foo@example.com",
+        "password_hash": "hash",
+        "salt": "salt",
+        "last_login": "2020-12-31T13:00:00.123+01:00",
+        "created": "2020-12-31 12:00:00",
+        "updated": 1_609_416_000_000i64,
+    };
+    common::block_on(users.insert_one(legacy, None)).unwrap();
+
+    // Legacy documents can be read before they are converted
+    let query = User::fields().username.eq("foo");
+    let user = common::block_on(db.find_one::<User>("users", &query)).unwrap();
+    let created = user.expect("The legacy user was not found").created;
+
+    common::block_on(Migrator::new(db).up()).unwrap();
+
+    let stored = common::block_on(users.find_one(doc! { "username": "foo" }, None))
+        .unwrap()
+        .unwrap();
+    for key in ["last_login", "created", "updated"] {
+        assert!(
+            matches!(stored.get(key), Some(Bson::DateTime(_))),
+            "{} was not converted",
+            key
+        );
+    }
+
+    let user = common::block_on(db.find_one::<User>("users", &query))
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.created, created);
+    assert_eq!(user.updated, created);
+    assert_eq!(user.last_login.timestamp_millis(), 1_609_416_000_123);
+}
+
+#[test]
+fn test_migrations_fail_on_invalid_dates() {
+    let client = common::setup();
+    let db = client.rocket().state::<Database>().unwrap();
+    let users = db.to_inner().collection::<Document>("users");
+
+    let invalid = doc! { "username": "foo", "created": "31/12/2020" };
+    common::block_on(users.insert_one(invalid, None)).unwrap();
+
+    match common::block_on(Migrator::new(db).up()) {
+        Err(MigrationError::Failed {
+            version: 4,
+            source: DBError::InvalidData(_),
+            ..
+        }) => {}
+        other => panic!("Expected the date migration to fail, got {:?}", other),
+    }
+
+    // The date is kept for it to be fixed
+    let stored = common::block_on(users.find_one(doc! { "username": "foo" }, None))
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.get_str("created"), Ok("31/12/2020"));
+}
+
+/// Migration which checks that migrations can't run while it is applied
+fn expect_locked(db: &Database) -> BoxFuture<'_, Result<(), DBError>> {
+    Box::pin(async move {
+        match Migrator::with_migrations(db, Vec::new()).up().await {
+            Err(MigrationError::Locked(_)) => Ok(()),
+            _ => panic!("Expected migrations to be locked"),
+        }
+    })
+}
+
+/// Migration which checks that the lock is renewed while it is applied
+fn expect_lock_renewed(db: &Database) -> BoxFuture<'_, Result<(), DBError>> {
+    Box::pin(async move {
+        let acquired = || async {
+            let lock: Option<Document> = db.find_one("migration_lock", &doc! {}).await?;
+            Ok::<_, DBError>(lock.unwrap().get_datetime("acquired").cloned().unwrap())
+        };
+
+        let before = acquired().await?;
+        time::sleep(Duration::from_millis(300)).await;
+        assert!(
+            acquired().await? > before,
+            "Expected the lock to be renewed"
+        );
+        Ok(())
+    })
+}
@@ -0,0 +1,94 @@
+use rocket::http::{ContentType, Status};
+use std::fs;
+use std::path::Path;
+
+mod common;
+
+/// Committed copy of the spec. Run the tests with `UPDATE_SNAPSHOTS=1` to
+/// rewrite it after an intended API change
+const SNAPSHOT: &str = "tests/snapshots/openapi.json";
+
+/// Routes serving the spec itself, Swagger UI and CORS preflights, which
+/// are left out of it
+const UNDOCUMENTED: &[&str] = &["/openapi.json", "/docs", "/docs/<asset>", "/<_..>"];
+
+fn fetch_spec(client: &common::TestClient) -> String {
+    let response = client.get("/openapi.json").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+
+    response.into_string().unwrap()
+}
+
+#[test]
+fn test_openapi_matches_snapshot() {
+    let client = common::setup_untracked();
+    let spec = fetch_spec(&client);
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(SNAPSHOT);
+
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &spec).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path)
+        .expect("Missing OpenAPI snapshot, run the tests with UPDATE_SNAPSHOTS=1");
+    assert!(
+        spec == expected,
+        "The OpenAPI document changed. If this was intended, run the tests with \
+         UPDATE_SNAPSHOTS=1 and commit {}",
+        SNAPSHOT
+    );
+}
+
+#[test]
+fn test_openapi_documents_every_route() {
+    let client = common::setup_untracked();
+    let spec: serde_json::Value = serde_json::from_str(&fetch_spec(&client)).unwrap();
+    let paths = spec["paths"].as_object().unwrap();
+
+    for route in client.rocket().routes() {
+        let path = route.uri.path();
+        if UNDOCUMENTED.contains(&path) {
+            continue;
+        }
+
+        let method = route.method.as_str().to_lowercase();
+        assert!(
+            paths.get(path).and_then(|p| p.get(&method)).is_some(),
+            "{} {} is missing from the OpenAPI document",
+            route.method,
+            path
+        );
+    }
+}
+
+#[test]
+fn test_docs() {
+    let client = common::setup_untracked();
+
+    let response = client.get("/docs").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::HTML));
+    let page = response.into_string().unwrap();
+    assert!(!page.contains("https://"));
+
+    // The page loads its assets from this server only
+    for (asset, content_type) in [
+        ("/docs/swagger-ui-bundle.js", ContentType::JavaScript),
+        ("/docs/swagger-initializer.js", ContentType::JavaScript),
+        ("/docs/swagger-ui.css", ContentType::CSS),
+    ] {
+        assert!(page.contains(asset));
+        let response = client.get(asset).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(content_type));
+    }
+
+    let response = client.get("/docs/swagger-initializer.js").dispatch();
+    assert!(response.into_string().unwrap().contains("/openapi.json"));
+
+    let response = client.get("/docs/index.html").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
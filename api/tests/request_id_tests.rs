@@ -0,0 +1,33 @@
+use rocket::http::{Header, Status};
+use serde_json::Value;
+
+mod common;
+
+#[test]
+fn test_request_id_echoed() {
+    let client = common::setup();
+
+    let response = client
+        .get("/self")
+        .header(Header::new("X-Request-Id", "abc-123"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(response.headers().get_one("X-Request-Id"), Some("abc-123"));
+
+    let body: Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    assert_eq!(body["request_id"], "abc-123");
+}
+
+#[test]
+fn test_request_id_generated() {
+    let client = common::setup();
+
+    let response = client
+        .get("/health/live")
+        .header(Header::new("X-Request-Id", "not a valid id"))
+        .dispatch();
+
+    let id = response.headers().get_one("X-Request-Id").unwrap();
+    assert_ne!(id, "not a valid id");
+    assert!(!id.is_empty());
+}
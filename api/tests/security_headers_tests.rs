@@ -0,0 +1,128 @@
+use rocket::http::{Header, Status};
+
+mod common;
+
+#[test]
+fn test_security_headers() {
+    let client = common::setup_untracked();
+
+    let response = client.get("/health/live").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let headers = response.headers();
+    // HSTS is only sent over HTTPS
+    assert_eq!(headers.get_one("Strict-Transport-Security"), None);
+    assert_eq!(
+        headers.get_one("Content-Security-Policy"),
+        Some("default-src 'none'; frame-ancestors 'none'")
+    );
+    assert_eq!(headers.get_one("X-Content-Type-Options"), Some("nosniff"));
+    assert_eq!(headers.get_one("Referrer-Policy"), Some("no-referrer"));
+    assert!(headers.get_one("Permissions-Policy").is_some());
+    assert_eq!(headers.get_one("Cache-Control"), None);
+}
+
+#[test]
+fn test_hsts_behind_proxy() {
+    let client = common::setup_with(("trusted_proxies", vec!["10.0.0.0/8"]));
+
+    let response = client
+        .get("/health/live")
+        .remote("10.0.0.2:4000".parse().unwrap())
+        .header(Header::new("X-Forwarded-Proto", "https"))
+        .dispatch();
+    assert_eq!(
+        response.headers().get_one("Strict-Transport-Security"),
+        Some("max-age=31536000; includeSubDomains")
+    );
+
+    // The scheme is read at the hop of the client, not the first proxy
+    let response = client
+        .get("/health/live")
+        .remote("10.0.0.2:4000".parse().unwrap())
+        .header(Header::new("X-Forwarded-For", "198.51.100.1, 10.0.0.3"))
+        .header(Header::new("X-Forwarded-Proto", "http, https"))
+        .dispatch();
+    assert_eq!(
+        response.headers().get_one("Strict-Transport-Security"),
+        None
+    );
+
+    // A scheme prepended by the client is skipped like its address
+    let response = client
+        .get("/health/live")
+        .remote("10.0.0.2:4000".parse().unwrap())
+        .header(Header::new("X-Forwarded-For", "203.0.113.7, 198.51.100.1"))
+        .header(Header::new("X-Forwarded-Proto", "http, https"))
+        .dispatch();
+    assert_eq!(
+        response.headers().get_one("Strict-Transport-Security"),
+        Some("max-age=31536000; includeSubDomains")
+    );
+
+    // Any client could claim to have used HTTPS
+    let response = client
+        .get("/health/live")
+        .remote("192.0.2.1:4000".parse().unwrap())
+        .header(Header::new("X-Forwarded-Proto", "https"))
+        .dispatch();
+    assert_eq!(
+        response.headers().get_one("Strict-Transport-Security"),
+        None
+    );
+}
+
+#[test]
+fn test_no_store_authenticated() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let auth_cookie = common::get_mock_user_auth_token(&client);
+
+    let response = client.get("/v1/self").cookie(auth_cookie).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("no-store")
+    );
+
+    // Failed authentication doesn't reveal account data
+    let response = client.get("/v1/self").dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(response.headers().get_one("Cache-Control"), None);
+}
+
+#[test]
+fn test_route_override() {
+    let client = common::setup_untracked();
+
+    let response = client.get("/docs").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let csp = response
+        .headers()
+        .get_one("Content-Security-Policy")
+        .unwrap();
+    assert!(csp.contains("script-src 'self'"));
+    assert!(!csp.contains("unsafe-inline"));
+}
+
+#[test]
+fn test_config() {
+    let client = common::setup_with(
+        rocket::figment::Figment::new()
+            .merge(("strict_transport_security", ""))
+            .merge(("referrer_policy", "same-origin"))
+            .merge(("trusted_proxies", vec!["10.0.0.0/8"])),
+    );
+
+    let response = client
+        .get("/health/live")
+        .remote("10.0.0.2:4000".parse().unwrap())
+        .header(Header::new("X-Forwarded-Proto", "https"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let headers = response.headers();
+    assert_eq!(headers.get_one("Strict-Transport-Security"), None);
+    assert_eq!(headers.get_one("Referrer-Policy"), Some("same-origin"));
+}
@@ -1,5 +1,5 @@
 use api::common::user::{SignupUser, UserBrief};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use rocket::http::{ContentType, Status};
 
 mod common;
@@ -23,7 +23,7 @@ fn test_signup() {
         last_login: Utc::now(),
     };
 
-    let mut response = client
+    let response = client
         .post("/signup")
         .header(ContentType::JSON)
         .body(serde_json::to_string(&signup).unwrap())
@@ -33,13 +33,13 @@ fn test_signup() {
 
     let result: UserBrief = serde_json::from_str(
         &response
-            .body_string()
+            .into_string()
             .expect("Could not convert body to string"),
     )
     .expect("Could not deserialize response body");
 
     // Set values we cannot predict in this test
-    expected.id = result.id.clone();
+    expected.id = result.id;
     expected.created = result.created;
     expected.updated = result.updated;
     expected.last_login = result.last_login;
@@ -106,3 +106,39 @@ fn test_multi_signup() {
 
     assert_eq!(response.status(), Status::Ok);
 }
+
+#[test]
+fn test_signup_response_format() {
+    let client = common::setup();
+
+    let signup = SignupUser {
+        email: "scipii48@gmail.com".into(),
+        username: "scipi".into(),
+        password: "password1234".into(),
+    };
+
+    let response = client
+        .post("/v1/signup")
+        .header(ContentType::JSON)
+        .body(serde_json::to_string(&signup).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let body: serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+
+    // The id is a plain hex string rather than extended JSON
+    let id = body["_id"].as_str().expect("The id is not a string");
+    assert_eq!(id.len(), 24);
+    assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+
+    for key in ["last_login", "created", "updated"] {
+        let timestamp = body[key].as_str().expect("The timestamp is not a string");
+        assert!(
+            DateTime::parse_from_rfc3339(timestamp).is_ok(),
+            "{} is not RFC 3339: {}",
+            key,
+            timestamp
+        );
+    }
+}
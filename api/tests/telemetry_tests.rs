@@ -0,0 +1,63 @@
+use rocket::figment::Figment;
+use rocket::http::{Header, Status};
+use serde_json::Value;
+
+mod common;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+#[test]
+fn test_request_spans() {
+    let file = std::env::temp_dir().join(format!("spans-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&file);
+
+    let client = common::setup_with(
+        Figment::new()
+            .merge(("otel_exporter", "file"))
+            .merge(("otel_file", file.to_str().unwrap())),
+    );
+    common::setup_mock_user(&client);
+
+    let response = client
+        .post("/v1/login")
+        .header(Header::new("Authorization", "foo:password1234"))
+        .header(Header::new(
+            "traceparent",
+            format!("00-{}-{}-01", TRACE_ID, PARENT_SPAN_ID),
+        ))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let spans: Vec<Value> = std::fs::read_to_string(&file)
+        .expect("No spans were exported")
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Invalid span"))
+        .collect();
+    let _ = std::fs::remove_file(&file);
+
+    let request = spans
+        .iter()
+        .find(|span| span["name"] == "POST /v1/login")
+        .expect("No request span");
+    assert_eq!(request["kind"], "Server");
+    assert_eq!(request["trace_id"], TRACE_ID);
+    assert_eq!(request["parent_span_id"], PARENT_SPAN_ID);
+    assert_eq!(request["attributes"]["http.route"], "/v1/login");
+    assert_eq!(request["attributes"]["http.status_code"], "200");
+
+    let children: Vec<&Value> = spans
+        .iter()
+        .filter(|span| span["parent_span_id"] == request["span_id"])
+        .collect();
+    assert!(children.iter().all(|span| span["trace_id"] == TRACE_ID));
+
+    let db_span = children
+        .iter()
+        .find(|span| span["name"] == "find_one users")
+        .expect("No database span");
+    assert_eq!(db_span["attributes"]["db.system"], "mongodb");
+    assert_eq!(db_span["attributes"]["db.operation"], "find_one");
+
+    assert!(children.iter().any(|span| span["name"] == "password_hash"));
+}
@@ -0,0 +1,97 @@
+use api::common::user::{UpdateUser, UserBrief};
+use rocket::http::{ContentType, Header, Status};
+
+mod common;
+
+#[test]
+fn test_update_self() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let (auth_cookie, csrf_header) = common::get_mock_user_session(&client);
+
+    let update = UpdateUser {
+        username: None,
+        email: Some("bar@example.com".into()),
+    };
+
+    let response = client
+        .patch("/self")
+        .header(ContentType::JSON)
+        .header(csrf_header)
+        .cookie(auth_cookie)
+        .body(serde_json::to_string(&update).unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let result: UserBrief = serde_json::from_str(
+        &response
+            .into_string()
+            .expect("Could not convert body to string"),
+    )
+    .expect("Could not deserialize response body");
+
+    assert_eq!(result.username, "foo");
+    assert_eq!(result.email, "bar@example.com");
+}
+
+#[test]
+fn test_update_password_revokes_token() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let auth_cookie = common::get_mock_user_auth_token(&client);
+
+    let response = client
+        .patch("/self/password")
+        .header(ContentType::JSON)
+        .header(Header::new("Authorization", "foo:password1234"))
+        .body(r#"{"password": "password5678"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::SeeOther);
+
+    let response = client
+        .get("/self")
+        .header(ContentType::JSON)
+        .cookie(auth_cookie)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[test]
+fn test_update_self_if_match() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let (auth_cookie, csrf_header) = common::get_mock_user_session(&client);
+
+    let response = client
+        .get("/self")
+        .header(ContentType::JSON)
+        .cookie(auth_cookie.clone())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let etag = response.headers().get_one("ETag").unwrap().to_string();
+
+    let response = client
+        .patch("/self")
+        .header(ContentType::JSON)
+        .header(Header::new("If-Match", etag.clone()))
+        .header(csrf_header.clone())
+        .cookie(auth_cookie.clone())
+        .body(r#"{"email": "bar@example.com"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_ne!(response.headers().get_one("ETag"), Some(etag.as_str()));
+
+    // The first update changed the version, so the old tag is now stale
+    let response = client
+        .patch("/self")
+        .header(ContentType::JSON)
+        .header(Header::new("If-Match", etag))
+        .header(csrf_header)
+        .cookie(auth_cookie)
+        .body(r#"{"email": "baz@example.com"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::PreconditionFailed);
+}
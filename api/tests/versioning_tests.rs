@@ -0,0 +1,70 @@
+use rocket::http::{ContentType, Status};
+
+mod common;
+
+#[test]
+fn test_v1_is_current() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let auth_cookie = common::get_mock_user_auth_token(&client);
+
+    let response = client
+        .get("/v1/self")
+        .header(ContentType::JSON)
+        .cookie(auth_cookie)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Deprecation"), None);
+    assert_eq!(response.headers().get_one("Sunset"), None);
+}
+
+#[test]
+fn test_unversioned_is_deprecated() {
+    let client = common::setup_untracked();
+    common::setup_mock_user(&client);
+    let auth_cookie = common::get_mock_user_auth_token(&client);
+
+    let response = client
+        .get("/self")
+        .header(ContentType::JSON)
+        .cookie(auth_cookie)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Deprecation"),
+        Some("@1792281600")
+    );
+    assert_eq!(
+        response.headers().get_one("Sunset"),
+        Some("Sun, 18 Apr 2027 00:00:00 GMT")
+    );
+    assert_eq!(
+        response.headers().get_one("Link"),
+        Some(r#"</v1/self>; rel="successor-version""#)
+    );
+}
+
+#[test]
+fn test_deprecation_headers_on_errors() {
+    let client = common::setup();
+
+    let response = client.get("/self").dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.headers().get_one("Deprecation").is_some());
+
+    let response = client.get("/v1/self").dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(response.headers().get_one("Deprecation"), None);
+}
+
+#[test]
+fn test_operational_routes_are_unversioned() {
+    let client = common::setup();
+
+    let response = client.get("/health/live").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Deprecation"), None);
+
+    let response = client.get("/v1/health/live").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
@@ -0,0 +1,115 @@
+//! This module contains the errors returned by the client. Error responses
+//! of the API are decoded into `ClientError::Api`, whose `code` can be
+//! matched on instead of the status or message
+
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Stable machine-readable code of an error response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorCode {
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    NotAcceptable,
+    Conflict,
+    PreconditionFailed,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    UnprocessableEntity,
+    ServiceUnavailable,
+    ClientError,
+    InternalError,
+    DatabaseUnavailable,
+    MissingCredentials,
+    InvalidCredentials,
+    MultipleAuthorizationHeaders,
+    MissingToken,
+    InvalidToken,
+    CsrfTokenMissing,
+    CsrfTokenInvalid,
+    UsernameTaken,
+    /// A code this version of the client doesn't know
+    Other(String),
+}
+
+impl From<&str> for ErrorCode {
+    fn from(code: &str) -> Self {
+        match code {
+            "bad_request" => ErrorCode::BadRequest,
+            "unauthorized" => ErrorCode::Unauthorized,
+            "forbidden" => ErrorCode::Forbidden,
+            "not_found" => ErrorCode::NotFound,
+            "method_not_allowed" => ErrorCode::MethodNotAllowed,
+            "not_acceptable" => ErrorCode::NotAcceptable,
+            "conflict" => ErrorCode::Conflict,
+            "precondition_failed" => ErrorCode::PreconditionFailed,
+            "payload_too_large" => ErrorCode::PayloadTooLarge,
+            "unsupported_media_type" => ErrorCode::UnsupportedMediaType,
+            "unprocessable_entity" => ErrorCode::UnprocessableEntity,
+            "service_unavailable" => ErrorCode::ServiceUnavailable,
+            "client_error" => ErrorCode::ClientError,
+            "internal_error" => ErrorCode::InternalError,
+            "database_unavailable" => ErrorCode::DatabaseUnavailable,
+            "missing_credentials" => ErrorCode::MissingCredentials,
+            "invalid_credentials" => ErrorCode::InvalidCredentials,
+            "multiple_authorization_headers" => ErrorCode::MultipleAuthorizationHeaders,
+            "missing_token" => ErrorCode::MissingToken,
+            "invalid_token" => ErrorCode::InvalidToken,
+            "csrf_token_missing" => ErrorCode::CsrfTokenMissing,
+            "csrf_token_invalid" => ErrorCode::CsrfTokenInvalid,
+            "username_taken" => ErrorCode::UsernameTaken,
+            other => ErrorCode::Other(other.into()),
+        }
+    }
+}
+
+/// Body of an error response
+#[derive(Deserialize, Debug)]
+pub(crate) struct ErrorBody {
+    pub status_code: u16,
+    pub code: String,
+    pub message: String,
+    pub request_id: String,
+}
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("The API responded with {status} {code:?}: {message}")]
+    Api {
+        status: u16,
+        code: ErrorCode,
+        message: String,
+        /// Id of the request, for finding it in the server's logs
+        request_id: String,
+        /// Seconds to wait before retrying, sent with `503` responses
+        retry_after: Option<u64>,
+    },
+
+    #[error("The request requires logging in first")]
+    NotLoggedIn,
+
+    #[error("Could not reach the API: {0}")]
+    Transport(String),
+
+    #[error("The API responded with an unexpected status {status}: {body}")]
+    UnexpectedResponse { status: u16, body: String },
+
+    #[error("Could not decode the response: {source}")]
+    Decode {
+        #[from]
+        source: serde_json::Error,
+    },
+}
+
+impl ClientError {
+    /// Returns the code of the error response, if the API sent one
+    pub fn code(&self) -> Option<&ErrorCode> {
+        match self {
+            ClientError::Api { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! # Client for the Simple Rocket API Server
+//!
+//! Typed methods for every endpoint of v1 of the API, built on the request
+//! and response types of `common::user`. The client keeps the session
+//! started by `login`: it sends the `auth_token` cookie with every request
+//! and the CSRF token with requests which change state.
+//!
+//! Sessions are only carried by the cookie: the server doesn't accept the
+//! auth token as an `Authorization: Bearer` header, and the cookie is
+//! encrypted by the server, so there is no token a client could send there.
+//! Services which need a long-lived credential store the `Session` instead
+//! and resume it with `set_session`.
+//!
+//! ```no_run
+//! use client::Client;
+//! use common::user::UpdateUser;
+//!
+//! # async fn example() -> Result<(), client::ClientError> {
+//! let client = Client::new("https://api.example.com");
+//! client.login("foo", "password1234").await?;
+//!
+//! let account = client.get_self().await?;
+//! let update = UpdateUser {
+//!     username: None,
+//!     email: Some("bar@example.com".into()),
+//! };
+//! client.update_self(&update, account.etag.as_deref()).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub use common;
+
+pub mod error;
+pub mod transport;
+
+pub use error::{ClientError, ErrorCode};
+#[cfg(feature = "http")]
+pub use transport::HttpTransport;
+pub use transport::{Method, Request, Response, Transport};
+
+use common::user::{SignupUser, UpdateUser, UpdateUserPassword, UserBrief};
+use error::ErrorBody;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::Mutex;
+
+const PREFIX: &str = "/v1";
+const AUTH_COOKIE: &str = "auth_token";
+const CSRF_COOKIE: &str = "csrf_token";
+const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// A logged in session, as set in the cookies of `POST /login`. It can be
+/// stored and handed to another client to resume the session. The auth
+/// token is the encrypted cookie value, which is only accepted as a cookie
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    /// Value of the `auth_token` cookie
+    pub auth_token: String,
+    /// Value of the `csrf_token` cookie
+    pub csrf_token: Option<String>,
+}
+
+/// A resource along with the `ETag` of the version it was read at, which
+/// can be sent as `If-Match` when updating it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tagged<T> {
+    pub value: T,
+    pub etag: Option<String>,
+}
+
+/// Client for v1 of the API
+pub struct Client<T: Transport> {
+    transport: T,
+    session: Mutex<Option<Session>>,
+}
+
+#[cfg(feature = "http")]
+impl Client<HttpTransport> {
+    /// Returns a client for the server at `base_url`, such as
+    /// `https://api.example.com`
+    pub fn new(base_url: &str) -> Self {
+        Client::with_transport(HttpTransport::new(base_url))
+    }
+}
+
+impl<T: Transport> Client<T> {
+    /// Returns a client sending its requests with `transport`
+    pub fn with_transport(transport: T) -> Self {
+        Client {
+            transport,
+            session: Mutex::new(None),
+        }
+    }
+
+    /// Returns the transport the client sends its requests with
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Returns the current session, if logged in
+    pub fn session(&self) -> Option<Session> {
+        self.session.lock().expect("Poisoned session").clone()
+    }
+
+    /// Replaces the current session, e.g. with one saved from another client
+    pub fn set_session(&self, session: Option<Session>) {
+        *self.session.lock().expect("Poisoned session") = session;
+    }
+
+    /// Creates an account. This doesn't log in
+    ///
+    /// # Arguments
+    ///
+    /// * `signup` - The details of the account
+    pub async fn signup(&self, signup: &SignupUser) -> Result<UserBrief, ClientError> {
+        let request = self.request(Method::Post, "/signup", Some(signup))?;
+        let response = self.send(request).await?;
+        decode(&response)
+    }
+
+    /// Logs in, starting the session the other methods authenticate with
+    ///
+    /// # Arguments
+    ///
+    /// * `username` - Username of the account
+    /// * `password` - Password of the account
+    pub async fn login(&self, username: &str, password: &str) -> Result<UserBrief, ClientError> {
+        let mut request = self.request::<()>(Method::Post, "/login", None)?;
+        request.headers.push(credentials(username, password));
+
+        let response = self.send(request).await?;
+        let user = decode(&response)?;
+
+        let cookie = |name| {
+            set_cookies(&response)
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| v)
+        };
+        match cookie(AUTH_COOKIE) {
+            Some(auth_token) => self.set_session(Some(Session {
+                auth_token: auth_token.into(),
+                csrf_token: cookie(CSRF_COOKIE).map(String::from),
+            })),
+            None => {
+                return Err(ClientError::UnexpectedResponse {
+                    status: response.status,
+                    body: "No auth_token cookie was set".into(),
+                })
+            }
+        }
+
+        Ok(user)
+    }
+
+    /// Logs out, revoking the auth token of every session of the account
+    pub async fn logout(&self) -> Result<(), ClientError> {
+        let request = self.authenticated::<()>(Method::Post, "/logout", None)?;
+        self.send(request).await?;
+        self.set_session(None);
+        Ok(())
+    }
+
+    /// Fetches the logged in account
+    pub async fn get_self(&self) -> Result<Tagged<UserBrief>, ClientError> {
+        let request = self.authenticated::<()>(Method::Get, "/self", None)?;
+        let response = self.send(request).await?;
+        tagged(&response)
+    }
+
+    /// Updates the logged in account
+    ///
+    /// # Arguments
+    ///
+    /// * `update` - The fields to change
+    /// * `if_match` - `ETag` the account must still have for the update to
+    ///   be applied, as returned by `get_self`
+    pub async fn update_self(
+        &self,
+        update: &UpdateUser,
+        if_match: Option<&str>,
+    ) -> Result<Tagged<UserBrief>, ClientError> {
+        let mut request = self.authenticated(Method::Patch, "/self", Some(update))?;
+        if let Some(etag) = if_match {
+            request.headers.push(("If-Match".into(), etag.into()));
+        }
+
+        let response = self.send(request).await?;
+        tagged(&response)
+    }
+
+    /// Changes the password of an account. Every session of the account
+    /// ends, this client's included
+    ///
+    /// # Arguments
+    ///
+    /// * `username` - Username of the account
+    /// * `password` - Current password of the account
+    /// * `update` - The new password
+    pub async fn update_password(
+        &self,
+        username: &str,
+        password: &str,
+        update: &UpdateUserPassword,
+    ) -> Result<(), ClientError> {
+        let mut request = self.request(Method::Patch, "/self/password", Some(update))?;
+        request.headers.push(credentials(username, password));
+
+        // The server answers with a redirect, which is a success as long as
+        // the transport doesn't follow it
+        self.send(request).await?;
+        self.set_session(None);
+        Ok(())
+    }
+
+    /// Returns a request to `path` under the version prefix
+    fn request<B: Serialize>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> Result<Request, ClientError> {
+        let mut headers = vec![("Accept".to_string(), "application/json".to_string())];
+        let body = match body {
+            Some(body) => {
+                headers.push(("Content-Type".into(), "application/json".into()));
+                Some(serde_json::to_string(body)?)
+            }
+            None => None,
+        };
+
+        Ok(Request {
+            method,
+            path: format!("{}{}", PREFIX, path),
+            headers,
+            body,
+        })
+    }
+
+    /// Returns a request authenticated by the current session
+    fn authenticated<B: Serialize>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> Result<Request, ClientError> {
+        let session = self.session().ok_or(ClientError::NotLoggedIn)?;
+        let mut request = self.request(method, path, body)?;
+
+        request.headers.push((
+            "Cookie".into(),
+            format!("{}={}", AUTH_COOKIE, session.auth_token),
+        ));
+        if let (Some(token), true) = (session.csrf_token, method != Method::Get) {
+            request.headers.push((CSRF_HEADER.into(), token));
+        }
+
+        Ok(request)
+    }
+
+    /// Sends `request`, turning error responses into errors
+    async fn send(&self, request: Request) -> Result<Response, ClientError> {
+        let response = self
+            .transport
+            .send(request)
+            .await
+            .map_err(ClientError::Transport)?;
+
+        if response.status < 400 {
+            return Ok(response);
+        }
+
+        match serde_json::from_str::<ErrorBody>(&response.body) {
+            Ok(body) => Err(ClientError::Api {
+                status: body.status_code,
+                code: ErrorCode::from(body.code.as_str()),
+                message: body.message,
+                request_id: body.request_id,
+                retry_after: response
+                    .header("Retry-After")
+                    .and_then(|seconds| seconds.parse().ok()),
+            }),
+            Err(_) => Err(ClientError::UnexpectedResponse {
+                status: response.status,
+                body: response.body,
+            }),
+        }
+    }
+}
+
+/// Returns the `Authorization` header carrying the credentials of an account
+fn credentials(username: &str, password: &str) -> (String, String) {
+    ("Authorization".into(), format!("{}:{}", username, password))
+}
+
+/// Returns the name and value of every cookie `response` sets
+fn set_cookies(response: &Response) -> impl Iterator<Item = (&str, &str)> {
+    response.headers("Set-Cookie").filter_map(|cookie| {
+        let pair = cookie.split(';').next()?;
+        let (name, value) = pair.split_once('=')?;
+        Some((name.trim(), value.trim()))
+    })
+}
+
+fn decode<R: DeserializeOwned>(response: &Response) -> Result<R, ClientError> {
+    Ok(serde_json::from_str(&response.body)?)
+}
+
+fn tagged<R: DeserializeOwned>(response: &Response) -> Result<Tagged<R>, ClientError> {
+    Ok(Tagged {
+        value: decode(response)?,
+        etag: response.header("ETag").map(String::from),
+    })
+}
@@ -0,0 +1,131 @@
+//! This module contains the transport the client sends its requests with.
+//! The `http` feature provides `HttpTransport`, which talks to a running
+//! server; tests can instead hand requests straight to a local Rocket
+
+use async_trait::async_trait;
+
+/// Methods of the requests the client sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Patch,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Patch => "PATCH",
+        }
+    }
+}
+
+/// A request to send, with a path relative to the server's root
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: Method,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+}
+
+/// A response as received from the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    /// Returns the first value of the header `name`
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns every value of the header `name`
+    pub fn headers<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.headers
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Sends requests to the server
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Sends `request`, failing only if no response was received
+    async fn send(&self, request: Request) -> Result<Response, String>;
+}
+
+/// Transport sending requests over HTTP(S) to a running server
+#[cfg(feature = "http")]
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "http")]
+impl HttpTransport {
+    /// Returns a transport sending requests to the server at `base_url`,
+    /// such as `https://api.example.com`
+    ///
+    /// Redirects are returned rather than followed: the API only redirects
+    /// after a successful request, such as the `303` of
+    /// `PATCH /self/password`, and following it would resend credentials
+    pub fn new(base_url: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Could not build HTTP client");
+
+        HttpTransport {
+            base_url: base_url.trim_end_matches('/').into(),
+            client,
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+#[async_trait]
+impl Transport for HttpTransport {
+    async fn send(&self, request: Request) -> Result<Response, String> {
+        let method = match request.method {
+            Method::Get => reqwest::Method::GET,
+            Method::Post => reqwest::Method::POST,
+            Method::Patch => reqwest::Method::PATCH,
+        };
+
+        let mut builder = self
+            .client
+            .request(method, format!("{}{}", self.base_url, request.path));
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+
+        let response = builder.send().await.map_err(|e| e.to_string())?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = response.text().await.map_err(|e| e.to_string())?;
+
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+}
@@ -0,0 +1,148 @@
+use client::common::user::{UpdateUser, UpdateUserPassword};
+use client::{ClientError, ErrorCode};
+
+mod common;
+
+#[test]
+fn test_signup_and_login() {
+    let client = common::setup();
+
+    client.block_on(async {
+        let user = client.signup(&common::mock_user()).await.unwrap();
+        assert_eq!(user.username, "foo");
+        assert_eq!(user.email, "foo@example.com");
+
+        let user = client.login("foo", "password1234").await.unwrap();
+        assert_eq!(user.username, "foo");
+
+        let session = client.session().expect("No session was started");
+        assert!(!session.auth_token.is_empty());
+        assert!(session.csrf_token.is_some());
+    });
+}
+
+#[test]
+fn test_get_self() {
+    let client = common::setup();
+    common::setup_mock_session(&client);
+
+    client.block_on(async {
+        let account = client.get_self().await.unwrap();
+        assert_eq!(account.value.username, "foo");
+        assert!(account.etag.is_some());
+    });
+}
+
+#[test]
+fn test_update_self() {
+    let client = common::setup();
+    common::setup_mock_session(&client);
+
+    client.block_on(async {
+        let account = client.get_self().await.unwrap();
+        let update = UpdateUser {
+            username: None,
+            email: Some("bar@example.com".into()),
+        };
+
+        let updated = client
+            .update_self(&update, account.etag.as_deref())
+            .await
+            .unwrap();
+        assert_eq!(updated.value.email, "bar@example.com");
+        assert_ne!(updated.etag, account.etag);
+
+        // The account has changed since the first read
+        let result = client.update_self(&update, account.etag.as_deref()).await;
+        assert_eq!(
+            result.unwrap_err().code(),
+            Some(&ErrorCode::PreconditionFailed)
+        );
+    });
+}
+
+#[test]
+fn test_update_password_ends_session() {
+    let client = common::setup();
+    common::setup_mock_session(&client);
+
+    client.block_on(async {
+        let session = client.session();
+        let update = UpdateUserPassword {
+            password: "password5678".into(),
+        };
+        client
+            .update_password("foo", "password1234", &update)
+            .await
+            .unwrap();
+        assert!(client.session().is_none());
+
+        // The old session was revoked on the server as well
+        client.set_session(session);
+        let result = client.get_self().await;
+        assert_eq!(result.unwrap_err().code(), Some(&ErrorCode::InvalidToken));
+
+        client.login("foo", "password5678").await.unwrap();
+        assert!(client.get_self().await.is_ok());
+    });
+}
+
+#[test]
+fn test_logout() {
+    let client = common::setup();
+    common::setup_mock_session(&client);
+
+    client.block_on(async {
+        client.logout().await.unwrap();
+        assert!(client.session().is_none());
+        assert!(matches!(
+            client.get_self().await,
+            Err(ClientError::NotLoggedIn)
+        ));
+    });
+}
+
+#[test]
+fn test_error_codes() {
+    let client = common::setup();
+    common::setup_mock_session(&client);
+
+    client.block_on(async {
+        let result = client.signup(&common::mock_user()).await;
+        match result.unwrap_err() {
+            ClientError::Api {
+                status,
+                code,
+                request_id,
+                ..
+            } => {
+                assert_eq!(status, 412);
+                assert_eq!(code, ErrorCode::UsernameTaken);
+                assert!(!request_id.is_empty());
+            }
+            e => panic!("Unexpected error: {}", e),
+        }
+
+        let result = client.login("foo", "wrongpassword").await;
+        assert_eq!(
+            result.unwrap_err().code(),
+            Some(&ErrorCode::InvalidCredentials)
+        );
+
+        // A session without its CSRF token can't change the account
+        let session = client.session().unwrap();
+        client.set_session(Some(client::Session {
+            csrf_token: None,
+            ..session
+        }));
+        let update = UpdateUser {
+            username: None,
+            email: Some("bar@example.com".into()),
+        };
+        let result = client.update_self(&update, None).await;
+        assert_eq!(
+            result.unwrap_err().code(),
+            Some(&ErrorCode::CsrfTokenMissing)
+        );
+    });
+}
@@ -0,0 +1,130 @@
+#![allow(dead_code)]
+
+use api::db::Database;
+use async_trait::async_trait;
+use client::common::user::SignupUser;
+use client::{Client, Method, Request, Response, Transport};
+use rocket::http::{Cookie, Header};
+use rocket::local::asynchronous;
+use rocket::tokio::runtime::{self, Runtime};
+use std::future::Future;
+use std::ops::{Deref, Drop};
+
+/// Transport handing requests to a local instance of the server
+pub struct LocalTransport(asynchronous::Client);
+
+#[async_trait]
+impl Transport for LocalTransport {
+    async fn send(&self, request: Request) -> Result<Response, String> {
+        let method = match request.method {
+            Method::Get => rocket::http::Method::Get,
+            Method::Post => rocket::http::Method::Post,
+            Method::Patch => rocket::http::Method::Patch,
+        };
+
+        let mut local = self.0.req(method, request.path);
+        for (name, value) in request.headers {
+            // Cookies of local requests are only read from the jar
+            if name.eq_ignore_ascii_case("Cookie") {
+                for pair in value.split(';') {
+                    if let Some((name, value)) = pair.split_once('=') {
+                        local =
+                            local.cookie(Cookie::new(name.trim().to_string(), value.to_string()));
+                    }
+                }
+            } else {
+                local = local.header(Header::new(name, value));
+            }
+        }
+        if let Some(body) = request.body {
+            local = local.body(body);
+        }
+
+        let response = local.dispatch().await;
+        let status = response.status().code;
+        let headers = response
+            .headers()
+            .iter()
+            .map(|header| (header.name().to_string(), header.value().to_string()))
+            .collect();
+        let body = response.into_string().await.unwrap_or_default();
+
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+/// A client of a local instance of the server, along with the runtime the
+/// instance runs on
+pub struct TestClient {
+    runtime: Runtime,
+    client: Client<LocalTransport>,
+}
+
+pub fn setup() -> TestClient {
+    let runtime = runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Could not start runtime");
+    let local = runtime
+        .block_on(asynchronous::Client::untracked(api::build_rocket()))
+        .expect("Invalid rocket instance");
+
+    TestClient {
+        runtime,
+        client: Client::with_transport(LocalTransport(local)),
+    }
+}
+
+impl TestClient {
+    /// Runs a future calling the client to completion
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+}
+
+pub fn mock_user() -> SignupUser {
+    SignupUser {
+        email: "foo@example.com".into(),
+        username: "foo".into(),
+        password: "password1234".into(),
+    }
+}
+
+/// Signs up and logs in as the mock user
+pub fn setup_mock_session(client: &TestClient) {
+    client.block_on(async {
+        client
+            .signup(&mock_user())
+            .await
+            .expect("Could not sign up");
+        client
+            .login("foo", "password1234")
+            .await
+            .expect("Could not log in");
+    });
+}
+
+impl Deref for TestClient {
+    type Target = Client<LocalTransport>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl Drop for TestClient {
+    fn drop(&mut self) {
+        let LocalTransport(local) = self.client.transport();
+        let db = local
+            .rocket()
+            .state::<Database>()
+            .expect("Failed to fetch db for cleanup");
+        self.runtime
+            .block_on(db.to_inner().drop(None))
+            .expect("Failed to drop db");
+    }
+}
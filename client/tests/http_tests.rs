@@ -0,0 +1,103 @@
+use client::common::user::UpdateUserPassword;
+use client::{Client, Session};
+use rocket::tokio::runtime;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::thread::{self, JoinHandle};
+
+/// Answers a single request with `response`, then stops listening.
+/// Returns the base url to send the request to and a handle resolving to
+/// the request line and headers received
+fn serve_once(response: &'static str) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Could not bind");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().expect("No request was sent");
+        let mut reader = BufReader::new(stream);
+
+        let mut head = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end().to_string();
+            if line.is_empty() {
+                break;
+            }
+            head.push(line);
+        }
+
+        let length = head
+            .iter()
+            .find_map(|line| {
+                line.to_lowercase()
+                    .strip_prefix("content-length:")
+                    .map(String::from)
+            })
+            .map(|length| length.trim().parse().unwrap())
+            .unwrap_or(0);
+        reader.read_exact(&mut vec![0; length]).unwrap();
+
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+        head
+    });
+
+    (url, handle)
+}
+
+#[test]
+fn test_update_password_does_not_follow_redirect() {
+    let (url, server) = serve_once(
+        "HTTP/1.1 303 See Other\r\nLocation: /\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+    );
+
+    let client = Client::new(&url);
+    client.set_session(Some(Session {
+        auth_token: "token".into(),
+        csrf_token: None,
+    }));
+
+    let update = UpdateUserPassword {
+        password: "password5678".into(),
+    };
+    let result = runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Could not start runtime")
+        .block_on(client.update_password("foo", "password1234", &update));
+
+    // Following the redirect would fail, as the server only answers once
+    assert!(result.is_ok(), "{:?}", result);
+    assert_eq!(client.session(), None);
+
+    let head = server.join().unwrap();
+    assert_eq!(head[0], "PATCH /v1/self/password HTTP/1.1");
+    assert!(head.contains(&"authorization: foo:password1234".to_string()));
+}
+
+#[test]
+fn test_session_is_sent_as_cookie() {
+    let (url, server) =
+        serve_once("HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+
+    let client = Client::new(&url);
+    client.set_session(Some(Session {
+        auth_token: "token".into(),
+        csrf_token: None,
+    }));
+
+    let result = runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Could not start runtime")
+        .block_on(client.get_self());
+    assert!(result.is_err());
+
+    // The server only accepts the auth token as a cookie
+    let head = server.join().unwrap();
+    assert_eq!(head[0], "GET /v1/self HTTP/1.1");
+    assert!(head.contains(&"cookie: auth_token=token".to_string()));
+    assert!(!head
+        .iter()
+        .any(|line| line.to_lowercase().starts_with("authorization:")));
+}
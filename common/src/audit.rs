@@ -0,0 +1,100 @@
+//! Provides the records stored in the append-only security audit log
+
+use crate::oid::ObjectId;
+#[cfg(feature = "server")]
+use crate::query::Model;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// What happened in an audited event
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventKind {
+    LoginSucceeded,
+    LoginFailed,
+    Logout,
+    PasswordChanged,
+    EmailChanged,
+    UsernameChanged,
+    SessionRevoked,
+    AdminAction,
+}
+
+/// A single security relevant event. Events are only ever inserted, never
+/// updated or deleted
+#[cfg(feature = "server")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Model)]
+pub struct AuditEvent {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<bson::oid::ObjectId>,
+    pub kind: AuditEventKind,
+    /// The account the event concerns, if it could be identified
+    pub user_id: Option<bson::oid::ObjectId>,
+    /// The username given, kept for failed logins of unknown accounts
+    pub username: Option<String>,
+    /// Why the event happened, e.g. the kind of a failed login's error
+    pub reason: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: String,
+    #[serde(with = "crate::datetime")]
+    pub timestamp: DateTime<Utc>,
+}
+
+/// An audit event as returned by the API
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AuditEventBrief {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<String>))]
+    pub id: Option<ObjectId>,
+    pub kind: AuditEventKind,
+    /// The account the event concerns, if it could be identified
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<String>))]
+    pub user_id: Option<ObjectId>,
+    /// The username given, kept for failed logins of unknown accounts
+    pub username: Option<String>,
+    /// Why the event happened, e.g. the kind of a failed login's error
+    pub reason: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: String,
+    #[serde(with = "crate::datetime::rfc3339")]
+    #[cfg_attr(feature = "openapi", schema(value_type = String, format = DateTime, example = "2020-12-31T12:00:00Z"))]
+    pub timestamp: DateTime<Utc>,
+}
+
+#[cfg(feature = "server")]
+impl AuditEvent {
+    pub fn new(kind: AuditEventKind, request_id: &str) -> AuditEvent {
+        AuditEvent {
+            id: None,
+            kind,
+            user_id: None,
+            username: None,
+            reason: None,
+            ip: None,
+            user_agent: None,
+            request_id: request_id.into(),
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<AuditEvent> for AuditEventBrief {
+    fn from(event: AuditEvent) -> Self {
+        AuditEventBrief {
+            id: event.id.map(ObjectId::from),
+            kind: event.kind,
+            user_id: event.user_id.map(ObjectId::from),
+            username: event.username,
+            reason: event.reason,
+            ip: event.ip,
+            user_agent: event.user_agent,
+            request_id: event.request_id,
+            timestamp: event.timestamp,
+        }
+    }
+}
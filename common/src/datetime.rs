@@ -1,23 +1,264 @@
 //! Provides serde serialization for `chrono::DateTime` objects
+//!
+//! `#[serde(with = "common::datetime")]` picks the format by serializer:
+//! dates are written as native BSON datetimes by serializers which aren't
+//! human readable, so the database can range-query and TTL-index them, and
+//! as RFC 3339 strings by any other, such as `serde_json`. The BSON
+//! serializers only report themselves as not human readable when asked to,
+//! so stored documents must be serialized with `common::query::to_bson`.
+//! BSON datetimes hold milliseconds, so finer precision is dropped when
+//! storing. Without the `server` feature dates are always RFC 3339.
+//!
+//! `common::datetime::rfc3339` always uses RFC 3339, for the request and
+//! response types of the API.
+//!
+//! Reading accepts native BSON datetimes and RFC 3339 strings, along with
+//! the `%Y-%m-%d %H:%M:%S` strings older documents hold and milliseconds
+//! since the Unix epoch.
 
 use chrono::{DateTime, TimeZone, Utc};
-use serde::{self, Deserialize, Deserializer, Serializer};
+use serde::de::{self, MapAccess, Visitor};
+use serde::{self, Deserialize, Deserializer};
+use std::convert::TryFrom;
+use std::fmt;
 
-const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// Format dates were stored in before they became native BSON datetimes
+pub const LEGACY_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 
+/// Serializes `date` as a native BSON datetime if `serializer` isn't human
+/// readable, and as an RFC 3339 string otherwise
 pub fn serialize<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
 where
-    S: Serializer,
+    S: serde::Serializer,
 {
-    let s = format!("{}", date.format(FORMAT));
-    serializer.serialize_str(&s)
+    #[cfg(feature = "server")]
+    if !serializer.is_human_readable() {
+        use serde::Serialize;
+        return bson::DateTime::from_millis(date.timestamp_millis()).serialize(serializer);
+    }
+
+    rfc3339::serialize(date, serializer)
 }
 
+/// Deserializes a native BSON datetime, an RFC 3339 string or any of the
+/// legacy formats
 pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let s = String::deserialize(deserializer)?;
-    Utc.datetime_from_str(&s, FORMAT)
-        .map_err(serde::de::Error::custom)
+    deserializer.deserialize_any(DateTimeVisitor)
+}
+
+/// Parses an RFC 3339 or `LEGACY_FORMAT` date
+///
+/// # Arguments
+///
+/// * `s` - The date string
+///
+/// # Examples
+///
+/// ```
+/// use common::datetime;
+///
+/// let legacy = datetime::parse("2020-12-31 12:00:00").unwrap();
+/// let rfc3339 = datetime::parse("2020-12-31T12:00:00Z").unwrap();
+///
+/// assert_eq!(legacy, rfc3339);
+/// ```
+pub fn parse(s: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|date| date.with_timezone(&Utc))
+        .or_else(|_| Utc.datetime_from_str(s, LEGACY_FORMAT))
+}
+
+/// Value of the `$date` key native BSON datetimes are deserialized as
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DateBody {
+    Millis(i64),
+    Canonical {
+        #[serde(rename = "$numberLong")]
+        millis: String,
+    },
+    Relaxed(String),
+}
+
+struct DateTimeVisitor;
+
+impl DateTimeVisitor {
+    fn from_millis<E: de::Error>(millis: i64) -> Result<DateTime<Utc>, E> {
+        Utc.timestamp_millis_opt(millis)
+            .single()
+            .ok_or_else(|| E::custom(format!("Date out of range: {}", millis)))
+    }
+}
+
+impl<'de> Visitor<'de> for DateTimeVisitor {
+    type Value = DateTime<Utc>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a BSON datetime, an RFC 3339 or %Y-%m-%d %H:%M:%S string or epoch millis")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+        parse(s).map_err(|e| E::custom(format!("Invalid date {}: {}", s, e)))
+    }
+
+    fn visit_i64<E: de::Error>(self, millis: i64) -> Result<Self::Value, E> {
+        Self::from_millis(millis)
+    }
+
+    fn visit_u64<E: de::Error>(self, millis: u64) -> Result<Self::Value, E> {
+        let millis = i64::try_from(millis).map_err(|_| E::custom("Date out of range"))?;
+        Self::from_millis(millis)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        match map.next_key::<String>()?.as_deref() {
+            Some("$date") => match map.next_value::<DateBody>()? {
+                DateBody::Millis(millis) => Self::from_millis(millis),
+                DateBody::Canonical { millis } => {
+                    let millis = millis.parse().map_err(de::Error::custom)?;
+                    Self::from_millis(millis)
+                }
+                DateBody::Relaxed(s) => self.visit_str(&s),
+            },
+            _ => Err(de::Error::custom("expected a $date key")),
+        }
+    }
+}
+
+/// Serializes `chrono::DateTime` objects as RFC 3339 strings, such as
+/// `2020-12-31T12:00:00Z`, for the request and response types of the API
+///
+/// # Examples
+///
+/// ```
+/// use chrono::{DateTime, TimeZone, Utc};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Event {
+///     #[serde(with = "common::datetime::rfc3339")]
+///     timestamp: DateTime<Utc>,
+/// }
+///
+/// let event = Event {
+///     timestamp: Utc.ymd(2020, 12, 31).and_hms(12, 0, 0),
+/// };
+/// let json = serde_json::to_string(&event).unwrap();
+///
+/// assert_eq!(json, r#"{"timestamp":"2020-12-31T12:00:00Z"}"#);
+/// ```
+pub mod rfc3339 {
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{self, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&date.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        DateTime::parse_from_rfc3339(&s)
+            .map(|date| date.with_timezone(&Utc))
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Serialize, Deserialize, Debug, PartialEq)]
+    struct Event {
+        #[serde(with = "crate::datetime::rfc3339")]
+        date: DateTime<Utc>,
+    }
+
+    #[derive(serde::Serialize, Deserialize, Debug, PartialEq)]
+    struct Stored {
+        #[serde(with = "crate::datetime")]
+        date: DateTime<Utc>,
+    }
+
+    fn date() -> DateTime<Utc> {
+        Utc.timestamp_millis(1_609_416_000_123)
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        let json = serde_json::to_string(&Event { date: date() }).unwrap();
+
+        assert_eq!(json, r#"{"date":"2020-12-31T12:00:00.123Z"}"#);
+        assert_eq!(serde_json::from_str::<Event>(&json).unwrap().date, date());
+    }
+
+    #[test]
+    fn test_json_is_rfc3339() {
+        let json = serde_json::to_string(&Stored { date: date() }).unwrap();
+
+        assert_eq!(json, r#"{"date":"2020-12-31T12:00:00.123Z"}"#);
+        assert_eq!(serde_json::from_str::<Stored>(&json).unwrap().date, date());
+    }
+
+    #[test]
+    fn test_lenient_parsing() {
+        let legacy: Stored = serde_json::from_str(r#"{"date":"2020-12-31 12:00:00"}"#).unwrap();
+        let offset: Stored =
+            serde_json::from_str(r#"{"date":"2020-12-31T13:00:00+01:00"}"#).unwrap();
+        let millis: Stored = serde_json::from_str(r#"{"date":1609416000000}"#).unwrap();
+
+        assert_eq!(legacy, offset);
+        assert_eq!(legacy, millis);
+        assert!(serde_json::from_str::<Stored>(r#"{"date":"31/12/2020"}"#).is_err());
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_bson_is_native() {
+        let doc = crate::query::to_bson(&Stored { date: date() }).unwrap();
+        let doc = doc.as_document().unwrap().clone();
+
+        assert_eq!(
+            doc.get_datetime("date").map(|d| d.timestamp_millis()),
+            Ok(date().timestamp_millis())
+        );
+        assert_eq!(bson::from_document::<Stored>(doc).unwrap().date, date());
+
+        let raw = bson::to_vec(&Stored { date: date() }).unwrap();
+        let stored = bson::Document::from_reader(raw.as_slice()).unwrap();
+        assert!(stored.get_datetime("date").is_ok());
+        assert_eq!(bson::from_slice::<Stored>(&raw).unwrap().date, date());
+
+        let legacy = bson::doc! { "date": "2020-12-31 12:00:00" };
+        assert!(bson::from_document::<Stored>(legacy).is_ok());
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_models_store_native_dates() {
+        use crate::audit::{AuditEvent, AuditEventKind};
+
+        let event = AuditEvent {
+            timestamp: date(),
+            ..AuditEvent::new(AuditEventKind::LoginSucceeded, "request")
+        };
+
+        let stored = crate::query::to_bson(&event).unwrap();
+        assert!(stored
+            .as_document()
+            .unwrap()
+            .get_datetime("timestamp")
+            .is_ok());
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["timestamp"], "2020-12-31T12:00:00.123Z");
+    }
 }
@@ -1,4 +1,20 @@
+//! Types shared by the server and its clients
+//!
+//! The `server` feature, on by default, adds the storage models, query
+//! builders and password hashing. Front-ends, including ones compiled to
+//! WebAssembly, can depend on this crate with `default-features = false` to
+//! get the request and response types alone.
+
+// Lets code generated by `common_derive` refer to `::common` from within
+// this crate as well
+extern crate self as common;
+
+pub mod audit;
 pub mod datetime;
+pub mod oid;
+#[cfg(feature = "server")]
+pub mod query;
+#[cfg(feature = "server")]
 pub mod security;
 pub mod user;
 
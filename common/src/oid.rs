@@ -0,0 +1,224 @@
+//! Provides an `ObjectId` which serializes as a plain hex string
+//!
+//! `bson::oid::ObjectId` serializes through serde_json as
+//! `{"$oid": "..."}` and needs the `server` feature. This wrapper holds the
+//! same 12 bytes without depending on `bson`, so it can be shared with
+//! WebAssembly front-ends. It still deserializes from native BSON
+//! `ObjectId`s, so types holding it can be read from the database.
+
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+const LENGTH: usize = 12;
+
+/// The id of a stored document
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId([u8; LENGTH]);
+
+/// An error produced when a string is not a 24 character hex `ObjectId`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseObjectIdError(pub String);
+
+impl fmt::Display for ParseObjectIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid ObjectId: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseObjectIdError {}
+
+impl ObjectId {
+    /// Generates a new id
+    #[cfg(feature = "server")]
+    pub fn new() -> ObjectId {
+        bson::oid::ObjectId::new().into()
+    }
+
+    pub fn from_bytes(bytes: [u8; LENGTH]) -> ObjectId {
+        ObjectId(bytes)
+    }
+
+    pub fn bytes(&self) -> [u8; LENGTH] {
+        self.0
+    }
+
+    /// Parses an id from its 24 character hex representation
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - The hex string
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use common::oid::ObjectId;
+    ///
+    /// let id = ObjectId::parse_str("5fed7d1b00d0e1b4002a6a87").unwrap();
+    /// assert_eq!(id.to_hex(), "5fed7d1b00d0e1b4002a6a87");
+    ///
+    /// assert!(ObjectId::parse_str("5fed7d1b").is_err());
+    /// ```
+    pub fn parse_str(s: &str) -> Result<ObjectId, ParseObjectIdError> {
+        let invalid = || ParseObjectIdError(s.into());
+        // `from_str_radix` also accepts a sign, so every digit is checked
+        if s.len() != LENGTH * 2 || !s.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+
+        let mut bytes = [0; LENGTH];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(ObjectId(bytes))
+    }
+
+    /// Returns the 24 character lowercase hex representation of the id
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+#[cfg(feature = "server")]
+impl Default for ObjectId {
+    fn default() -> Self {
+        ObjectId::new()
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = ParseObjectIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ObjectId::parse_str(s)
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ObjectId({})", self.to_hex())
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<bson::oid::ObjectId> for ObjectId {
+    fn from(id: bson::oid::ObjectId) -> Self {
+        ObjectId(id.bytes())
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<ObjectId> for bson::oid::ObjectId {
+    fn from(id: ObjectId) -> Self {
+        bson::oid::ObjectId::from_bytes(id.0)
+    }
+}
+
+impl Serialize for ObjectId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+impl<'de> Deserialize<'de> for ObjectId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ObjectIdVisitor)
+    }
+}
+
+/// Accepts hex strings, raw bytes and the `{"$oid": "..."}` form native
+/// BSON ids are deserialized as
+struct ObjectIdVisitor;
+
+impl<'de> Visitor<'de> for ObjectIdVisitor {
+    type Value = ObjectId;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an ObjectId as a 24 character hex string")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+        ObjectId::parse_str(s).map_err(E::custom)
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+        let bytes = <[u8; LENGTH]>::try_from(bytes)
+            .map_err(|_| E::invalid_length(bytes.len(), &"12 bytes"))?;
+        Ok(ObjectId(bytes))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        match map.next_entry::<String, String>()? {
+            Some((key, hex)) if key == "$oid" => self.visit_str(&hex),
+            _ => Err(de::Error::custom("expected an $oid key")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: &str = "5fed7d1b00d0e1b4002a6a87";
+
+    #[test]
+    fn test_json_is_hex_string() {
+        let id = ObjectId::parse_str(HEX).unwrap();
+        let json = serde_json::to_string(&id).unwrap();
+
+        assert_eq!(json, format!("\"{}\"", HEX));
+        assert_eq!(serde_json::from_str::<ObjectId>(&json).unwrap(), id);
+    }
+
+    #[test]
+    fn test_parse_rejects_non_hex_digits() {
+        assert!(ObjectId::parse_str(&HEX.to_uppercase()).is_ok());
+        assert!(ObjectId::parse_str("+fed7d1b00d0e1b4002a6a87").is_err());
+        assert!(ObjectId::parse_str("5fed7d1b00d0e1b4002a6a+7").is_err());
+        assert!(ObjectId::parse_str("-fed7d1b00d0e1b4002a6a87").is_err());
+        assert!(ObjectId::parse_str("5fed7d1b00d0e1b4002a6a8g").is_err());
+        assert!(ObjectId::parse_str(" fed7d1b00d0e1b4002a6a87").is_err());
+    }
+
+    #[test]
+    fn test_extended_json() {
+        let json = format!(r#"{{"$oid": "{}"}}"#, HEX);
+        let id: ObjectId = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(id.to_hex(), HEX);
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_native_bson() {
+        let native = bson::oid::ObjectId::new();
+        let doc = bson::doc! { "_id": native };
+
+        #[derive(Deserialize)]
+        struct Stored {
+            #[serde(rename = "_id")]
+            id: ObjectId,
+        }
+
+        let stored: Stored = bson::from_document(doc.clone()).unwrap();
+        assert_eq!(bson::oid::ObjectId::from(stored.id), native);
+
+        let raw = bson::to_vec(&doc).unwrap();
+        let stored: Stored = bson::from_slice(&raw).unwrap();
+        assert_eq!(stored.id.to_hex(), native.to_hex());
+    }
+}
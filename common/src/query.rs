@@ -0,0 +1,554 @@
+//! Provides typed, compile-checked query and update builders
+//!
+//! Models deriving `Model` expose a set of `Field` descriptors through
+//! `Model::fields()`. Filters and updates are built from those descriptors
+//! so misspelled keys or mismatched value types fail to compile rather than
+//! silently matching nothing. Both produce plain BSON documents which any
+//! storage backend can consume.
+
+use bson::ser::SerializerOptions;
+use bson::{Bson, Document};
+use serde::Serialize;
+use std::fmt;
+use std::marker::PhantomData;
+
+pub use bson::Serializer as BsonSerializer;
+pub use common_derive::Model;
+
+/// Returns the options values are stored with. BSON serializers report
+/// themselves as human readable unless told otherwise, which would make
+/// `common::datetime` write strings rather than native BSON datetimes
+#[allow(deprecated)]
+fn storage_options() -> SerializerOptions {
+    SerializerOptions::builder().human_readable(false).build()
+}
+
+/// Returns a serializer producing values as they are stored
+pub fn bson_serializer() -> BsonSerializer {
+    BsonSerializer::new_with_options(storage_options())
+}
+
+/// Serializes `value` as it is stored. Models must be converted with this
+/// rather than `bson::to_bson`, so their dates are native BSON datetimes
+///
+/// # Arguments
+///
+/// * `value` - The value to store
+///
+/// # Examples
+///
+/// ```
+/// use chrono::{DateTime, Utc};
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct Event {
+///     #[serde(with = "common::datetime")]
+///     timestamp: DateTime<Utc>,
+/// }
+///
+/// let stored = common::query::to_bson(&Event { timestamp: Utc::now() }).unwrap();
+///
+/// assert!(stored.as_document().unwrap().get_datetime("timestamp").is_ok());
+/// ```
+pub fn to_bson<T: Serialize + ?Sized>(value: &T) -> Result<Bson, bson::ser::Error> {
+    value.serialize(bson_serializer())
+}
+
+/// Implemented by structs which are stored as documents
+pub trait Model {
+    /// Struct containing one `Field` descriptor per document key
+    type Fields;
+
+    /// Returns the field descriptors for this model
+    fn fields() -> Self::Fields;
+}
+
+/// An error produced when a query value cannot be represented as BSON
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid value for field {}: {}",
+            self.field, self.message
+        )
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+type SerializeFn<T> = fn(&T) -> Result<Bson, bson::ser::Error>;
+
+/// Describes a single key of model `M` holding values of type `T`
+pub struct Field<M, T> {
+    name: &'static str,
+    serialize: SerializeFn<T>,
+    _model: PhantomData<fn() -> M>,
+}
+
+impl<M, T> Clone for Field<M, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M, T> Copy for Field<M, T> {}
+
+fn serialize_value<T: Serialize>(value: &T) -> Result<Bson, bson::ser::Error> {
+    to_bson(value)
+}
+
+impl<M, T> Field<M, T> {
+    /// Returns a descriptor for the document key `name` whose values are
+    /// serialized with `T`'s `Serialize` implementation
+    pub fn new(name: &'static str) -> Self
+    where
+        T: Serialize,
+    {
+        Field {
+            name,
+            serialize: serialize_value::<T>,
+            _model: PhantomData,
+        }
+    }
+
+    /// Returns a descriptor for the document key `name` whose values are
+    /// serialized with `serialize`, for keys using `#[serde(with = "...")]`
+    pub fn with_serializer(name: &'static str, serialize: SerializeFn<T>) -> Self {
+        Field {
+            name,
+            serialize,
+            _model: PhantomData,
+        }
+    }
+
+    /// Returns the document key this field is stored under
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn to_bson(self, value: impl Into<T>) -> Result<Bson, QueryError> {
+        (self.serialize)(&value.into()).map_err(|e| QueryError {
+            field: self.name,
+            message: e.to_string(),
+        })
+    }
+
+    fn compare(self, op: &str, value: impl Into<T>) -> Filter<M> {
+        let clause = self.to_bson(value).map(|v| {
+            let mut doc = Document::new();
+            doc.insert(op, v);
+            Bson::Document(doc)
+        });
+        Filter::clause(self.name, clause)
+    }
+
+    /// Matches documents where this field equals `value`
+    pub fn eq(self, value: impl Into<T>) -> Filter<M> {
+        let clause = self.to_bson(value);
+        Filter::clause(self.name, clause)
+    }
+
+    /// Matches documents where this field does not equal `value`
+    pub fn ne(self, value: impl Into<T>) -> Filter<M> {
+        self.compare("$ne", value)
+    }
+
+    /// Matches documents where this field is greater than `value`
+    pub fn gt(self, value: impl Into<T>) -> Filter<M> {
+        self.compare("$gt", value)
+    }
+
+    /// Matches documents where this field is greater than or equal to `value`
+    pub fn gte(self, value: impl Into<T>) -> Filter<M> {
+        self.compare("$gte", value)
+    }
+
+    /// Matches documents where this field is less than `value`
+    pub fn lt(self, value: impl Into<T>) -> Filter<M> {
+        self.compare("$lt", value)
+    }
+
+    /// Matches documents where this field is less than or equal to `value`
+    pub fn lte(self, value: impl Into<T>) -> Filter<M> {
+        self.compare("$lte", value)
+    }
+
+    /// Matches documents where this field equals any of `values`
+    pub fn is_in<V: Into<T>>(self, values: impl IntoIterator<Item = V>) -> Filter<M> {
+        let clause = values
+            .into_iter()
+            .map(|v| self.to_bson(v))
+            .collect::<Result<Vec<_>, _>>()
+            .map(|values| {
+                let mut doc = Document::new();
+                doc.insert("$in", values);
+                Bson::Document(doc)
+            });
+        Filter::clause(self.name, clause)
+    }
+
+    /// Matches documents where this field is (or is not) present
+    pub fn exists(self, exists: bool) -> Filter<M> {
+        let mut doc = Document::new();
+        doc.insert("$exists", exists);
+        Filter::clause(self.name, Ok(Bson::Document(doc)))
+    }
+
+    /// Returns a sort order by this field, smallest first
+    pub fn ascending(self) -> Sort<M> {
+        Sort::key(self.name, 1)
+    }
+
+    /// Returns a sort order by this field, largest first
+    pub fn descending(self) -> Sort<M> {
+        Sort::key(self.name, -1)
+    }
+}
+
+/// A sort order over documents of model `M`
+///
+/// Keys added with `then` break ties between documents equal on every
+/// earlier key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sort<M> {
+    keys: Vec<(&'static str, i32)>,
+    _model: PhantomData<fn() -> M>,
+}
+
+impl<M> Sort<M> {
+    fn key(name: &'static str, direction: i32) -> Self {
+        Sort {
+            keys: vec![(name, direction)],
+            _model: PhantomData,
+        }
+    }
+
+    /// Returns a sort order which falls back to `other` on ties
+    pub fn then(mut self, other: Sort<M>) -> Self {
+        self.keys.extend(other.keys);
+        self
+    }
+
+    /// Converts the sort order into a BSON sort document
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use common::query::Model;
+    /// use common::user::User;
+    ///
+    /// let fields = User::fields();
+    /// let sort = fields.created.descending().then(fields.username.ascending());
+    ///
+    /// let doc = sort.to_document();
+    /// assert_eq!(doc.get_i32("created"), Ok(-1));
+    /// ```
+    pub fn to_document(&self) -> Document {
+        let mut doc = Document::new();
+        for (name, direction) in &self.keys {
+            doc.insert(*name, *direction);
+        }
+        doc
+    }
+}
+
+/// A query over documents of model `M`
+///
+/// Clauses added with `and` must all match. Serialization errors are kept
+/// until the filter is converted with `to_document`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter<M> {
+    clauses: Vec<(&'static str, Result<Bson, QueryError>)>,
+    _model: PhantomData<fn() -> M>,
+}
+
+impl<M> Filter<M> {
+    /// Returns a filter matching every document
+    pub fn all() -> Self {
+        Filter {
+            clauses: Vec::new(),
+            _model: PhantomData,
+        }
+    }
+
+    fn clause(name: &'static str, value: Result<Bson, QueryError>) -> Self {
+        Filter {
+            clauses: vec![(name, value)],
+            _model: PhantomData,
+        }
+    }
+
+    /// Returns a filter matching documents matched by both `self` and `other`
+    pub fn and(mut self, other: Filter<M>) -> Self {
+        self.clauses.extend(other.clauses);
+        self
+    }
+
+    /// Returns a filter matching documents matched by `self` or `other`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use common::query::Model;
+    /// use common::user::User;
+    ///
+    /// let fields = User::fields();
+    /// let query = fields.username.eq("foo").or(fields.email.eq("foo@example.com"));
+    ///
+    /// let doc = query.to_document().unwrap();
+    /// assert_eq!(doc.get_array("$or").map(Vec::len), Ok(2));
+    /// ```
+    pub fn or(self, other: Filter<M>) -> Self {
+        let branches = [self, other]
+            .iter()
+            .map(|filter| filter.to_document().map(Bson::Document))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Bson::Array);
+        Filter::clause("$or", branches)
+    }
+
+    /// Converts the filter into a BSON query document
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use common::query::Model;
+    /// use common::user::User;
+    ///
+    /// let fields = User::fields();
+    /// let query = fields.username.eq("foo").and(fields.auth_token.exists(true));
+    ///
+    /// let doc = query.to_document().unwrap();
+    /// assert_eq!(doc.get_str("username"), Ok("foo"));
+    /// ```
+    pub fn to_document(&self) -> Result<Document, QueryError> {
+        let mut doc = Document::new();
+        let mut repeated = false;
+
+        for (name, value) in &self.clauses {
+            repeated |= doc.insert(*name, value.clone()?).is_some();
+        }
+
+        if !repeated {
+            return Ok(doc);
+        }
+
+        // The same key was used by several clauses, so combine them with
+        // `$and` instead of letting later clauses overwrite earlier ones
+        let clauses = self
+            .clauses
+            .iter()
+            .map(|(name, value)| {
+                let mut clause = Document::new();
+                clause.insert(*name, value.clone()?);
+                Ok(Bson::Document(clause))
+            })
+            .collect::<Result<Vec<_>, QueryError>>()?;
+
+        let mut doc = Document::new();
+        doc.insert("$and", clauses);
+        Ok(doc)
+    }
+}
+
+/// A set of modifications to documents of model `M`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update<M> {
+    set: Vec<(&'static str, Result<Bson, QueryError>)>,
+    unset: Vec<&'static str>,
+    inc: Vec<(&'static str, Result<Bson, QueryError>)>,
+    _model: PhantomData<fn() -> M>,
+}
+
+impl<M> Default for Update<M> {
+    fn default() -> Self {
+        Update {
+            set: Vec::new(),
+            unset: Vec::new(),
+            inc: Vec::new(),
+            _model: PhantomData,
+        }
+    }
+}
+
+impl<M> Update<M> {
+    /// Returns an update which modifies nothing
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `field` to `value`
+    pub fn set<T>(mut self, field: Field<M, T>, value: impl Into<T>) -> Self {
+        self.set.push((field.name, field.to_bson(value)));
+        self
+    }
+
+    /// Sets `field` to `value` if it is `Some`, otherwise leaves it untouched
+    pub fn set_if_some<T>(self, field: Field<M, T>, value: Option<impl Into<T>>) -> Self {
+        match value {
+            Some(value) => self.set(field, value),
+            None => self,
+        }
+    }
+
+    /// Sets `field` to `value` if it is `Some`, otherwise removes it from
+    /// the document rather than storing `null`
+    pub fn set_or_unset<T>(self, field: Field<M, Option<T>>, value: Option<T>) -> Self {
+        match value {
+            Some(value) => self.set(field, value),
+            None => self.unset(field),
+        }
+    }
+
+    /// Removes `field` from the document
+    pub fn unset<T>(mut self, field: Field<M, T>) -> Self {
+        self.unset.push(field.name);
+        self
+    }
+
+    /// Adds `amount` to the numeric `field`
+    pub fn inc<T>(mut self, field: Field<M, T>, amount: impl Into<T>) -> Self {
+        self.inc.push((field.name, field.to_bson(amount)));
+        self
+    }
+
+    /// Returns true if the update would not modify anything
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.unset.is_empty() && self.inc.is_empty()
+    }
+
+    /// Converts the update into a BSON update document
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use common::query::{Model, Update};
+    /// use common::user::User;
+    ///
+    /// let fields = User::fields();
+    /// let update = Update::new()
+    ///     .set(fields.email, "foo@example.com")
+    ///     .unset(fields.auth_token);
+    ///
+    /// let doc = update.to_document().unwrap();
+    /// assert!(doc.contains_key("$set"));
+    /// assert!(doc.contains_key("$unset"));
+    /// ```
+    pub fn to_document(&self) -> Result<Document, QueryError> {
+        let mut doc = Document::new();
+
+        if !self.set.is_empty() {
+            let mut set = Document::new();
+            for (name, value) in &self.set {
+                set.insert(*name, value.clone()?);
+            }
+            doc.insert("$set", set);
+        }
+
+        if !self.unset.is_empty() {
+            let mut unset = Document::new();
+            for name in &self.unset {
+                unset.insert(*name, "");
+            }
+            doc.insert("$unset", unset);
+        }
+
+        if !self.inc.is_empty() {
+            let mut inc = Document::new();
+            for (name, amount) in &self.inc {
+                inc.insert(*name, amount.clone()?);
+            }
+            doc.insert("$inc", inc);
+        }
+
+        Ok(doc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::User;
+
+    #[test]
+    fn test_filter_uses_document_keys() {
+        let id = bson::oid::ObjectId::new();
+        let doc = User::fields().id.eq(id).to_document().unwrap();
+
+        assert_eq!(doc.get_object_id("_id"), Ok(id));
+    }
+
+    #[test]
+    fn test_repeated_keys_use_and() {
+        let fields = User::fields();
+        let doc = fields
+            .username
+            .ne("foo")
+            .and(fields.username.ne("bar"))
+            .to_document()
+            .unwrap();
+
+        assert_eq!(doc.get_array("$and").map(Vec::len), Ok(2));
+    }
+
+    #[test]
+    fn test_or_combines_with_and() {
+        let fields = User::fields();
+        let doc = fields
+            .admin
+            .eq(true)
+            .and(fields.username.eq("foo").or(fields.username.eq("bar")))
+            .to_document()
+            .unwrap();
+
+        assert_eq!(doc.get_bool("admin"), Ok(true));
+        let branches = doc.get_array("$or").unwrap();
+        assert_eq!(
+            branches[1].as_document().unwrap().get_str("username"),
+            Ok("bar")
+        );
+    }
+
+    #[test]
+    fn test_custom_serializer() {
+        let now = chrono::Utc::now();
+        let doc = User::fields().created.lt(now).to_document().unwrap();
+        let expected = bson::DateTime::from_millis(now.timestamp_millis());
+
+        assert_eq!(
+            doc.get_document("created").unwrap().get_datetime("$lt"),
+            Ok(&expected)
+        );
+    }
+
+    #[test]
+    fn test_update_operators() {
+        let fields = User::fields();
+        let doc = Update::new()
+            .inc(fields.login_count, 1)
+            .set_or_unset(fields.last_login_ip, Some("127.0.0.1".to_string()))
+            .set_or_unset(fields.last_login_user_agent, None)
+            .to_document()
+            .unwrap();
+
+        assert_eq!(
+            doc.get_document("$inc").unwrap().get_i64("login_count"),
+            Ok(1)
+        );
+        assert_eq!(
+            doc.get_document("$set").unwrap().get_str("last_login_ip"),
+            Ok("127.0.0.1")
+        );
+        assert!(doc
+            .get_document("$unset")
+            .unwrap()
+            .contains_key("last_login_user_agent"));
+    }
+}
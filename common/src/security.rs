@@ -73,6 +73,31 @@ pub fn generate_auth_token(len: usize) -> String {
         .collect()
 }
 
+/// Returns true if `a` and `b` are equal, taking the same time for any two
+/// inputs of the same length so that comparing secrets doesn't reveal how
+/// much of them was guessed correctly
+///
+/// # Arguments
+///
+/// * `a` - The first string to compare
+/// * `b` - The second string to compare
+///
+/// # Examples
+///
+/// ```
+/// use common::security;
+///
+/// assert!(security::constant_time_eq("token", "token"));
+/// assert!(!security::constant_time_eq("token", "tokem"));
+/// ```
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -87,4 +112,12 @@ mod tests {
 
         assert_eq!(hash("salt", "asdf1234"), expected);
     }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq("", ""));
+        assert!(constant_time_eq("abc", "abc"));
+        assert!(!constant_time_eq("abc", "abd"));
+        assert!(!constant_time_eq("abc", "abcd"));
+    }
 }
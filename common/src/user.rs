@@ -1,10 +1,15 @@
+use crate::oid::ObjectId;
+#[cfg(feature = "server")]
+use crate::query::Model;
+#[cfg(feature = "server")]
 use crate::security;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::cmp::PartialEq;
-use std::convert::From;
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// An account as stored in the database
+#[cfg(feature = "server")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Model)]
 pub struct User {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<bson::oid::ObjectId>,
@@ -14,29 +19,57 @@ pub struct User {
     pub salt: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub auth_token: Option<String>,
+    /// Token state-changing requests authenticated by the `auth_token`
+    /// cookie must echo, issued together with it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub csrf_token: Option<String>,
     #[serde(with = "crate::datetime")]
     pub last_login: DateTime<Utc>,
+    /// Address the last successful login came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_login_ip: Option<String>,
+    /// `User-Agent` header of the last successful login
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_login_user_agent: Option<String>,
+    /// Number of successful logins
+    #[serde(default)]
+    pub login_count: i64,
     #[serde(with = "crate::datetime")]
     pub created: DateTime<Utc>,
+    /// Set to the time of every write to the stored document
     #[serde(with = "crate::datetime")]
     pub updated: DateTime<Utc>,
+    /// Incremented on every write to the stored document
+    #[serde(default)]
+    pub version: i64,
+    /// Grants access to the admin endpoints
+    #[serde(default)]
+    pub admin: bool,
 }
 
+/// An account as returned by the API. Unlike `User` it holds no secrets,
+/// and its id and timestamps are plain strings on the wire
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct UserBrief {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
-    pub id: Option<bson::oid::ObjectId>,
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<String>))]
+    pub id: Option<ObjectId>,
     pub username: String,
     pub email: String,
-    #[serde(with = "crate::datetime")]
+    #[serde(with = "crate::datetime::rfc3339")]
+    #[cfg_attr(feature = "openapi", schema(value_type = String, format = DateTime, example = "2020-12-31T12:00:00Z"))]
     pub last_login: DateTime<Utc>,
-    #[serde(with = "crate::datetime")]
+    #[serde(with = "crate::datetime::rfc3339")]
+    #[cfg_attr(feature = "openapi", schema(value_type = String, format = DateTime, example = "2020-12-31T12:00:00Z"))]
     pub created: DateTime<Utc>,
-    #[serde(with = "crate::datetime")]
+    #[serde(with = "crate::datetime::rfc3339")]
+    #[cfg_attr(feature = "openapi", schema(value_type = String, format = DateTime, example = "2020-12-31T12:00:00Z"))]
     pub updated: DateTime<Utc>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct UpdateUser {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub username: Option<String>,
@@ -45,17 +78,20 @@ pub struct UpdateUser {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct UpdateUserPassword {
     pub password: String,
 }
 
 #[derive(Deserialize, Serialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct SignupUser {
     pub username: String,
     pub password: String,
     pub email: String,
 }
 
+#[cfg(feature = "server")]
 impl User {
     pub fn new(email: &str, username: &str, password: &str) -> User {
         let salt = security::generate_salt(64);
@@ -70,23 +106,31 @@ impl User {
             password_hash: hash,
             salt,
             auth_token: None,
+            csrf_token: None,
             last_login: now,
+            last_login_ip: None,
+            last_login_user_agent: None,
+            login_count: 0,
             created: now,
             updated: now,
+            version: 0,
+            admin: false,
         }
     }
 }
 
+#[cfg(feature = "server")]
 impl From<SignupUser> for User {
     fn from(data: SignupUser) -> Self {
         Self::new(&data.email, &data.username, &data.password)
     }
 }
 
+#[cfg(feature = "server")]
 impl From<User> for UserBrief {
     fn from(data: User) -> Self {
         UserBrief {
-            id: data.id,
+            id: data.id.map(ObjectId::from),
             username: data.username,
             email: data.email,
             last_login: data.last_login,
@@ -0,0 +1,150 @@
+//! Derive macros for the `common` crate
+//!
+//! `#[derive(Model)]` generates a set of typed field descriptors for a
+//! struct so that queries and updates built with `common::query` are
+//! checked by the compiler instead of failing silently at runtime.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, Lit, Meta, NestedMeta};
+
+/// Derives `common::query::Model` for a struct with named fields
+///
+/// A `<Struct>Fields` struct is generated with one `Field` per struct field,
+/// named after the rust field and pointing at the document key the field is
+/// stored under (honouring `#[serde(rename = "...")]`). Fields using
+/// `#[serde(with = "...")]` serialize query values through the same module.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize, Model)]
+/// pub struct Person {
+///     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+///     pub id: Option<bson::oid::ObjectId>,
+///     pub name: String,
+/// }
+///
+/// let filter = Person::fields().name.eq("Foo");
+/// ```
+#[proc_macro_derive(Model)]
+pub fn derive_model(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand_model(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_model(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let vis = &input.vis;
+    let fields_name = format_ident!("{}Fields", name);
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "Model can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "Model can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut declarations = Vec::new();
+    let mut initializers = Vec::new();
+
+    for field in fields {
+        let ident = field.ident.as_ref().expect("Named field without an ident");
+        let ty = &field.ty;
+        let attrs = SerdeAttrs::parse(&field.attrs)?;
+        let key = attrs.rename.unwrap_or_else(|| ident.to_string());
+        let doc = format!("Descriptor for the `{}` document key", key);
+
+        declarations.push(quote! {
+            #[doc = #doc]
+            pub #ident: ::common::query::Field<#name, #ty>
+        });
+
+        initializers.push(match attrs.with {
+            Some(module) => {
+                let module: syn::Path = syn::parse_str(&module)?;
+                quote! {
+                    #ident: ::common::query::Field::with_serializer(#key, |value| {
+                        #module::serialize(value, ::common::query::bson_serializer())
+                    })
+                }
+            }
+            None => quote! {
+                #ident: ::common::query::Field::new(#key)
+            },
+        });
+    }
+
+    let doc = format!("Typed field descriptors for `{}`", name);
+
+    Ok(quote! {
+        #[doc = #doc]
+        #vis struct #fields_name {
+            #(#declarations,)*
+        }
+
+        impl ::common::query::Model for #name {
+            type Fields = #fields_name;
+
+            fn fields() -> Self::Fields {
+                #fields_name {
+                    #(#initializers,)*
+                }
+            }
+        }
+    })
+}
+
+/// The subset of serde field attributes that affect how a field is stored
+#[derive(Default)]
+struct SerdeAttrs {
+    rename: Option<String>,
+    with: Option<String>,
+}
+
+impl SerdeAttrs {
+    fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let serde = Ident::new("serde", Span::call_site());
+        let mut result = SerdeAttrs::default();
+
+        for attr in attrs.iter().filter(|a| a.path.is_ident(&serde)) {
+            let list = match attr.parse_meta()? {
+                Meta::List(list) => list,
+                _ => continue,
+            };
+
+            for nested in list.nested {
+                if let NestedMeta::Meta(Meta::NameValue(nv)) = nested {
+                    let value = match nv.lit {
+                        Lit::Str(s) => s.value(),
+                        _ => continue,
+                    };
+
+                    if nv.path.is_ident("rename") {
+                        result.rename = Some(value);
+                    } else if nv.path.is_ident("with") {
+                        result.with = Some(value);
+                    }
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}